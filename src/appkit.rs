@@ -9,10 +9,13 @@
 
 #![allow(non_upper_case_globals)]
 
-use base::{id, class, BOOL, SEL};
+use base::{id, class, nil, BOOL, Id, SEL};
 use block::Block;
 use foundation::{NSInteger, NSUInteger, NSTimeInterval,
-                 NSPoint, NSSize, NSRect, NSRectEdge};
+                 NSPoint, NSSize, NSRange, NSRect, NSRectEdge,
+                 AttributeKey, FromCocoa, NSAttributedString, NSMutableAttributedString, NSURL, ToCocoa,
+                 is_kind_of_class};
+use styled_text::{Attributes, Color, Font, ParagraphStyle, Span, StyledText, TextAlignment, TextIndex, Underline};
 use libc;
 
 pub use core_graphics::base::CGFloat;
@@ -33,6 +36,17 @@ pub type GLint = libc::int32_t;
 extern {
     pub static NSAppKitVersionNumber: f64;
 
+    pub static NSEventTrackingRunLoopMode: id;
+    pub static NSModalPanelRunLoopMode: id;
+
+    // Attributed string keys (NSAttributedStringKey)
+    pub static NSFontAttributeName: id;
+    pub static NSParagraphStyleAttributeName: id;
+    pub static NSForegroundColorAttributeName: id;
+    pub static NSBackgroundColorAttributeName: id;
+    pub static NSUnderlineStyleAttributeName: id;
+    pub static NSLinkAttributeName: id;
+
     // Types for Standard Data - OS X v10.6 and later. (NSString *const)
     pub static NSPasteboardTypeString: id;
     pub static NSPasteboardTypePDF: id;
@@ -2784,6 +2798,7 @@ pub trait NSButton: Sized {
      unsafe fn setImage_(self, img: id /* (NSImage *) */);
      unsafe fn setBezelStyle_(self, style: NSBezelStyle);
      unsafe fn setTitle_(self, title: id /* (NSString*) */);
+     unsafe fn setAttributedTitle_(self, title: id /* (NSAttributedString*) */);
      unsafe fn alloc(_: Self) -> id {
          msg_send![class("NSButton"), alloc]
      }
@@ -2800,6 +2815,9 @@ impl NSButton for id {
     unsafe fn setTitle_(self, title: id /* (NSString*) */) {
         msg_send![self, setTitle:title]
     }
+    unsafe fn setAttributedTitle_(self, title: id /* (NSAttributedString*) */) {
+        msg_send![self, setAttributedTitle:title]
+    }
     unsafe fn setImage_(self, img: id /* (NSImage *) */) {
         msg_send![self, setImage:img]
     }
@@ -3393,6 +3411,8 @@ pub trait NSTextField: Sized {
     unsafe fn initWithFrame_(self, frameRect: NSRect) -> id;
     unsafe fn setEditable_(self, editable: BOOL);
     unsafe fn setStringValue_(self, label: id /* NSString */);
+    unsafe fn attributedStringValue(self) -> id /* NSAttributedString */;
+    unsafe fn setAttributedStringValue_(self, label: id /* NSAttributedString */);
 }
 
 impl NSTextField for id {
@@ -3405,6 +3425,12 @@ impl NSTextField for id {
     unsafe fn setStringValue_(self, label: id) {
         msg_send![self, setStringValue:label];
     }
+    unsafe fn attributedStringValue(self) -> id {
+        msg_send![self, attributedStringValue]
+    }
+    unsafe fn setAttributedStringValue_(self, label: id) {
+        let () = msg_send![self, setAttributedStringValue:label];
+    }
 }
 
 #[repr(u64)]
@@ -3721,10 +3747,463 @@ impl NSLayoutDimension for id {
 
 pub trait NSColor: Sized {
     unsafe fn clearColor(_: Self) -> id;
+
+    unsafe fn colorWithSRGBRed_green_blue_alpha_(_: Self, red: CGFloat, green: CGFloat, blue: CGFloat,
+                                                 alpha: CGFloat) -> id {
+        msg_send![class("NSColor"), colorWithSRGBRed:red green:green blue:blue alpha:alpha]
+    }
+
+    unsafe fn colorUsingColorSpace_(self, space: id) -> id;
+    unsafe fn redComponent(self) -> CGFloat;
+    unsafe fn greenComponent(self) -> CGFloat;
+    unsafe fn blueComponent(self) -> CGFloat;
+    unsafe fn alphaComponent(self) -> CGFloat;
 }
 
 impl NSColor for id {
     unsafe fn clearColor(_: Self) -> id {
         msg_send![class("NSColor"), clearColor]
     }
+
+    unsafe fn colorUsingColorSpace_(self, space: id) -> id {
+        msg_send![self, colorUsingColorSpace:space]
+    }
+
+    unsafe fn redComponent(self) -> CGFloat {
+        msg_send![self, redComponent]
+    }
+
+    unsafe fn greenComponent(self) -> CGFloat {
+        msg_send![self, greenComponent]
+    }
+
+    unsafe fn blueComponent(self) -> CGFloat {
+        msg_send![self, blueComponent]
+    }
+
+    unsafe fn alphaComponent(self) -> CGFloat {
+        msg_send![self, alphaComponent]
+    }
+}
+
+pub trait NSColorSpace: Sized {
+    unsafe fn sRGBColorSpace(_: Self) -> id {
+        msg_send![class("NSColorSpace"), sRGBColorSpace]
+    }
+}
+
+impl NSColorSpace for id {
+}
+
+pub trait NSFont: Sized {
+    unsafe fn fontWithName_size_(_: Self, fontName: id, fontSize: CGFloat) -> id {
+        msg_send![class("NSFont"), fontWithName:fontName size:fontSize]
+    }
+
+    unsafe fn systemFontOfSize_(_: Self, fontSize: CGFloat) -> id {
+        msg_send![class("NSFont"), systemFontOfSize:fontSize]
+    }
+
+    unsafe fn boldSystemFontOfSize_(_: Self, fontSize: CGFloat) -> id {
+        msg_send![class("NSFont"), boldSystemFontOfSize:fontSize]
+    }
+
+    unsafe fn fontName(self) -> id;
+    unsafe fn pointSize(self) -> CGFloat;
+}
+
+impl NSFont for id {
+    unsafe fn fontName(self) -> id {
+        msg_send![self, fontName]
+    }
+
+    unsafe fn pointSize(self) -> CGFloat {
+        msg_send![self, pointSize]
+    }
+}
+
+/// Center and right swap places on Apple silicon.
+#[repr(i64)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NSTextAlignment {
+    NSTextAlignmentLeft = 0,
+    #[cfg(not(target_arch = "aarch64"))]
+    NSTextAlignmentRight = 1,
+    #[cfg(not(target_arch = "aarch64"))]
+    NSTextAlignmentCenter = 2,
+    #[cfg(target_arch = "aarch64")]
+    NSTextAlignmentCenter = 1,
+    #[cfg(target_arch = "aarch64")]
+    NSTextAlignmentRight = 2,
+    NSTextAlignmentJustified = 3,
+    NSTextAlignmentNatural = 4,
+}
+
+impl From<TextAlignment> for NSTextAlignment {
+    fn from(alignment: TextAlignment) -> NSTextAlignment {
+        match alignment {
+            TextAlignment::Left => NSTextAlignment::NSTextAlignmentLeft,
+            TextAlignment::Right => NSTextAlignment::NSTextAlignmentRight,
+            TextAlignment::Center => NSTextAlignment::NSTextAlignmentCenter,
+            TextAlignment::Justified => NSTextAlignment::NSTextAlignmentJustified,
+            TextAlignment::Natural => NSTextAlignment::NSTextAlignmentNatural,
+        }
+    }
+}
+
+impl From<NSTextAlignment> for TextAlignment {
+    fn from(alignment: NSTextAlignment) -> TextAlignment {
+        match alignment {
+            NSTextAlignment::NSTextAlignmentLeft => TextAlignment::Left,
+            NSTextAlignment::NSTextAlignmentRight => TextAlignment::Right,
+            NSTextAlignment::NSTextAlignmentCenter => TextAlignment::Center,
+            NSTextAlignment::NSTextAlignmentJustified => TextAlignment::Justified,
+            NSTextAlignment::NSTextAlignmentNatural => TextAlignment::Natural,
+        }
+    }
+}
+
+pub trait NSParagraphStyle: Sized {
+    unsafe fn defaultParagraphStyle(_: Self) -> id {
+        msg_send![class("NSParagraphStyle"), defaultParagraphStyle]
+    }
+
+    unsafe fn alignment(self) -> NSTextAlignment;
+    unsafe fn lineSpacing(self) -> CGFloat;
+    unsafe fn paragraphSpacing(self) -> CGFloat;
+    unsafe fn firstLineHeadIndent(self) -> CGFloat;
+    unsafe fn headIndent(self) -> CGFloat;
+    unsafe fn tailIndent(self) -> CGFloat;
+}
+
+impl NSParagraphStyle for id {
+    unsafe fn alignment(self) -> NSTextAlignment {
+        let alignment: NSInteger = msg_send![self, alignment];
+        [NSTextAlignment::NSTextAlignmentLeft, NSTextAlignment::NSTextAlignmentRight,
+         NSTextAlignment::NSTextAlignmentCenter, NSTextAlignment::NSTextAlignmentJustified]
+            .iter()
+            .cloned()
+            .find(|&known| known as NSInteger == alignment)
+            .unwrap_or(NSTextAlignment::NSTextAlignmentNatural)
+    }
+
+    unsafe fn lineSpacing(self) -> CGFloat {
+        msg_send![self, lineSpacing]
+    }
+
+    unsafe fn paragraphSpacing(self) -> CGFloat {
+        msg_send![self, paragraphSpacing]
+    }
+
+    unsafe fn firstLineHeadIndent(self) -> CGFloat {
+        msg_send![self, firstLineHeadIndent]
+    }
+
+    unsafe fn headIndent(self) -> CGFloat {
+        msg_send![self, headIndent]
+    }
+
+    unsafe fn tailIndent(self) -> CGFloat {
+        msg_send![self, tailIndent]
+    }
+}
+
+pub trait NSMutableParagraphStyle: Sized {
+    unsafe fn new(_: Self) -> id {
+        msg_send![class("NSMutableParagraphStyle"), new]
+    }
+
+    unsafe fn setAlignment_(self, alignment: NSTextAlignment);
+    unsafe fn setLineSpacing_(self, lineSpacing: CGFloat);
+    unsafe fn setParagraphSpacing_(self, paragraphSpacing: CGFloat);
+    unsafe fn setFirstLineHeadIndent_(self, firstLineHeadIndent: CGFloat);
+    unsafe fn setHeadIndent_(self, headIndent: CGFloat);
+    unsafe fn setTailIndent_(self, tailIndent: CGFloat);
+}
+
+impl NSMutableParagraphStyle for id {
+    unsafe fn setAlignment_(self, alignment: NSTextAlignment) {
+        msg_send![self, setAlignment:alignment as NSInteger]
+    }
+
+    unsafe fn setLineSpacing_(self, lineSpacing: CGFloat) {
+        msg_send![self, setLineSpacing:lineSpacing]
+    }
+
+    unsafe fn setParagraphSpacing_(self, paragraphSpacing: CGFloat) {
+        msg_send![self, setParagraphSpacing:paragraphSpacing]
+    }
+
+    unsafe fn setFirstLineHeadIndent_(self, firstLineHeadIndent: CGFloat) {
+        msg_send![self, setFirstLineHeadIndent:firstLineHeadIndent]
+    }
+
+    unsafe fn setHeadIndent_(self, headIndent: CGFloat) {
+        msg_send![self, setHeadIndent:headIndent]
+    }
+
+    unsafe fn setTailIndent_(self, tailIndent: CGFloat) {
+        msg_send![self, setTailIndent:tailIndent]
+    }
+}
+
+/// Unknown fonts become the system font of the same size.
+impl ToCocoa for Font {
+    unsafe fn to_cocoa(&self) -> Id {
+        let font = NSFont::fontWithName_size_(nil, *self.name.to_cocoa(), self.size);
+        if font.is_null() {
+            Id::from_unretained(NSFont::systemFontOfSize_(nil, self.size))
+        } else {
+            Id::from_unretained(font)
+        }
+    }
+}
+
+impl FromCocoa for Font {
+    unsafe fn from_cocoa(object: id) -> Option<Font> {
+        if !is_kind_of_class(object, "NSFont") {
+            return None;
+        }
+        Some(Font { name: String::from_cocoa(object.fontName())?, size: object.pointSize() })
+    }
+}
+
+impl ToCocoa for Color {
+    unsafe fn to_cocoa(&self) -> Id {
+        Id::from_unretained(NSColor::colorWithSRGBRed_green_blue_alpha_(
+            nil, self.red, self.green, self.blue, self.alpha))
+    }
+}
+
+/// Returns `None` for colors with no sRGB equivalent, such as patterns.
+impl FromCocoa for Color {
+    unsafe fn from_cocoa(object: id) -> Option<Color> {
+        if !is_kind_of_class(object, "NSColor") {
+            return None;
+        }
+        let color = object.colorUsingColorSpace_(NSColorSpace::sRGBColorSpace(nil));
+        if color.is_null() {
+            return None;
+        }
+        Some(Color {
+            red: color.redComponent(),
+            green: color.greenComponent(),
+            blue: color.blueComponent(),
+            alpha: color.alphaComponent(),
+        })
+    }
+}
+
+impl ToCocoa for ParagraphStyle {
+    unsafe fn to_cocoa(&self) -> Id {
+        let style = Id::from_retained(NSMutableParagraphStyle::new(nil));
+        style.setAlignment_(self.alignment.into());
+        style.setLineSpacing_(self.line_spacing);
+        style.setParagraphSpacing_(self.paragraph_spacing);
+        style.setFirstLineHeadIndent_(self.first_line_head_indent);
+        style.setHeadIndent_(self.head_indent);
+        style.setTailIndent_(self.tail_indent);
+        style
+    }
+}
+
+impl FromCocoa for ParagraphStyle {
+    unsafe fn from_cocoa(object: id) -> Option<ParagraphStyle> {
+        if !is_kind_of_class(object, "NSParagraphStyle") {
+            return None;
+        }
+        Some(ParagraphStyle {
+            alignment: object.alignment().into(),
+            line_spacing: object.lineSpacing(),
+            paragraph_spacing: object.paragraphSpacing(),
+            first_line_head_indent: object.firstLineHeadIndent(),
+            head_indent: object.headIndent(),
+            tail_indent: object.tailIndent(),
+        })
+    }
+}
+
+/// `NSFontAttributeName`.
+pub enum FontAttribute {}
+
+impl AttributeKey for FontAttribute {
+    type Value = Font;
+
+    unsafe fn key() -> id {
+        NSFontAttributeName
+    }
+
+    unsafe fn to_object(value: &Font) -> Id {
+        value.to_cocoa()
+    }
+
+    unsafe fn from_object(object: id) -> Option<Font> {
+        Font::from_cocoa(object)
+    }
+}
+
+/// `NSForegroundColorAttributeName`.
+pub enum ForegroundColorAttribute {}
+
+impl AttributeKey for ForegroundColorAttribute {
+    type Value = Color;
+
+    unsafe fn key() -> id {
+        NSForegroundColorAttributeName
+    }
+
+    unsafe fn to_object(value: &Color) -> Id {
+        value.to_cocoa()
+    }
+
+    unsafe fn from_object(object: id) -> Option<Color> {
+        Color::from_cocoa(object)
+    }
+}
+
+/// `NSBackgroundColorAttributeName`.
+pub enum BackgroundColorAttribute {}
+
+impl AttributeKey for BackgroundColorAttribute {
+    type Value = Color;
+
+    unsafe fn key() -> id {
+        NSBackgroundColorAttributeName
+    }
+
+    unsafe fn to_object(value: &Color) -> Id {
+        value.to_cocoa()
+    }
+
+    unsafe fn from_object(object: id) -> Option<Color> {
+        Color::from_cocoa(object)
+    }
+}
+
+/// `NSUnderlineStyleAttributeName`, whose values are `NSUnderlineStyle` numbers.
+pub enum UnderlineAttribute {}
+
+impl AttributeKey for UnderlineAttribute {
+    type Value = Underline;
+
+    unsafe fn key() -> id {
+        NSUnderlineStyleAttributeName
+    }
+
+    unsafe fn to_object(value: &Underline) -> Id {
+        (value.raw() as i64).to_cocoa()
+    }
+
+    unsafe fn from_object(object: id) -> Option<Underline> {
+        Underline::from_raw(i64::from_cocoa(object)? as isize)
+    }
+}
+
+/// `NSLinkAttributeName`. Links are set as `NSURL`s, or as strings if they do not parse, and
+/// either is read back.
+pub enum LinkAttribute {}
+
+impl AttributeKey for LinkAttribute {
+    type Value = String;
+
+    unsafe fn key() -> id {
+        NSLinkAttributeName
+    }
+
+    unsafe fn to_object(value: &String) -> Id {
+        let url = NSURL::URLWithString_(nil, *value.to_cocoa());
+        if url.is_null() {
+            value.to_cocoa()
+        } else {
+            Id::from_unretained(url)
+        }
+    }
+
+    unsafe fn from_object(object: id) -> Option<String> {
+        if is_kind_of_class(object, "NSURL") {
+            String::from_cocoa(object.absoluteString())
+        } else {
+            String::from_cocoa(object)
+        }
+    }
+}
+
+/// `NSParagraphStyleAttributeName`.
+pub enum ParagraphStyleAttribute {}
+
+impl AttributeKey for ParagraphStyleAttribute {
+    type Value = ParagraphStyle;
+
+    unsafe fn key() -> id {
+        NSParagraphStyleAttributeName
+    }
+
+    unsafe fn to_object(value: &ParagraphStyle) -> Id {
+        value.to_cocoa()
+    }
+
+    unsafe fn from_object(object: id) -> Option<ParagraphStyle> {
+        ParagraphStyle::from_cocoa(object)
+    }
+}
+
+unsafe fn add_attribute<K: AttributeKey>(string: id, value: &Option<K::Value>, range: NSRange) {
+    if let Some(ref value) = *value {
+        string.addAttribute_value_range_(K::key(), *K::to_object(value), range);
+    }
+}
+
+unsafe fn attribute_from_dictionary<K: AttributeKey>(attributes: id) -> Option<K::Value> {
+    let object: id = msg_send![attributes, objectForKey:K::key()];
+    if object.is_null() {
+        None
+    } else {
+        K::from_object(object)
+    }
+}
+
+/// An `NSMutableAttributedString`.
+impl ToCocoa for StyledText {
+    unsafe fn to_cocoa(&self) -> Id {
+        let string = NSMutableAttributedString::alloc(nil);
+        let string = Id::from_retained(NSAttributedString::initWithString_(string, *self.text.to_cocoa()));
+        let index = TextIndex::new(&self.text);
+        string.beginEditing();
+        for span in &self.spans {
+            let range = index.to_ns_range(span.range.clone());
+            let attributes = &span.attributes;
+            add_attribute::<FontAttribute>(*string, &attributes.font, range);
+            add_attribute::<ForegroundColorAttribute>(*string, &attributes.foreground_color, range);
+            add_attribute::<BackgroundColorAttribute>(*string, &attributes.background_color, range);
+            add_attribute::<UnderlineAttribute>(*string, &attributes.underline, range);
+            add_attribute::<LinkAttribute>(*string, &attributes.link, range);
+            add_attribute::<ParagraphStyleAttribute>(*string, &attributes.paragraph_style, range);
+        }
+        string.endEditing();
+        string
+    }
+}
+
+/// Attributes other than the six `Attributes` models are dropped. The spans come back
+/// normalized.
+impl FromCocoa for StyledText {
+    unsafe fn from_cocoa(object: id) -> Option<StyledText> {
+        if !is_kind_of_class(object, "NSAttributedString") {
+            return None;
+        }
+        let mut text = StyledText::plain(&object.text());
+        for (range, attributes) in object.attributes_runs() {
+            let attributes = Attributes {
+                font: attribute_from_dictionary::<FontAttribute>(attributes),
+                foreground_color: attribute_from_dictionary::<ForegroundColorAttribute>(attributes),
+                background_color: attribute_from_dictionary::<BackgroundColorAttribute>(attributes),
+                underline: attribute_from_dictionary::<UnderlineAttribute>(attributes),
+                link: attribute_from_dictionary::<LinkAttribute>(attributes),
+                paragraph_style: attribute_from_dictionary::<ParagraphStyleAttribute>(attributes),
+            };
+            text.spans.push(Span { range, attributes });
+        }
+        text.normalize();
+        Some(text)
+    }
 }
@@ -0,0 +1,110 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use objc::runtime;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+
+pub use objc::runtime::{BOOL, NO, YES};
+
+pub type Class = *mut runtime::Class;
+#[allow(non_camel_case_types)]
+pub type id = *mut runtime::Object;
+pub type SEL = runtime::Sel;
+
+#[allow(non_upper_case_globals)]
+pub const nil: id = 0 as id;
+#[allow(non_upper_case_globals)]
+pub const Nil: Class = 0 as Class;
+
+/// A convenience method to convert the name of a class to the class object itself.
+#[inline]
+pub fn class(name: &str) -> Class {
+    unsafe {
+        mem::transmute(runtime::Class::get(name))
+    }
+}
+
+/// A convenience method to convert the name of a selector to the selector object.
+#[inline]
+pub fn selector(name: &str) -> SEL {
+    runtime::Sel::register(name)
+}
+
+/// A strong reference to an Objective-C object. Cloning retains the object and dropping releases
+/// it. The reference may be `nil`.
+///
+/// An `Id` has the same layout as an `id`, so a slice of them can be handed to Objective-C.
+#[repr(transparent)]
+pub struct Id(id);
+
+impl Id {
+    /// Takes over a reference the caller owns, such as the result of `alloc`/`init`, `new` or
+    /// `copy`.
+    pub unsafe fn from_retained(object: id) -> Id {
+        Id(object)
+    }
+
+    /// Retains an object the caller does not own, such as an autoreleased one.
+    pub unsafe fn from_unretained(object: id) -> Id {
+        Id(msg_send![object, retain])
+    }
+
+    pub fn as_ptr(&self) -> id {
+        self.0
+    }
+
+    /// Gives up the reference without releasing it.
+    pub fn into_retained(self) -> id {
+        let object = self.0;
+        mem::forget(self);
+        object
+    }
+}
+
+impl Deref for Id {
+    type Target = id;
+
+    fn deref(&self) -> &id {
+        &self.0
+    }
+}
+
+impl Clone for Id {
+    fn clone(&self) -> Id {
+        unsafe { Id::from_unretained(self.0) }
+    }
+}
+
+impl fmt::Debug for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Id({:p})", self.0)
+    }
+}
+
+impl Drop for Id {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![self.0, release];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_nsapp() {
+        unsafe {
+            let _nsApp: id = msg_send![class("NSApplication"), sharedApplication];
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Base64 with the semantics of `-[NSData base64EncodedStringWithOptions:]` and
+//! `-[NSData initWithBase64EncodedString:options:]`.
+
+#![allow(non_upper_case_globals)]
+// bitflags 0.9 expands to `try!`.
+#![allow(deprecated)]
+
+use libc;
+
+bitflags! {
+    pub struct NSDataBase64EncodingOptions: libc::c_ulonglong {
+        const NSDataBase64Encoding64CharacterLineLength = 1 << 0;
+        const NSDataBase64Encoding76CharacterLineLength = 1 << 1;
+        const NSDataBase64EncodingEndLineWithCarriageReturn = 1 << 4;
+        const NSDataBase64EncodingEndLineWithLineFeed = 1 << 5;
+    }
+}
+
+bitflags! {
+    pub struct NSDataBase64DecodingOptions: libc::c_ulonglong {
+       const NSDataBase64DecodingIgnoreUnknownCharacters = 1 << 0;
+    }
+}
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `bytes`, always padding with `=`.
+///
+/// Lines are only wrapped if a line length option is given, 64 taking precedence over 76.
+/// Wrapped lines end in CR LF unless exactly one of the line ending options picks CR or LF.
+/// There is no line ending after the last line.
+pub fn encode(bytes: &[u8], options: NSDataBase64EncodingOptions) -> String {
+    let line_length = if options.contains(NSDataBase64Encoding64CharacterLineLength) {
+        64
+    } else if options.contains(NSDataBase64Encoding76CharacterLineLength) {
+        76
+    } else {
+        0
+    };
+    let cr = options.contains(NSDataBase64EncodingEndLineWithCarriageReturn);
+    let lf = options.contains(NSDataBase64EncodingEndLineWithLineFeed);
+    let line_ending = match (cr, lf) {
+        (true, false) => "\r",
+        (false, true) => "\n",
+        _ => "\r\n",
+    };
+
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    let mut column = 0;
+    let mut push = |out: &mut String, c: u8| {
+        if line_length != 0 && column == line_length {
+            out.push_str(line_ending);
+            column = 0;
+        }
+        out.push(c as char);
+        column += 1;
+    };
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        push(&mut out, ALPHABET[n >> 18]);
+        push(&mut out, ALPHABET[n >> 12 & 0x3F]);
+        push(&mut out, if chunk.len() > 1 { ALPHABET[n >> 6 & 0x3F] } else { b'=' });
+        push(&mut out, if chunk.len() > 2 { ALPHABET[n & 0x3F] } else { b'=' });
+    }
+    out
+}
+
+/// Decodes `input`, returning `None` where Foundation would return nil.
+///
+/// Without `NSDataBase64DecodingIgnoreUnknownCharacters`, any byte outside the alphabet,
+/// including whitespace and line endings, is an error. Either way the input must be padded to a
+/// multiple of four characters, and `=` may only appear as padding.
+pub fn decode<T: AsRef<[u8]>>(input: T, options: NSDataBase64DecodingOptions) -> Option<Vec<u8>> {
+    let ignore_unknown = options.contains(NSDataBase64DecodingIgnoreUnknownCharacters);
+    let mut sextets = Vec::with_capacity(input.as_ref().len());
+    let mut padding = 0;
+    for &c in input.as_ref() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            _ if ignore_unknown => continue,
+            _ => return None,
+        };
+        if padding > 0 {
+            // Data after padding.
+            return None;
+        }
+        sextets.push(value);
+    }
+
+    if padding > 2 || (sextets.len() + padding) % 4 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(sextets.len() * 3 / 4);
+    for chunk in sextets.chunks(4) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &s)| n | (s as u32) << (18 - 6 * i));
+        out.push((n >> 16) as u8);
+        if chunk.len() > 2 {
+            out.push((n >> 8) as u8);
+        }
+        if chunk.len() > 3 {
+            out.push(n as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rfc4648_vectors() {
+        let vectors = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="),
+                       ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+        for &(plain, encoded) in &vectors {
+            assert_eq!(encode(plain.as_bytes(), NSDataBase64EncodingOptions::empty()), encoded);
+            assert_eq!(decode(encoded, NSDataBase64DecodingOptions::empty()), Some(plain.as_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_line_wrapping() {
+        let bytes: Vec<u8> = (0..60).collect();
+        let unwrapped = encode(&bytes, NSDataBase64EncodingOptions::empty());
+        assert_eq!(unwrapped.len(), 80);
+
+        let crlf = encode(&bytes, NSDataBase64Encoding64CharacterLineLength);
+        assert_eq!(crlf, format!("{}\r\n{}", &unwrapped[..64], &unwrapped[64..]));
+
+        let lf = encode(&bytes, NSDataBase64Encoding76CharacterLineLength | NSDataBase64EncodingEndLineWithLineFeed);
+        assert_eq!(lf, format!("{}\n{}", &unwrapped[..76], &unwrapped[76..]));
+
+        let cr = encode(&bytes, NSDataBase64Encoding64CharacterLineLength |
+                                NSDataBase64Encoding76CharacterLineLength |
+                                NSDataBase64EncodingEndLineWithCarriageReturn);
+        assert_eq!(cr, format!("{}\r{}", &unwrapped[..64], &unwrapped[64..]));
+
+        let both = encode(&bytes, NSDataBase64Encoding76CharacterLineLength |
+                                  NSDataBase64EncodingEndLineWithCarriageReturn |
+                                  NSDataBase64EncodingEndLineWithLineFeed);
+        assert_eq!(both, format!("{}\r\n{}", &unwrapped[..76], &unwrapped[76..]));
+
+        // Line ending options alone do not wrap, and exactly full lines get no trailing ending.
+        assert_eq!(encode(&bytes, NSDataBase64EncodingEndLineWithLineFeed), unwrapped);
+        assert_eq!(encode(&bytes[..48], NSDataBase64Encoding64CharacterLineLength).len(), 64);
+    }
+
+    #[test]
+    fn test_decode_options() {
+        let wrapped = "Zm9v\r\nYmFy";
+        assert_eq!(decode(wrapped, NSDataBase64DecodingOptions::empty()), None);
+        assert_eq!(decode(wrapped, NSDataBase64DecodingIgnoreUnknownCharacters), Some(b"foobar".to_vec()));
+        assert_eq!(decode("Zm9v*YmFy!", NSDataBase64DecodingIgnoreUnknownCharacters), Some(b"foobar".to_vec()));
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_padding() {
+        let options = NSDataBase64DecodingIgnoreUnknownCharacters;
+        assert_eq!(decode("Zm9vYg", options), None);
+        assert_eq!(decode("Zm9vY===", options), None);
+        assert_eq!(decode("Zg==Zg==", options), None);
+        assert_eq!(decode("Zm9=v", options), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let options = NSDataBase64Encoding76CharacterLineLength;
+        let encoded = encode(&bytes, options);
+        assert!(encoded.split("\r\n").all(|line| line.len() <= 76));
+        assert_eq!(decode(&encoded, NSDataBase64DecodingIgnoreUnknownCharacters), Some(bytes));
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `NSData` operations implemented over byte slices.
+
+#![allow(non_upper_case_globals)]
+// bitflags 0.9 expands to `try!`.
+#![allow(deprecated)]
+
+use libc;
+use base::NSUInteger;
+use range::NSRange;
+
+bitflags! {
+    pub struct NSDataSearchOptions: libc::c_ulonglong {
+        const NSDataSearchBackwards = 1 << 0;
+        const NSDataSearchAnchored = 1 << 1;
+    }
+}
+
+/// Searches `data` for `data_to_find` like `-[NSData rangeOfData:options:range:]`.
+///
+/// Only matches lying entirely within `search_range` count. Forward searches return the first
+/// match and backward searches the last. With `NSDataSearchAnchored`, a match must start at the
+/// beginning of the search range, or when searching backwards, end at its end. The returned
+/// range is relative to the start of `data`. An empty `data_to_find` is never found.
+///
+/// # Panics
+///
+/// If `search_range` extends past the end of `data`, where Foundation raises
+/// `NSRangeException`.
+pub fn range_of_data(data: &[u8], data_to_find: &[u8], options: NSDataSearchOptions,
+                     search_range: NSRange) -> Option<NSRange> {
+    let start = search_range.location as usize;
+    let end = start.checked_add(search_range.length as usize)
+                   .filter(|&end| end <= data.len())
+                   .unwrap_or_else(|| panic!("search range {:?} out of bounds for data of length {}",
+                                             search_range, data.len()));
+    let haystack = &data[start..end];
+    let needle_len = data_to_find.len();
+    if needle_len == 0 || needle_len > haystack.len() {
+        return None;
+    }
+
+    let last = haystack.len() - needle_len;
+    let matches_at = |offset: usize| &haystack[offset..offset + needle_len] == data_to_find;
+    let offset = match (options.contains(NSDataSearchBackwards), options.contains(NSDataSearchAnchored)) {
+        (false, false) => (0..=last).find(|&offset| matches_at(offset)),
+        (true, false) => (0..=last).rev().find(|&offset| matches_at(offset)),
+        (false, true) => Some(0).filter(|&offset| matches_at(offset)),
+        (true, true) => Some(last).filter(|&offset| matches_at(offset)),
+    };
+    offset.map(|offset| NSRange::new((start + offset) as NSUInteger, needle_len as NSUInteger))
+}
+
+#[cfg(test)]
+mod test {
+    use base::NSUInteger;
+    use range::NSRange;
+    use super::*;
+
+    const DATA: &[u8] = b"abcabcab";
+
+    fn find(needle: &[u8], options: NSDataSearchOptions, location: usize, length: usize) -> Option<NSRange> {
+        range_of_data(DATA, needle, options, NSRange::new(location as NSUInteger, length as NSUInteger))
+    }
+
+    fn found(location: usize, length: usize) -> Option<NSRange> {
+        Some(NSRange::new(location as NSUInteger, length as NSUInteger))
+    }
+
+    #[test]
+    fn test_forward_and_backward() {
+        let none = NSDataSearchOptions::empty();
+        assert_eq!(find(b"ab", none, 0, 8), found(0, 2));
+        assert_eq!(find(b"ab", NSDataSearchBackwards, 0, 8), found(6, 2));
+        assert_eq!(find(b"cab", NSDataSearchBackwards, 0, 8), found(5, 3));
+        assert_eq!(find(b"abd", none, 0, 8), None);
+        assert_eq!(find(b"abcabcabc", none, 0, 8), None);
+    }
+
+    #[test]
+    fn test_search_range() {
+        let none = NSDataSearchOptions::empty();
+        // Results are relative to the whole data, and matches must lie inside the range.
+        assert_eq!(find(b"ab", none, 1, 7), found(3, 2));
+        assert_eq!(find(b"ab", NSDataSearchBackwards, 0, 7), found(3, 2));
+        assert_eq!(find(b"abc", none, 4, 4), None);
+        assert_eq!(find(b"a", none, 8, 0), None);
+    }
+
+    #[test]
+    fn test_anchored() {
+        let backwards_anchored = NSDataSearchAnchored | NSDataSearchBackwards;
+        assert_eq!(find(b"abc", NSDataSearchAnchored, 0, 8), found(0, 3));
+        assert_eq!(find(b"bca", NSDataSearchAnchored, 0, 8), None);
+        assert_eq!(find(b"bca", NSDataSearchAnchored, 1, 7), found(1, 3));
+        assert_eq!(find(b"cab", backwards_anchored, 0, 8), found(5, 3));
+        assert_eq!(find(b"abc", backwards_anchored, 0, 8), None);
+        assert_eq!(find(b"abc", backwards_anchored, 0, 6), found(3, 3));
+    }
+
+    #[test]
+    fn test_empty_needle() {
+        assert_eq!(find(b"", NSDataSearchOptions::empty(), 0, 8), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_range_out_of_bounds() {
+        find(b"a", NSDataSearchOptions::empty(), 4, 5);
+    }
+}
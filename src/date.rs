@@ -0,0 +1,122 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversions between `NSTimeInterval`s and `std::time`.
+//!
+//! `NSDate` counts seconds from its reference date, 2001-01-01 00:00:00 UTC, as a double. Dates
+//! before the reference date have negative intervals.
+
+#![allow(non_upper_case_globals)]
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use libc;
+
+pub type NSTimeInterval = libc::c_double;
+
+/// Seconds from the Unix epoch to the `NSDate` reference date.
+pub const NSTimeIntervalSince1970: NSTimeInterval = 978307200.0;
+
+const REFERENCE_DATE_UNIX_SECONDS: u64 = 978307200;
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+/// Converts an interval since the reference date to a `SystemTime`.
+///
+/// Returns `None` for NaN, infinite intervals, and dates the platform cannot represent.
+pub fn system_time_from_reference_interval(interval: NSTimeInterval) -> Option<SystemTime> {
+    let reference = UNIX_EPOCH.checked_add(Duration::from_secs(REFERENCE_DATE_UNIX_SECONDS))?;
+    if interval >= 0.0 {
+        reference.checked_add(duration_from_interval(interval)?)
+    } else {
+        reference.checked_sub(duration_from_interval(-interval)?)
+    }
+}
+
+/// Converts a `SystemTime` to an interval since the reference date.
+pub fn reference_interval_from_system_time(time: SystemTime) -> NSTimeInterval {
+    // Subtract whole seconds as integers so that sub-second precision survives for dates near
+    // the reference date.
+    let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i128, after.subsec_nanos() as i128),
+        Err(before) => {
+            let before = before.duration();
+            (-(before.as_secs() as i128), -(before.subsec_nanos() as i128))
+        }
+    };
+    (secs - REFERENCE_DATE_UNIX_SECONDS as i128) as f64 + nanos as f64 / NANOS_PER_SEC
+}
+
+/// Converts a non-negative interval to a `Duration`, rounding to the nearest nanosecond.
+///
+/// Returns `None` for negative, NaN and overly large intervals.
+pub fn duration_from_interval(interval: NSTimeInterval) -> Option<Duration> {
+    if interval.is_nan() || interval < 0.0 || interval >= u64::MAX as f64 {
+        return None;
+    }
+    let secs = interval.trunc();
+    let nanos = ((interval - secs) * NANOS_PER_SEC).round();
+    if nanos >= NANOS_PER_SEC {
+        Duration::from_secs(secs as u64).checked_add(Duration::from_secs(1))
+    } else {
+        Some(Duration::new(secs as u64, nanos as u32))
+    }
+}
+
+pub fn interval_from_duration(duration: Duration) -> NSTimeInterval {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / NANOS_PER_SEC
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::*;
+
+    #[test]
+    fn test_reference_date() {
+        let reference = UNIX_EPOCH + Duration::from_secs(978307200);
+        assert_eq!(system_time_from_reference_interval(0.0), Some(reference));
+        assert_eq!(reference_interval_from_system_time(reference), 0.0);
+        assert_eq!(reference_interval_from_system_time(UNIX_EPOCH), -NSTimeIntervalSince1970);
+        assert_eq!(system_time_from_reference_interval(-NSTimeIntervalSince1970), Some(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn test_negative_intervals() {
+        let reference = UNIX_EPOCH + Duration::from_secs(978307200);
+        let before = reference - Duration::new(86400, 250_000_000);
+        assert_eq!(system_time_from_reference_interval(-86400.25), Some(before));
+        assert_eq!(reference_interval_from_system_time(before), -86400.25);
+
+        // Before the Unix epoch, where the duration since the epoch is an error.
+        let time = UNIX_EPOCH - Duration::new(10, 500_000_000);
+        let interval = reference_interval_from_system_time(time);
+        assert_eq!(interval, -NSTimeIntervalSince1970 - 10.5);
+        assert_eq!(system_time_from_reference_interval(interval), Some(time));
+    }
+
+    #[test]
+    fn test_sub_second_precision() {
+        let time = UNIX_EPOCH + Duration::new(978307200 + 600_000_000, 123_457_000);
+        let interval = reference_interval_from_system_time(time);
+        let back = system_time_from_reference_interval(interval).unwrap();
+        let error = back.duration_since(time).or_else(|e| Ok::<_, ()>(e.duration())).unwrap();
+        // A double holds about 16 significant digits, so microseconds survive at this distance.
+        assert!(error < Duration::from_micros(1), "error {:?}", error);
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(duration_from_interval(1.5), Some(Duration::from_millis(1500)));
+        assert_eq!(duration_from_interval(0.999_999_999_9), Some(Duration::from_secs(1)));
+        assert_eq!(duration_from_interval(-1.0), None);
+        assert_eq!(duration_from_interval(f64::NAN), None);
+        assert_eq!(duration_from_interval(f64::INFINITY), None);
+        assert_eq!(interval_from_duration(Duration::new(2, 250_000_000)), 2.25);
+        assert_eq!(system_time_from_reference_interval(f64::NAN), None);
+    }
+}
@@ -0,0 +1,514 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A single-threaded executor for `!Send` futures, independent of the loop that drives it.
+//!
+//! Wakers may be used from any thread. They queue their task and call `Signal::signal`, after
+//! which the driving loop must call `Executor::run_ready` on the executor's thread. On macOS,
+//! `foundation::RunLoopExecutor` does this from a run loop source.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// Tells the driving loop that tasks are ready. Called from whichever thread wakes them.
+pub trait Signal: Send + Sync + 'static {
+    fn signal(&self);
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct TaskId {
+    index: usize,
+    generation: u64,
+}
+
+struct Shared<S> {
+    ready: Mutex<VecDeque<TaskId>>,
+    signal: S,
+}
+
+struct TaskWaker<S> {
+    shared: Arc<Shared<S>>,
+    task: TaskId,
+}
+
+impl<S: Signal> Wake for TaskWaker<S> {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.shared.ready.lock().unwrap().push_back(self.task);
+        self.shared.signal.signal();
+    }
+}
+
+enum Slot {
+    Free,
+    Idle(Pin<Box<dyn Future<Output = ()>>>),
+    // Taken out while being polled. Set to true if woken meanwhile, by a nested `run_ready`.
+    Running(bool),
+}
+
+struct Task {
+    generation: u64,
+    slot: Slot,
+}
+
+pub struct Executor<S> {
+    shared: Arc<Shared<S>>,
+    tasks: RefCell<Vec<Task>>,
+    free: RefCell<Vec<usize>>,
+}
+
+impl<S: Signal> Executor<S> {
+    pub fn new(signal: S) -> Executor<S> {
+        Executor {
+            shared: Arc::new(Shared { ready: Mutex::new(VecDeque::new()), signal }),
+            tasks: RefCell::new(vec![]),
+            free: RefCell::new(vec![]),
+        }
+    }
+
+    /// Queues `future` to be polled on the next `run_ready`. It may be spawned from inside another
+    /// task, and runs to completion even if the handle is dropped.
+    pub fn spawn<F: Future + 'static>(&self, future: F) -> JoinHandle<F::Output> {
+        let (sender, receiver) = oneshot();
+        let future = Box::pin(Spawned { future: Box::pin(future), sender: Some(sender) });
+        let mut tasks = self.tasks.borrow_mut();
+        let index = match self.free.borrow_mut().pop() {
+            Some(index) => index,
+            None => {
+                tasks.push(Task { generation: 0, slot: Slot::Free });
+                tasks.len() - 1
+            }
+        };
+        let task = &mut tasks[index];
+        task.generation += 1;
+        task.slot = Slot::Idle(future);
+        let id = TaskId { index, generation: task.generation };
+        drop(tasks);
+
+        self.shared.ready.lock().unwrap().push_back(id);
+        self.shared.signal.signal();
+        JoinHandle { receiver }
+    }
+
+    /// Polls every task woken since the last call, returning how many were polled.
+    ///
+    /// Tasks woken while this runs are left for the next call, for which the signal has been
+    /// raised again, so a task that always wakes itself cannot starve the driving loop.
+    ///
+    /// It may be called from inside a task's `poll`, by a nested run loop for example, which then
+    /// polls the other ready tasks. The task further up the stack is queued again once its own
+    /// poll returns.
+    pub fn run_ready(&self) -> usize {
+        let ready = mem::take(&mut *self.shared.ready.lock().unwrap());
+        let mut polled = 0;
+        let mut woken_while_running = vec![];
+        for id in ready {
+            let mut future = {
+                let mut tasks = self.tasks.borrow_mut();
+                let task = &mut tasks[id.index];
+                if task.generation != id.generation {
+                    continue;
+                }
+                match mem::replace(&mut task.slot, Slot::Running(false)) {
+                    Slot::Idle(future) => future,
+                    Slot::Running(_) => {
+                        // Being polled further up the stack, which queues it again afterwards.
+                        task.slot = Slot::Running(true);
+                        continue;
+                    }
+                    Slot::Free => {
+                        task.slot = Slot::Free;
+                        continue;
+                    }
+                }
+            };
+
+            let waker = Waker::from(Arc::new(TaskWaker { shared: self.shared.clone(), task: id }));
+            let done = future.as_mut().poll(&mut Context::from_waker(&waker)).is_ready();
+            polled += 1;
+
+            let mut tasks = self.tasks.borrow_mut();
+            let woken = matches!(tasks[id.index].slot, Slot::Running(true));
+            if done {
+                tasks[id.index].slot = Slot::Free;
+                self.free.borrow_mut().push(id.index);
+                // Dropped without the borrow held, as the future may own other handles.
+                drop(tasks);
+                drop(future);
+            } else {
+                tasks[id.index].slot = Slot::Idle(future);
+                if woken {
+                    woken_while_running.push(id);
+                }
+            }
+        }
+
+        if !woken_while_running.is_empty() {
+            self.shared.ready.lock().unwrap().extend(woken_while_running);
+            self.shared.signal.signal();
+        }
+        polled
+    }
+
+    /// Runs `future` to completion, calling `turn` to wait for wakeups whenever no task is ready.
+    /// Other tasks run meanwhile.
+    pub fn block_on<F: Future + 'static, T: FnMut()>(&self, future: F, mut turn: T) -> F::Output {
+        let mut handle = self.spawn(future);
+        loop {
+            self.run_ready();
+            if let Some(output) = handle.try_take() {
+                return output;
+            }
+            if self.shared.ready.lock().unwrap().is_empty() {
+                turn();
+            }
+        }
+    }
+
+    /// The number of tasks that have not yet completed.
+    pub fn len(&self) -> usize {
+        self.tasks.borrow().iter().filter(|task| !matches!(task.slot, Slot::Free)).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<S> fmt::Debug for Executor<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Executor").field("tasks", &self.tasks.borrow().len()).finish()
+    }
+}
+
+struct Spawned<F: Future> {
+    future: Pin<Box<F>>,
+    sender: Option<Sender<F::Output>>,
+}
+
+impl<F: Future> Future for Spawned<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        match self.future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                if let Some(sender) = self.sender.take() {
+                    sender.send(output);
+                }
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Resolves to the output of a spawned task.
+pub struct JoinHandle<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Takes the output if the task has completed.
+    pub fn try_take(&mut self) -> Option<T> {
+        self.receiver.try_take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    /// # Panics
+    ///
+    /// Panics if the executor was dropped before the task completed.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        Pin::new(&mut self.receiver).poll(cx).map(|output| output.expect("task dropped before completing"))
+    }
+}
+
+struct OneshotState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Sends a single value to a `Receiver`, from any thread.
+pub struct Sender<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+/// A future resolving to the value sent by the `Sender`, or `None` if it was dropped unsent.
+pub struct Receiver<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+pub fn oneshot<T>() -> (Sender<T>, Receiver<T>) {
+    let state = Arc::new(Mutex::new(OneshotState { value: None, waker: None, closed: false }));
+    (Sender { state: state.clone() }, Receiver { state })
+}
+
+impl<T> Sender<T> {
+    pub fn send(self, value: T) {
+        self.state.lock().unwrap().value = Some(value);
+        // Dropping wakes the receiver.
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    fn try_take(&mut self) -> Option<T> {
+        self.state.lock().unwrap().value.take()
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.value.take() {
+            Poll::Ready(Some(value))
+        } else if state.closed {
+            Poll::Ready(None)
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+// Shared with the integration tests.
+#[cfg(test)]
+#[path = "../tests/support/mod.rs"]
+mod support;
+
+#[cfg(test)]
+mod test {
+    use std::cell::{Cell, RefCell};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::thread;
+    use super::support::and_then;
+    use super::*;
+
+    /// Counts signals, standing in for a run loop source.
+    #[derive(Clone, Default)]
+    struct FakeSignal(Arc<AtomicUsize>);
+
+    impl Signal for FakeSignal {
+        fn signal(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Pending until polled `n` times, waking itself each time.
+    struct Yield(usize);
+
+    impl Future for Yield {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            if self.0 == 0 {
+                return Poll::Ready(());
+            }
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_spawn_and_run() {
+        let signal = FakeSignal::default();
+        let executor = Executor::new(signal.clone());
+        let done = Rc::new(Cell::new(false));
+        {
+            let done = done.clone();
+            executor.spawn(and_then(Yield(2), move |()| {
+                done.set(true);
+                Yield(0)
+            }));
+        }
+        assert_eq!(signal.0.load(Ordering::SeqCst), 1);
+        assert_eq!(executor.len(), 1);
+
+        // Each pass polls once, as self-wakes are left for the next pass.
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(executor.run_ready(), 1);
+        assert!(!done.get());
+        assert_eq!(executor.run_ready(), 1);
+        assert!(done.get());
+        assert!(executor.is_empty());
+        assert_eq!(executor.run_ready(), 0);
+        assert_eq!(signal.0.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_join_handles() {
+        let executor = Rc::new(Executor::new(FakeSignal::default()));
+        let inner = executor.clone();
+        let output = executor.block_on(and_then(Yield(1), move |()| {
+            // Spawned from inside a task, and reusing a freed slot once the first completes.
+            and_then(inner.spawn(and_then(Yield(3), |()| Ready(Some(7)))), |n| Ready(Some(n * 6)))
+        }), || panic!("no external wakeups"));
+        assert_eq!(output, 42);
+        assert!(executor.is_empty());
+    }
+
+    struct Ready<T>(Option<T>);
+
+    impl<T: Unpin> Future for Ready<T> {
+        type Output = T;
+
+        fn poll(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<T> {
+            Poll::Ready(self.0.take().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_wake_from_another_thread() {
+        let signal = FakeSignal::default();
+        let executor = Executor::new(signal.clone());
+        let (sender, receiver) = oneshot();
+        let mut handle = executor.spawn(receiver);
+        executor.run_ready();
+        assert_eq!(handle.try_take(), None);
+
+        let before = signal.0.load(Ordering::SeqCst);
+        thread::spawn(move || sender.send("hello")).join().unwrap();
+        assert_eq!(signal.0.load(Ordering::SeqCst), before + 1);
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(handle.try_take(), Some(Some("hello")));
+    }
+
+    #[test]
+    fn test_block_on_turns() {
+        let executor = Executor::new(FakeSignal::default());
+        let (sender, receiver) = oneshot::<u32>();
+        let sender = RefCell::new(Some(sender));
+        let turns = Cell::new(0);
+        let output = executor.block_on(receiver, || {
+            turns.set(turns.get() + 1);
+            // The driver delivers an external event on the second turn.
+            if turns.get() == 2 {
+                sender.borrow_mut().take().unwrap().send(5);
+            }
+        });
+        assert_eq!(output, Some(5));
+        assert_eq!(turns.get(), 2);
+    }
+
+    #[test]
+    fn test_dropped_sender() {
+        let executor = Executor::new(FakeSignal::default());
+        let (sender, receiver) = oneshot::<()>();
+        let mut handle = executor.spawn(receiver);
+        executor.run_ready();
+        drop(sender);
+        executor.run_ready();
+        assert_eq!(handle.try_take(), Some(None));
+    }
+
+    #[test]
+    fn test_stale_wakers() {
+        let executor = Executor::new(FakeSignal::default());
+        let waker = Rc::new(RefCell::new(None));
+        {
+            let waker = waker.clone();
+            executor.spawn(PollFn(move |cx: &mut Context| {
+                *waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Ready(())
+            }));
+        }
+        executor.run_ready();
+        let polls = Rc::new(Cell::new(0));
+        {
+            let polls = polls.clone();
+            executor.spawn(PollFn(move |_: &mut Context| {
+                polls.set(polls.get() + 1);
+                Poll::Pending::<()>
+            }));
+        }
+        executor.run_ready();
+        assert_eq!(polls.get(), 1);
+
+        // The first task's waker must not poll the second, which reuses its slot.
+        waker.borrow_mut().take().unwrap().wake();
+        assert_eq!(executor.run_ready(), 0);
+        assert_eq!(polls.get(), 1);
+    }
+
+    #[test]
+    fn test_nested_run_ready() {
+        let executor = Rc::new(Executor::new(FakeSignal::default()));
+        let (sender, receiver) = oneshot::<u32>();
+        let mut handle = executor.spawn(receiver);
+        executor.run_ready();
+
+        // A task that runs a nested loop, waking itself and the receiver first.
+        let nested = Rc::new(Cell::new(vec![]));
+        {
+            let inner = Rc::downgrade(&executor);
+            let nested = nested.clone();
+            let sender = RefCell::new(Some(sender));
+            executor.spawn(PollFn(move |cx: &mut Context| {
+                cx.waker().wake_by_ref();
+                if let Some(sender) = sender.borrow_mut().take() {
+                    sender.send(7);
+                }
+                let mut counts = nested.take();
+                counts.push(inner.upgrade().unwrap().run_ready());
+                nested.set(counts);
+                Poll::Pending::<()>
+            }));
+        }
+
+        // The nested call polls the receiver but not the task running it, which is polled again
+        // on the next pass.
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(nested.take(), vec![1]);
+        assert_eq!(handle.try_take(), Some(Some(7)));
+        assert_eq!(executor.run_ready(), 1);
+        assert_eq!(nested.take(), vec![0]);
+    }
+
+    struct PollFn<F>(F);
+
+    impl<T, F: FnMut(&mut Context) -> Poll<T> + Unpin> Future for PollFn<F> {
+        type Output = T;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+            (self.0)(cx)
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Both sides of the `NSFastEnumeration` protocol, independent of the Objective-C runtime.
+//!
+//! `countByEnumeratingWithState:objects:count:` hands out items in batches, either copied into
+//! the caller's buffer or pointing into the collection. It also points the state at a mutation
+//! counter, which must not change until enumeration is over.
+
+use std::error;
+use std::fmt;
+use std::ptr;
+use libc;
+
+#[repr(C)]
+pub struct NSFastEnumerationState<T> {
+    pub state: libc::c_ulong,
+    pub items_ptr: *mut *mut T,
+    pub mutations_ptr: *mut libc::c_ulong,
+    pub extra: [libc::c_ulong; 5],
+}
+
+/// A collection that implements `countByEnumeratingWithState:objects:count:`.
+pub trait FastEnumerable<T> {
+    /// Fills in the next batch of at most `len` items, returning how many there are, or 0 once
+    /// enumeration is over.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must have room for `len` items, and `state` must only ever have been passed to
+    /// this collection.
+    unsafe fn count_by_enumerating(&self, state: &mut NSFastEnumerationState<T>, buffer: *mut *mut T,
+                                   len: usize) -> usize;
+}
+
+/// The collection changed while it was being enumerated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MutatedDuringEnumeration;
+
+impl fmt::Display for MutatedDuringEnumeration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("The collection was mutated while being enumerated")
+    }
+}
+
+impl error::Error for MutatedDuringEnumeration {}
+
+/// Implements `countByEnumeratingWithState:objects:count:` for items in contiguous memory.
+///
+/// Everything not yet enumerated is handed out as one batch pointing into `items`, so the
+/// caller's buffer is never used. `state.state` records how many items have been handed out.
+///
+/// # Safety
+///
+/// `items` and `mutations` must outlive the enumeration.
+pub unsafe fn enumerate_slice<T>(state: &mut NSFastEnumerationState<T>, items: &[*mut T],
+                                 mutations: *mut libc::c_ulong) -> usize {
+    state.mutations_ptr = mutations;
+    let start = state.state as usize;
+    if start >= items.len() {
+        return 0;
+    }
+    state.items_ptr = items.as_ptr().add(start) as *mut *mut T;
+    state.state = items.len() as libc::c_ulong;
+    items.len() - start
+}
+
+const BUFFER_LEN: usize = 16;
+
+pub struct FastIterator<T, E> {
+    enumerable: E,
+    state: NSFastEnumerationState<T>,
+    buffer: [*mut T; BUFFER_LEN],
+    mutations: Option<libc::c_ulong>,
+    len: usize,
+    idx: usize,
+    finished: bool,
+}
+
+impl<T, E: FastEnumerable<T>> FastIterator<T, E> {
+    /// # Safety
+    ///
+    /// The collection must outlive the iterator.
+    pub unsafe fn new(enumerable: E) -> FastIterator<T, E> {
+        FastIterator {
+            enumerable,
+            state: NSFastEnumerationState {
+                state: 0,
+                items_ptr: ptr::null_mut(),
+                mutations_ptr: ptr::null_mut(),
+                extra: [0; 5],
+            },
+            buffer: [ptr::null_mut(); BUFFER_LEN],
+            mutations: None,
+            len: 0,
+            idx: 0,
+            finished: false,
+        }
+    }
+
+    /// Like `next`, but reports mutation as an error rather than panicking.
+    pub fn try_next(&mut self) -> Result<Option<*mut T>, MutatedDuringEnumeration> {
+        if self.idx >= self.len {
+            if self.finished {
+                return Ok(None);
+            }
+            self.len = unsafe {
+                self.enumerable.count_by_enumerating(&mut self.state, self.buffer.as_mut_ptr(), BUFFER_LEN)
+            };
+            self.idx = 0;
+            self.finished = self.len == 0;
+        }
+
+        // An empty collection may finish without ever setting up the mutation counter.
+        if !self.state.mutations_ptr.is_null() {
+            let mutations = unsafe { *self.state.mutations_ptr };
+            match self.mutations {
+                Some(old) if old != mutations => return Err(MutatedDuringEnumeration),
+                _ => self.mutations = Some(mutations),
+            }
+        }
+
+        if self.idx < self.len {
+            let item = unsafe { *self.state.items_ptr.add(self.idx) };
+            self.idx += 1;
+            Ok(Some(item))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T, E: FastEnumerable<T>> Iterator for FastIterator<T, E> {
+    type Item = *mut T;
+
+    fn next(&mut self) -> Option<*mut T> {
+        self.try_next().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Only the rest of the current batch is known to exist.
+        (self.len - self.idx, if self.finished { Some(0) } else { None })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use libc;
+    use super::*;
+
+    /// Enumerates `0..len` as fake pointers, `batch` at a time.
+    struct Fake {
+        items: Vec<*mut u8>,
+        batch: usize,
+        copy: bool,
+        mutations: Cell<libc::c_ulong>,
+        calls: Cell<usize>,
+    }
+
+    impl Fake {
+        fn new(len: usize, batch: usize, copy: bool) -> Fake {
+            Fake {
+                items: (1..len + 1).map(|i| i as *mut u8).collect(),
+                batch,
+                copy,
+                mutations: Cell::new(0),
+                calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl FastEnumerable<u8> for &Fake {
+        unsafe fn count_by_enumerating(&self, state: &mut NSFastEnumerationState<u8>, buffer: *mut *mut u8,
+                                       len: usize) -> usize {
+            self.calls.set(self.calls.get() + 1);
+            let start = state.state as usize;
+            let count = self.batch.min(len).min(self.items.len() - start);
+            if count == 0 {
+                return 0;
+            }
+            state.mutations_ptr = self.mutations.as_ptr();
+            if self.copy {
+                ptr::copy_nonoverlapping(self.items.as_ptr().add(start), buffer, count);
+                state.items_ptr = buffer;
+            } else {
+                state.items_ptr = self.items.as_ptr().add(start) as *mut _;
+            }
+            state.state += count as libc::c_ulong;
+            count
+        }
+    }
+
+    fn collect(iter: FastIterator<u8, &Fake>) -> Vec<usize> {
+        iter.map(|item| item as usize).collect()
+    }
+
+    #[test]
+    fn test_batches() {
+        for &copy in &[true, false] {
+            for &(len, batch) in &[(0, 4), (5, 1), (10, 3), (40, 16), (40, 100)] {
+                let fake = Fake::new(len, batch, copy);
+                let items = collect(unsafe { FastIterator::new(&fake) });
+                assert_eq!(items, (1..len + 1).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutation() {
+        let fake = Fake::new(10, 4, true);
+        let mut iter = unsafe { FastIterator::new(&fake) };
+        assert_eq!(iter.try_next().map(|item| item.map(|p| p as usize)), Ok(Some(1)));
+        fake.mutations.set(1);
+        // Detected within a batch and at the start of the next one.
+        assert_eq!(iter.try_next(), Err(MutatedDuringEnumeration));
+        for _ in 0..5 {
+            assert_eq!(iter.try_next(), Err(MutatedDuringEnumeration));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "mutated while being enumerated")]
+    fn test_mutation_panics() {
+        let fake = Fake::new(10, 4, false);
+        let mut iter = unsafe { FastIterator::new(&fake) };
+        iter.next();
+        fake.mutations.set(7);
+        iter.next();
+    }
+
+    #[test]
+    fn test_fused() {
+        let fake = Fake::new(3, 2, true);
+        let mut iter = unsafe { FastIterator::new(&fake) };
+        assert_eq!(iter.size_hint(), (0, None));
+        iter.next();
+        assert_eq!(iter.size_hint(), (1, None));
+        assert_eq!(iter.by_ref().count(), 2);
+        assert_eq!(fake.calls.get(), 3);
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+
+        // Mutation after the end is allowed, and the collection is not asked again.
+        fake.mutations.set(1);
+        assert_eq!(iter.try_next(), Ok(None));
+        assert_eq!(fake.calls.get(), 3);
+    }
+
+    struct Slice {
+        items: Vec<*mut u8>,
+        mutations: Cell<libc::c_ulong>,
+    }
+
+    impl FastEnumerable<u8> for &Slice {
+        unsafe fn count_by_enumerating(&self, state: &mut NSFastEnumerationState<u8>, _: *mut *mut u8,
+                                       _: usize) -> usize {
+            enumerate_slice(state, &self.items, self.mutations.as_ptr())
+        }
+    }
+
+    #[test]
+    fn test_enumerate_slice() {
+        for &len in &[0, 1, 16, 17, 1000] {
+            let slice = Slice { items: (1..len + 1).map(|i| i as *mut u8).collect(), mutations: Cell::new(0) };
+            let mut iter = unsafe { FastIterator::new(&slice) };
+            let mut items = vec![];
+            while let Some(item) = iter.try_next().unwrap() {
+                items.push(item as usize);
+            }
+            assert_eq!(items, (1..len + 1).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_enumerate_slice_state() {
+        let items: Vec<*mut u8> = (1..6).map(|i| i as *mut u8).collect();
+        let mut mutations = 0;
+        let mut state = NSFastEnumerationState {
+            state: 0,
+            items_ptr: ptr::null_mut(),
+            mutations_ptr: ptr::null_mut(),
+            extra: [0; 5],
+        };
+        unsafe {
+            assert_eq!(enumerate_slice(&mut state, &items, &mut mutations), 5);
+            assert_eq!(state.items_ptr as *const _, items.as_ptr());
+            assert_eq!(state.mutations_ptr, &mut mutations as *mut _);
+            assert_eq!(enumerate_slice(&mut state, &items, &mut mutations), 0);
+
+            // Resuming part way through, as a caller may after copying the state.
+            state.state = 3;
+            assert_eq!(enumerate_slice(&mut state, &items, &mut mutations), 2);
+            assert_eq!(*state.items_ptr as usize, 4);
+        }
+    }
+}
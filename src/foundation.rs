@@ -9,24 +9,62 @@
 
 #![allow(non_upper_case_globals)]
 
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error;
+use std::ffi::{CStr, CString, OsStr};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::{Deref, Range};
+use std::pin::Pin;
+use std::process;
+use std::os::unix::ffi::OsStrExt;
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::ptr;
-use base::{id, class, BOOL, SEL, nil};
-use block::Block;
+use std::rc::{Rc, Weak};
+use std::slice;
+use std::sync::{Arc, Mutex, Once};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use base::{id, class, BOOL, Class, Id, NO, SEL, nil};
+pub use base::{NSInteger, NSUInteger};
+pub use range::{NSNotFound, NSRange};
+pub use base64::{NSDataBase64EncodingOptions, NSDataBase64Encoding64CharacterLineLength,
+                 NSDataBase64Encoding76CharacterLineLength, NSDataBase64EncodingEndLineWithCarriageReturn,
+                 NSDataBase64EncodingEndLineWithLineFeed};
+pub use base64::{NSDataBase64DecodingOptions, NSDataBase64DecodingIgnoreUnknownCharacters};
+pub use data::{NSDataSearchOptions, NSDataSearchBackwards, NSDataSearchAnchored};
+use block::{Block, ConcreteBlock, RcBlock};
+use data;
+use date;
+use executor::{self, JoinHandle};
+use plist;
+use styled_text::TextIndex;
+use fast_enumeration::{self, FastEnumerable, FastIterator};
+pub use fast_enumeration::{MutatedDuringEnumeration, NSFastEnumerationState};
 use core_graphics::base::CGFloat;
 use core_graphics::geometry::CGRect;
 use libc;
 use objc;
-
-#[cfg(target_pointer_width = "32")]
-pub type NSInteger = libc::c_int;
-#[cfg(target_pointer_width = "32")]
-pub type NSUInteger = libc::c_uint;
-
-#[cfg(target_pointer_width = "64")]
-pub type NSInteger = libc::c_long;
-#[cfg(target_pointer_width = "64")]
-pub type NSUInteger = libc::c_ulong;
+use objc::declare::ClassDecl;
+use objc::runtime::{Object, Sel};
+#[cfg(feature = "serde_json")]
+use serde_json;
+#[cfg(feature = "url")]
+use url::Url;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
 
 const UTF8_ENCODING: usize = 4;
 
@@ -113,22 +151,6 @@ impl NSRect {
     }
 }
 
-#[repr(C)]
-pub struct NSRange {
-    pub location: NSUInteger,
-    pub length: NSUInteger,
-}
-
-impl NSRange {
-    #[inline]
-    pub fn new(location: NSUInteger, length: NSUInteger) -> NSRange {
-        NSRange {
-            location: location,
-            length: length
-        }
-    }
-}
-
 unsafe impl objc::Encode for NSRect {
     fn encode() -> objc::Encoding {
         let encoding = format!("{{CGRect={}{}}}",
@@ -150,6 +172,7 @@ pub enum NSRectEdge {
 #[link(name = "Foundation", kind = "framework")]
 extern {
     pub static NSDefaultRunLoopMode: id;
+    pub static NSRunLoopCommonModes: id;
 }
 
 pub trait NSAutoreleasePool: Sized {
@@ -171,753 +194,5433 @@ impl NSAutoreleasePool for id {
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct NSOperatingSystemVersion {
+    pub majorVersion: NSInteger,
+    pub minorVersion: NSInteger,
+    pub patchVersion: NSInteger,
+}
+
+impl NSOperatingSystemVersion {
+    #[inline]
+    pub fn new(majorVersion: NSInteger, minorVersion: NSInteger, patchVersion: NSInteger)
+               -> NSOperatingSystemVersion {
+        NSOperatingSystemVersion { majorVersion, minorVersion, patchVersion }
+    }
+}
+
+impl fmt::Display for NSOperatingSystemVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.majorVersion, self.minorVersion, self.patchVersion)
+    }
+}
+
+unsafe impl objc::Encode for NSOperatingSystemVersion {
+    fn encode() -> objc::Encoding {
+        let encoding = format!("{{NSOperatingSystemVersion={}{}{}}}",
+                               NSInteger::encode().as_str(),
+                               NSInteger::encode().as_str(),
+                               NSInteger::encode().as_str());
+        unsafe { objc::Encoding::from_str(&encoding) }
+    }
+}
+
+#[repr(isize)]
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum NSProcessInfoThermalState {
+    NSProcessInfoThermalStateNominal = 0,
+    NSProcessInfoThermalStateFair = 1,
+    NSProcessInfoThermalStateSerious = 2,
+    NSProcessInfoThermalStateCritical = 3,
+}
+
+bitflags! {
+    pub struct NSActivityOptions: libc::c_ulonglong {
+        const NSActivityIdleDisplaySleepDisabled = 1 << 40;
+        const NSActivityIdleSystemSleepDisabled = 1 << 20;
+        const NSActivitySuddenTerminationDisabled = 1 << 14;
+        const NSActivityAutomaticTerminationDisabled = 1 << 15;
+        const NSActivityUserInitiated = 0x00FFFFFF;
+        const NSActivityUserInitiatedAllowingIdleSystemSleep = 0x00FFFFFF & !(1 << 20);
+        const NSActivityBackground = 0x000000FF;
+        const NSActivityLatencyCritical = 0xFF00000000;
+        const NSActivityUserInteractive = 0x00FFFFFF | 0xFF00000000;
+    }
+}
+
 pub trait NSProcessInfo: Sized {
     unsafe fn processInfo(_: Self) -> id {
         msg_send![class("NSProcessInfo"), processInfo]
     }
 
     unsafe fn processName(self) -> id;
+    unsafe fn arguments(self) -> id;
+    unsafe fn environment(self) -> id;
+    unsafe fn processIdentifier(self) -> libc::c_int;
+    unsafe fn globallyUniqueString(self) -> id;
+    unsafe fn hostName(self) -> id;
+    unsafe fn operatingSystemVersion(self) -> NSOperatingSystemVersion;
+    unsafe fn operatingSystemVersionString(self) -> id;
+    unsafe fn isOperatingSystemAtLeastVersion_(self, version: NSOperatingSystemVersion) -> BOOL;
+    unsafe fn physicalMemory(self) -> libc::c_ulonglong;
+    unsafe fn processorCount(self) -> NSUInteger;
+    unsafe fn activeProcessorCount(self) -> NSUInteger;
+    unsafe fn systemUptime(self) -> NSTimeInterval;
+    /// `None` for a state newer than the ones `NSProcessInfoThermalState` knows.
+    unsafe fn thermalState(self) -> Option<NSProcessInfoThermalState>;
+    /// Only available from macOS 12; see `low_power_mode`.
+    unsafe fn isLowPowerModeEnabled(self) -> BOOL;
+    unsafe fn beginActivityWithOptions_reason_(self, options: NSActivityOptions, reason: id) -> id;
+    unsafe fn endActivity_(self, activity: id);
+    unsafe fn disableSuddenTermination(self);
+    unsafe fn enableSuddenTermination(self);
+    unsafe fn disableAutomaticTermination_(self, reason: id);
+    unsafe fn enableAutomaticTermination_(self, reason: id);
+
+    unsafe fn args(self) -> Vec<String>;
+    unsafe fn env_vars(self) -> HashMap<String, String>;
+    /// Returns `None` before macOS 12, which has no Low Power Mode.
+    unsafe fn low_power_mode(self) -> Option<bool>;
+    /// Begins an activity that lasts until the returned guard is dropped. With
+    /// `NSActivityUserInitiated`, for example, App Nap leaves the process alone meanwhile.
+    unsafe fn begin_activity(self, options: NSActivityOptions, reason: &str) -> NSActivity;
 }
 
 impl NSProcessInfo for id {
     unsafe fn processName(self) -> id {
         msg_send![self, processName]
     }
-}
-
-pub type NSTimeInterval = libc::c_double;
 
-pub trait NSValue: Sized {
-    unsafe fn valueWithPoint(_: Self, point: NSPoint) -> id {
-        msg_send![class("NSValue"), valueWithPoint:point]
+    unsafe fn arguments(self) -> id {
+        msg_send![self, arguments]
     }
 
-    unsafe fn valueWithSize(_: Self, size: NSSize) -> id {
-        msg_send![class("NSValue"), valueWithSize:size]
+    unsafe fn environment(self) -> id {
+        msg_send![self, environment]
     }
-}
 
-impl NSValue for id {
-}
-
-pub trait NSArray: Sized {
-    unsafe fn array(_: Self) -> id {
-        msg_send![class("NSArray"), array]
+    unsafe fn processIdentifier(self) -> libc::c_int {
+        msg_send![self, processIdentifier]
     }
 
-    unsafe fn arrayWithObjects(_: Self, objects: &[id]) -> id {
-        msg_send![class("NSArray"), arrayWithObjects:objects.as_ptr()
-                                    count:objects.len()]
+    unsafe fn globallyUniqueString(self) -> id {
+        msg_send![self, globallyUniqueString]
     }
 
-    unsafe fn arrayWithObject(_: Self, object: id) -> id {
-        msg_send![class("NSArray"), arrayWithObject:object]
+    unsafe fn hostName(self) -> id {
+        msg_send![self, hostName]
     }
 
-    unsafe fn arrayByAddingObjectFromArray(self, object: id) -> id;
-    unsafe fn arrayByAddingObjectsFromArray(self, objects: id) -> id;
-}
-
-impl NSArray for id {
-    unsafe fn arrayByAddingObjectFromArray(self, object: id) -> id {
-        msg_send![self, arrayByAddingObjectFromArray:object]
+    unsafe fn operatingSystemVersion(self) -> NSOperatingSystemVersion {
+        msg_send![self, operatingSystemVersion]
     }
 
-    unsafe fn arrayByAddingObjectsFromArray(self, objects: id) -> id {
-        msg_send![self, arrayByAddingObjectsFromArray:objects]
+    unsafe fn operatingSystemVersionString(self) -> id {
+        msg_send![self, operatingSystemVersionString]
     }
-}
 
-pub trait NSDictionary: Sized {
-    unsafe fn dictionary(_: Self) -> id {
-        msg_send![class("NSDictionary"), dictionary]
+    unsafe fn isOperatingSystemAtLeastVersion_(self, version: NSOperatingSystemVersion) -> BOOL {
+        msg_send![self, isOperatingSystemAtLeastVersion:version]
     }
 
-    unsafe fn dictionaryWithContentsOfFile_(_: Self, path: id) -> id {
-        msg_send![class("NSDictionary"), dictionaryWithContentsOfFile:path]
+    unsafe fn physicalMemory(self) -> libc::c_ulonglong {
+        msg_send![self, physicalMemory]
     }
 
-    unsafe fn dictionaryWithContentsOfURL_(_: Self, aURL: id) -> id {
-        msg_send![class("NSDictionary"), dictionaryWithContentsOfURL:aURL]
+    unsafe fn processorCount(self) -> NSUInteger {
+        msg_send![self, processorCount]
     }
 
-    unsafe fn dictionaryWithDictionary_(_: Self, otherDictionary: id) -> id {
-        msg_send![class("NSDictionary"), dictionaryWithDictionary:otherDictionary]
+    unsafe fn activeProcessorCount(self) -> NSUInteger {
+        msg_send![self, activeProcessorCount]
     }
 
-    unsafe fn dictionaryWithObject_forKey_(_: Self, anObject: id, aKey: id) -> id {
-        msg_send![class("NSDictionary"), dictionaryWithObject:anObject forKey:aKey]
+    unsafe fn systemUptime(self) -> NSTimeInterval {
+        msg_send![self, systemUptime]
     }
 
-    unsafe fn dictionaryWithObjects_forKeys_(_: Self, objects: id, keys: id) -> id {
-        msg_send![class("NSDictionary"), dictionaryWithObjects:objects forKeys:keys]
+    unsafe fn thermalState(self) -> Option<NSProcessInfoThermalState> {
+        let state: NSInteger = msg_send![self, thermalState];
+        [NSProcessInfoThermalState::NSProcessInfoThermalStateNominal,
+         NSProcessInfoThermalState::NSProcessInfoThermalStateFair,
+         NSProcessInfoThermalState::NSProcessInfoThermalStateSerious,
+         NSProcessInfoThermalState::NSProcessInfoThermalStateCritical]
+            .iter()
+            .cloned()
+            .find(|&known| known as NSInteger == state)
     }
 
-    unsafe fn dictionaryWithObjects_forKeys_count_(_: Self, objects: *const id, keys: *const id, count: NSUInteger) -> id {
-        msg_send![class("NSDictionary"), dictionaryWithObjects:objects forKeys:keys count:count]
+    unsafe fn isLowPowerModeEnabled(self) -> BOOL {
+        msg_send![self, isLowPowerModeEnabled]
     }
 
-    unsafe fn dictionaryWithObjectsAndKeys_(_: Self, firstObject: id) -> id {
-        msg_send![class("NSDictionary"), dictionaryWithObjectsAndKeys:firstObject]
+    unsafe fn beginActivityWithOptions_reason_(self, options: NSActivityOptions, reason: id) -> id {
+        msg_send![self, beginActivityWithOptions:options reason:reason]
     }
 
-    unsafe fn init(self) -> id;
-    unsafe fn initWithContentsOfFile_(self, path: id) -> id;
-    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id;
-    unsafe fn initWithDictionary_(self, otherDicitonary: id) -> id;
-    unsafe fn initWithDictionary_copyItems_(self, otherDicitonary: id, flag: BOOL) -> id;
-    unsafe fn initWithObjects_forKeys_(self, objects: id, keys: id) -> id;
-    unsafe fn initWithObjects_forKeys_count_(self, objects: id, keys: id, count: NSUInteger) -> id;
-    unsafe fn initWithObjectsAndKeys_(self, firstObject: id) -> id;
+    unsafe fn endActivity_(self, activity: id) {
+        msg_send![self, endActivity:activity]
+    }
 
-    unsafe fn sharedKeySetForKeys_(_: Self, keys: id) -> id {
-        msg_send![class("NSDictionary"), sharedKeySetForKeys:keys]
+    unsafe fn disableSuddenTermination(self) {
+        msg_send![self, disableSuddenTermination]
     }
 
-    unsafe fn count(self) -> NSUInteger;
+    unsafe fn enableSuddenTermination(self) {
+        msg_send![self, enableSuddenTermination]
+    }
 
-    unsafe fn isEqualToDictionary_(self, otherDictionary: id) -> BOOL;
+    unsafe fn disableAutomaticTermination_(self, reason: id) {
+        msg_send![self, disableAutomaticTermination:reason]
+    }
 
-    unsafe fn allKeys(self) -> id;
-    unsafe fn allKeysForObject_(self, anObject: id) -> id;
-    unsafe fn allValues(self) -> id;
-    unsafe fn objectForKey_(self, aKey: id) -> id;
-    unsafe fn objectForKeyedSubscript_(self, key: id) -> id;
-    unsafe fn objectsForKeys_notFoundMarker_(self, keys: id, anObject: id) -> id;
-    unsafe fn valueForKey_(self, key: id) -> id;
+    unsafe fn enableAutomaticTermination_(self, reason: id) {
+        msg_send![self, enableAutomaticTermination:reason]
+    }
 
-    unsafe fn keyEnumerator(self) -> id;
-    unsafe fn objectEnumerator(self) -> id;
-    unsafe fn enumerateKeysAndObjectsUsingBlock_(self, block: *mut Block<(id, id, *mut BOOL), ()>);
-    unsafe fn enumerateKeysAndObjectsWithOptions_usingBlock_(self, opts: NSEnumerationOptions,
-                                                             block: *mut Block<(id, id, *mut BOOL), ()>);
+    unsafe fn args(self) -> Vec<String> {
+        Vec::from_cocoa(self.arguments()).unwrap_or_default()
+    }
 
-    unsafe fn keysSortedByValueUsingSelector_(self, comparator: SEL) -> id;
-    unsafe fn keysSortedByValueUsingComparator_(self, cmptr: NSComparator) -> id;
-    unsafe fn keysSortedByValueWithOptions_usingComparator_(self, opts: NSEnumerationOptions, cmptr: NSComparator) -> id;
+    unsafe fn env_vars(self) -> HashMap<String, String> {
+        HashMap::from_cocoa(self.environment()).unwrap_or_default()
+    }
 
-    unsafe fn keysOfEntriesPassingTest_(self, predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id;
-    unsafe fn keysOfEntriesWithOptions_PassingTest_(self, opts: NSEnumerationOptions,
-                                                    predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id;
+    unsafe fn low_power_mode(self) -> Option<bool> {
+        let responds: BOOL = msg_send![self, respondsToSelector:sel!(isLowPowerModeEnabled)];
+        if responds != NO { Some(self.isLowPowerModeEnabled() != NO) } else { None }
+    }
 
-    unsafe fn writeToFile_atomically_(self, path: id, flag: BOOL) -> BOOL;
-    unsafe fn writeToURL_atomically_(self, aURL: id, flag: BOOL) -> BOOL;
+    unsafe fn begin_activity(self, options: NSActivityOptions, reason: &str) -> NSActivity {
+        let activity = self.beginActivityWithOptions_reason_(options, *reason.to_cocoa());
+        NSActivity {
+            process_info: Id::from_unretained(self),
+            activity: Id::from_unretained(activity),
+        }
+    }
+}
 
-    unsafe fn fileCreationDate(self) -> id;
-    unsafe fn fileExtensionHidden(self) -> BOOL;
-    unsafe fn fileGroupOwnerAccountID(self) -> id;
-    unsafe fn fileGroupOwnerAccountName(self) -> id;
-    unsafe fn fileIsAppendOnly(self) -> BOOL;
-    unsafe fn fileIsImmutable(self) -> BOOL;
-    unsafe fn fileModificationDate(self) -> id;
-    unsafe fn fileOwnerAccountID(self) -> id;
-    unsafe fn fileOwnerAccountName(self) -> id;
-    unsafe fn filePosixPermissions(self) -> NSUInteger;
-    unsafe fn fileSize(self) -> libc::c_ulonglong;
-    unsafe fn fileSystemFileNumber(self) -> NSUInteger;
-    unsafe fn fileSystemNumber(self) -> NSInteger;
-    unsafe fn fileType(self) -> id;
+/// An activity begun by `NSProcessInfo::begin_activity`, ended on drop.
+pub struct NSActivity {
+    process_info: Id,
+    activity: Id,
+}
 
-    unsafe fn description(self) -> id;
-    unsafe fn descriptionInStringsFileFormat(self) -> id;
-    unsafe fn descriptionWithLocale_(self, locale: id) -> id;
-    unsafe fn descriptionWithLocale_indent_(self, locale: id, indent: NSUInteger) -> id;
+impl NSActivity {
+    /// The token from `beginActivityWithOptions:reason:`.
+    pub fn as_id(&self) -> id {
+        self.activity.as_ptr()
+    }
 }
 
-impl NSDictionary for id {
-    unsafe fn init(self) -> id {
-        msg_send![self, init]
+impl Drop for NSActivity {
+    fn drop(&mut self) {
+        unsafe { self.process_info.endActivity_(*self.activity) }
     }
+}
 
-    unsafe fn initWithContentsOfFile_(self, path: id) -> id {
-        msg_send![self, initWithContentsOfFile:path]
+pub use date::{NSTimeInterval, NSTimeIntervalSince1970};
+
+pub trait NSValue: Sized {
+    unsafe fn valueWithPoint(_: Self, point: NSPoint) -> id {
+        msg_send![class("NSValue"), valueWithPoint:point]
     }
 
-    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id {
-        msg_send![self, initWithContentsOfURL:aURL]
+    unsafe fn valueWithSize(_: Self, size: NSSize) -> id {
+        msg_send![class("NSValue"), valueWithSize:size]
     }
+}
 
-    unsafe fn initWithDictionary_(self, otherDictionary: id) -> id {
-        msg_send![self, initWithDictionary:otherDictionary]
+impl NSValue for id {
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern {
+    static kCFBooleanTrue: id;
+    static kCFBooleanFalse: id;
+}
+
+pub trait NSNumber: Sized {
+    unsafe fn numberWithBool_(_: Self, value: BOOL) -> id {
+        msg_send![class("NSNumber"), numberWithBool:value]
     }
 
-    unsafe fn initWithDictionary_copyItems_(self, otherDictionary: id, flag: BOOL) -> id {
-        msg_send![self, initWithDictionary:otherDictionary copyItems:flag]
+    unsafe fn numberWithChar_(_: Self, value: libc::c_char) -> id {
+        msg_send![class("NSNumber"), numberWithChar:value]
     }
 
-    unsafe fn initWithObjects_forKeys_(self, objects: id, keys: id) -> id {
-        msg_send![self, initWithObjects:objects forKeys:keys]
+    unsafe fn numberWithUnsignedChar_(_: Self, value: libc::c_uchar) -> id {
+        msg_send![class("NSNumber"), numberWithUnsignedChar:value]
     }
 
-    unsafe fn initWithObjects_forKeys_count_(self, objects: id, keys: id, count: NSUInteger) -> id {
-        msg_send![self, initWithObjects:objects forKeys:keys count:count]
+    unsafe fn numberWithShort_(_: Self, value: libc::c_short) -> id {
+        msg_send![class("NSNumber"), numberWithShort:value]
     }
 
-    unsafe fn initWithObjectsAndKeys_(self, firstObject: id) -> id {
-        msg_send![self, initWithObjectsAndKeys:firstObject]
+    unsafe fn numberWithUnsignedShort_(_: Self, value: libc::c_ushort) -> id {
+        msg_send![class("NSNumber"), numberWithUnsignedShort:value]
     }
 
-    unsafe fn count(self) -> NSUInteger {
-        msg_send![self, count]
+    unsafe fn numberWithInt_(_: Self, value: libc::c_int) -> id {
+        msg_send![class("NSNumber"), numberWithInt:value]
     }
 
-    unsafe fn isEqualToDictionary_(self, otherDictionary: id) -> BOOL {
-        msg_send![self, isEqualToDictionary:otherDictionary]
+    unsafe fn numberWithUnsignedInt_(_: Self, value: libc::c_uint) -> id {
+        msg_send![class("NSNumber"), numberWithUnsignedInt:value]
     }
 
-    unsafe fn allKeys(self) -> id {
-        msg_send![self, allKeys]
+    unsafe fn numberWithLong_(_: Self, value: libc::c_long) -> id {
+        msg_send![class("NSNumber"), numberWithLong:value]
     }
 
-    unsafe fn allKeysForObject_(self, anObject: id) -> id {
-        msg_send![self, allKeysForObject:anObject]
+    unsafe fn numberWithUnsignedLong_(_: Self, value: libc::c_ulong) -> id {
+        msg_send![class("NSNumber"), numberWithUnsignedLong:value]
     }
 
-    unsafe fn allValues(self) -> id {
-        msg_send![self, allValues]
+    unsafe fn numberWithLongLong_(_: Self, value: libc::c_longlong) -> id {
+        msg_send![class("NSNumber"), numberWithLongLong:value]
     }
 
-    unsafe fn objectForKey_(self, aKey: id) -> id {
-        msg_send![self, objectForKey:aKey]
+    unsafe fn numberWithUnsignedLongLong_(_: Self, value: libc::c_ulonglong) -> id {
+        msg_send![class("NSNumber"), numberWithUnsignedLongLong:value]
     }
 
-    unsafe fn objectForKeyedSubscript_(self, key: id) -> id {
-        msg_send![self, objectForKeyedSubscript:key]
+    unsafe fn numberWithInteger_(_: Self, value: NSInteger) -> id {
+        msg_send![class("NSNumber"), numberWithInteger:value]
     }
 
-    unsafe fn objectsForKeys_notFoundMarker_(self, keys: id, anObject: id) -> id {
-        msg_send![self, objectsForKeys:keys notFoundMarker:anObject]
+    unsafe fn numberWithUnsignedInteger_(_: Self, value: NSUInteger) -> id {
+        msg_send![class("NSNumber"), numberWithUnsignedInteger:value]
     }
 
-    unsafe fn valueForKey_(self, key: id) -> id {
-        msg_send![self, valueForKey:key]
+    unsafe fn numberWithFloat_(_: Self, value: libc::c_float) -> id {
+        msg_send![class("NSNumber"), numberWithFloat:value]
     }
 
-    unsafe fn keyEnumerator(self) -> id {
-        msg_send![self, keyEnumerator]
+    unsafe fn numberWithDouble_(_: Self, value: libc::c_double) -> id {
+        msg_send![class("NSNumber"), numberWithDouble:value]
     }
 
-    unsafe fn objectEnumerator(self) -> id {
-        msg_send![self, objectEnumerator]
+    unsafe fn boolValue(self) -> BOOL;
+    unsafe fn charValue(self) -> libc::c_char;
+    unsafe fn unsignedCharValue(self) -> libc::c_uchar;
+    unsafe fn shortValue(self) -> libc::c_short;
+    unsafe fn unsignedShortValue(self) -> libc::c_ushort;
+    unsafe fn intValue(self) -> libc::c_int;
+    unsafe fn unsignedIntValue(self) -> libc::c_uint;
+    unsafe fn longValue(self) -> libc::c_long;
+    unsafe fn unsignedLongValue(self) -> libc::c_ulong;
+    unsafe fn longLongValue(self) -> libc::c_longlong;
+    unsafe fn unsignedLongLongValue(self) -> libc::c_ulonglong;
+    unsafe fn integerValue(self) -> NSInteger;
+    unsafe fn unsignedIntegerValue(self) -> NSUInteger;
+    unsafe fn floatValue(self) -> libc::c_float;
+    unsafe fn doubleValue(self) -> libc::c_double;
+
+    unsafe fn objCType(self) -> *const libc::c_char;
+    unsafe fn stringValue(self) -> id;
+    unsafe fn compare_(self, otherNumber: id) -> NSComparisonResult;
+    unsafe fn isEqualToNumber_(self, number: id) -> BOOL;
+
+    /// Reads the number as the type it was created with, as reported by `objCType`.
+    unsafe fn value(self) -> NSNumberValue;
+}
+
+impl NSNumber for id {
+    unsafe fn boolValue(self) -> BOOL {
+        msg_send![self, boolValue]
     }
 
-    unsafe fn enumerateKeysAndObjectsUsingBlock_(self, block: *mut Block<(id, id, *mut BOOL), ()>) {
-        msg_send![self, enumerateKeysAndObjectsUsingBlock:block]
+    unsafe fn charValue(self) -> libc::c_char {
+        msg_send![self, charValue]
     }
 
-    unsafe fn enumerateKeysAndObjectsWithOptions_usingBlock_(self, opts: NSEnumerationOptions,
-                                                     block: *mut Block<(id, id, *mut BOOL), ()>) {
-        msg_send![self, enumerateKeysAndObjectsWithOptions:opts usingBlock:block]
+    unsafe fn unsignedCharValue(self) -> libc::c_uchar {
+        msg_send![self, unsignedCharValue]
     }
 
-    unsafe fn keysSortedByValueUsingSelector_(self, comparator: SEL) -> id {
-        msg_send![self, keysSortedByValueUsingSelector:comparator]
+    unsafe fn shortValue(self) -> libc::c_short {
+        msg_send![self, shortValue]
     }
 
-    unsafe fn keysSortedByValueUsingComparator_(self, cmptr: NSComparator) -> id {
-        msg_send![self, keysSortedByValueUsingComparator:cmptr]
+    unsafe fn unsignedShortValue(self) -> libc::c_ushort {
+        msg_send![self, unsignedShortValue]
     }
 
-    unsafe fn keysSortedByValueWithOptions_usingComparator_(self, opts: NSEnumerationOptions, cmptr: NSComparator) -> id {
-        let rv: id = msg_send![self, keysSortedByValueWithOptions:opts usingComparator:cmptr];
-        rv
+    unsafe fn intValue(self) -> libc::c_int {
+        msg_send![self, intValue]
     }
 
-    unsafe fn keysOfEntriesPassingTest_(self, predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id {
-        msg_send![self, keysOfEntriesPassingTest:predicate]
+    unsafe fn unsignedIntValue(self) -> libc::c_uint {
+        msg_send![self, unsignedIntValue]
     }
 
-    unsafe fn keysOfEntriesWithOptions_PassingTest_(self, opts: NSEnumerationOptions,
-                                                    predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id {
-        msg_send![self, keysOfEntriesWithOptions:opts PassingTest:predicate]
+    unsafe fn longValue(self) -> libc::c_long {
+        msg_send![self, longValue]
     }
 
-    unsafe fn writeToFile_atomically_(self, path: id, flag: BOOL) -> BOOL {
-        msg_send![self, writeToFile:path atomically:flag]
+    unsafe fn unsignedLongValue(self) -> libc::c_ulong {
+        msg_send![self, unsignedLongValue]
     }
 
-    unsafe fn writeToURL_atomically_(self, aURL: id, flag: BOOL) -> BOOL {
-        msg_send![self, writeToURL:aURL atomically:flag]
+    unsafe fn longLongValue(self) -> libc::c_longlong {
+        msg_send![self, longLongValue]
     }
 
-    unsafe fn fileCreationDate(self) -> id {
-        msg_send![self, fileCreationDate]
+    unsafe fn unsignedLongLongValue(self) -> libc::c_ulonglong {
+        msg_send![self, unsignedLongLongValue]
     }
 
-    unsafe fn fileExtensionHidden(self) -> BOOL {
-        msg_send![self, fileExtensionHidden]
+    unsafe fn integerValue(self) -> NSInteger {
+        msg_send![self, integerValue]
     }
 
-    unsafe fn fileGroupOwnerAccountID(self) -> id {
-        msg_send![self, fileGroupOwnerAccountID]
+    unsafe fn unsignedIntegerValue(self) -> NSUInteger {
+        msg_send![self, unsignedIntegerValue]
     }
 
-    unsafe fn fileGroupOwnerAccountName(self) -> id {
-        msg_send![self, fileGroupOwnerAccountName]
+    unsafe fn floatValue(self) -> libc::c_float {
+        msg_send![self, floatValue]
     }
 
-    unsafe fn fileIsAppendOnly(self) -> BOOL {
-        msg_send![self, fileIsAppendOnly]
+    unsafe fn doubleValue(self) -> libc::c_double {
+        msg_send![self, doubleValue]
     }
 
-    unsafe fn fileIsImmutable(self) -> BOOL {
-        msg_send![self, fileIsImmutable]
+    unsafe fn objCType(self) -> *const libc::c_char {
+        msg_send![self, objCType]
     }
 
-    unsafe fn fileModificationDate(self) -> id {
-        msg_send![self, fileModificationDate]
+    unsafe fn stringValue(self) -> id {
+        msg_send![self, stringValue]
     }
 
-    unsafe fn fileOwnerAccountID(self) -> id {
-        msg_send![self, fileOwnerAccountID]
+    unsafe fn compare_(self, otherNumber: id) -> NSComparisonResult {
+        msg_send![self, compare:otherNumber]
     }
 
-    unsafe fn fileOwnerAccountName(self) -> id {
-        msg_send![self, fileOwnerAccountName]
+    unsafe fn isEqualToNumber_(self, number: id) -> BOOL {
+        msg_send![self, isEqualToNumber:number]
     }
 
-    unsafe fn filePosixPermissions(self) -> NSUInteger {
-        msg_send![self, filePosixPermissions]
+    unsafe fn value(self) -> NSNumberValue {
+        // `numberWithBool:` returns one of the two CFBoolean singletons, whose objCType is "c".
+        if self == kCFBooleanTrue || self == kCFBooleanFalse {
+            return NSNumberValue::Bool(self == kCFBooleanTrue);
+        }
+        match *self.objCType() as u8 {
+            b'B' => NSNumberValue::Bool(self.boolValue() != NO),
+            b'c' => NSNumberValue::I8(self.charValue()),
+            b'C' => NSNumberValue::U8(self.unsignedCharValue()),
+            b's' => NSNumberValue::I16(self.shortValue()),
+            b'S' => NSNumberValue::U16(self.unsignedShortValue()),
+            b'i' => NSNumberValue::I32(self.intValue()),
+            b'I' => NSNumberValue::U32(self.unsignedIntValue()),
+            b'l' if mem::size_of::<libc::c_long>() == 4 => NSNumberValue::I32(self.intValue()),
+            b'L' if mem::size_of::<libc::c_ulong>() == 4 => NSNumberValue::U32(self.unsignedIntValue()),
+            b'l' | b'q' => NSNumberValue::I64(self.longLongValue()),
+            b'L' | b'Q' => NSNumberValue::U64(self.unsignedLongLongValue()),
+            b'f' => NSNumberValue::F32(self.floatValue()),
+            _ => NSNumberValue::F64(self.doubleValue()),
+        }
     }
+}
 
-    unsafe fn fileSize(self) -> libc::c_ulonglong {
-        msg_send![self, fileSize]
+/// The value stored in an `NSNumber`, tagged with its C type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NSNumberValue {
+    Bool(bool),
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+}
+
+impl NSNumberValue {
+    /// Creates an autoreleased `NSNumber` holding this value.
+    pub unsafe fn into_number(self) -> id {
+        match self {
+            NSNumberValue::Bool(b) => NSNumber::numberWithBool_(nil, b as BOOL),
+            NSNumberValue::I8(i) => NSNumber::numberWithChar_(nil, i as libc::c_char),
+            NSNumberValue::U8(i) => NSNumber::numberWithUnsignedChar_(nil, i),
+            NSNumberValue::I16(i) => NSNumber::numberWithShort_(nil, i),
+            NSNumberValue::U16(i) => NSNumber::numberWithUnsignedShort_(nil, i),
+            NSNumberValue::I32(i) => NSNumber::numberWithInt_(nil, i),
+            NSNumberValue::U32(i) => NSNumber::numberWithUnsignedInt_(nil, i),
+            NSNumberValue::I64(i) => NSNumber::numberWithLongLong_(nil, i),
+            NSNumberValue::U64(i) => NSNumber::numberWithUnsignedLongLong_(nil, i),
+            NSNumberValue::F32(f) => NSNumber::numberWithFloat_(nil, f),
+            NSNumberValue::F64(f) => NSNumber::numberWithDouble_(nil, f),
+        }
     }
 
-    unsafe fn fileSystemFileNumber(self) -> NSUInteger {
-        msg_send![self, fileSystemFileNumber]
+    fn as_i128(self) -> Result<i128, NSNumberConversionError> {
+        match self {
+            NSNumberValue::Bool(_) => Err(NSNumberConversionError::NotANumber),
+            NSNumberValue::I8(i) => Ok(i as i128),
+            NSNumberValue::U8(i) => Ok(i as i128),
+            NSNumberValue::I16(i) => Ok(i as i128),
+            NSNumberValue::U16(i) => Ok(i as i128),
+            NSNumberValue::I32(i) => Ok(i as i128),
+            NSNumberValue::U32(i) => Ok(i as i128),
+            NSNumberValue::I64(i) => Ok(i as i128),
+            NSNumberValue::U64(i) => Ok(i as i128),
+            NSNumberValue::F32(f) => float_to_i128(f as f64),
+            NSNumberValue::F64(f) => float_to_i128(f),
+        }
     }
 
-    unsafe fn fileSystemNumber(self) -> NSInteger {
-        msg_send![self, fileSystemNumber]
+    fn as_f64(self) -> Result<f64, NSNumberConversionError> {
+        match self {
+            NSNumberValue::F32(f) => Ok(f as f64),
+            NSNumberValue::F64(f) => Ok(f),
+            _ => {
+                let i = self.as_i128()?;
+                let f = i as f64;
+                if f as i128 == i && f.abs() < 2f64.powi(127) {
+                    Ok(f)
+                } else {
+                    Err(NSNumberConversionError::Inexact)
+                }
+            }
+        }
     }
+}
 
-    unsafe fn fileType(self) -> id {
-        msg_send![self, fileType]
+fn float_to_i128(f: f64) -> Result<i128, NSNumberConversionError> {
+    if f.is_infinite() || f.abs() >= 2f64.powi(127) {
+        Err(NSNumberConversionError::Overflow)
+    } else if f.is_nan() || f.fract() != 0.0 {
+        Err(NSNumberConversionError::Inexact)
+    } else {
+        Ok(f as i128)
+    }
+}
+
+/// Why an `NSNumberValue` could not be converted to a Rust type without losing information.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NSNumberConversionError {
+    /// The value is outside the range of the target type.
+    Overflow,
+    /// The value is within range but would be rounded, or is NaN.
+    Inexact,
+    /// Booleans only convert to `bool`, and `bool` only from booleans.
+    NotANumber,
+}
+
+impl fmt::Display for NSNumberConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            NSNumberConversionError::Overflow => "number out of range for the target type",
+            NSNumberConversionError::Inexact => "number is not exactly representable in the target type",
+            NSNumberConversionError::NotANumber => "booleans and numbers do not convert to each other",
+        })
+    }
+}
+
+impl error::Error for NSNumberConversionError {}
+
+macro_rules! number_value_conversions {
+    ($($ty:ident => $variant:ident),*) => {
+        $(
+            impl From<$ty> for NSNumberValue {
+                fn from(value: $ty) -> NSNumberValue {
+                    NSNumberValue::$variant(value)
+                }
+            }
+        )*
+    }
+}
+
+number_value_conversions!(bool => Bool, i8 => I8, u8 => U8, i16 => I16, u16 => U16, i32 => I32, u32 => U32,
+                          i64 => I64, u64 => U64, f32 => F32, f64 => F64);
+
+macro_rules! number_value_integer_conversions {
+    ($($ty:ident),*) => {
+        $(
+            impl TryFrom<NSNumberValue> for $ty {
+                type Error = NSNumberConversionError;
+
+                fn try_from(value: NSNumberValue) -> Result<$ty, NSNumberConversionError> {
+                    $ty::try_from(value.as_i128()?).map_err(|_| NSNumberConversionError::Overflow)
+                }
+            }
+        )*
+    }
+}
+
+number_value_integer_conversions!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+impl TryFrom<NSNumberValue> for f64 {
+    type Error = NSNumberConversionError;
+
+    fn try_from(value: NSNumberValue) -> Result<f64, NSNumberConversionError> {
+        value.as_f64()
+    }
+}
+
+impl TryFrom<NSNumberValue> for f32 {
+    type Error = NSNumberConversionError;
+
+    fn try_from(value: NSNumberValue) -> Result<f32, NSNumberConversionError> {
+        let f = value.as_f64()?;
+        if f.is_nan() || f.is_infinite() || f as f32 as f64 == f {
+            Ok(f as f32)
+        } else if f.abs() > f32::MAX as f64 {
+            Err(NSNumberConversionError::Overflow)
+        } else {
+            Err(NSNumberConversionError::Inexact)
+        }
+    }
+}
+
+impl TryFrom<NSNumberValue> for bool {
+    type Error = NSNumberConversionError;
+
+    fn try_from(value: NSNumberValue) -> Result<bool, NSNumberConversionError> {
+        match value {
+            NSNumberValue::Bool(b) => Ok(b),
+            _ => Err(NSNumberConversionError::NotANumber),
+        }
+    }
+}
+
+pub trait NSArray: Sized {
+    unsafe fn array(_: Self) -> id {
+        msg_send![class("NSArray"), array]
+    }
+
+    unsafe fn arrayWithObjects(_: Self, objects: &[id]) -> id {
+        msg_send![class("NSArray"), arrayWithObjects:objects.as_ptr()
+                                    count:objects.len()]
+    }
+
+    unsafe fn arrayWithObject(_: Self, object: id) -> id {
+        msg_send![class("NSArray"), arrayWithObject:object]
+    }
+
+    /// Wraps `objects` in a new autoreleased `NSArray` without copying them. See `ArrayId::from`.
+    unsafe fn from_vec(_: Self, objects: Vec<Id>) -> id {
+        msg_send![ArrayId::from(objects).into_id().into_retained(), autorelease]
+    }
+
+    unsafe fn count(self) -> NSUInteger;
+    unsafe fn objectAtIndex_(self, index: NSUInteger) -> id;
+    unsafe fn firstObject(self) -> id;
+    unsafe fn lastObject(self) -> id;
+    unsafe fn containsObject_(self, anObject: id) -> BOOL;
+    unsafe fn indexOfObject_(self, anObject: id) -> NSUInteger;
+    unsafe fn reverseObjectEnumerator(self) -> id;
+
+    unsafe fn arrayByAddingObjectFromArray(self, object: id) -> id;
+    unsafe fn arrayByAddingObjectsFromArray(self, objects: id) -> id;
+
+    /// Returns the object at `index`, or `None` where `objectAtIndex:` would raise.
+    unsafe fn get(self, index: usize) -> Option<id>;
+    unsafe fn first(self) -> Option<id>;
+    unsafe fn last(self) -> Option<id>;
+    /// Tests membership with `isEqual:`.
+    unsafe fn contains(self, object: id) -> bool;
+    /// Returns the lowest index of an object equal to `object` under `isEqual:`.
+    unsafe fn index_of(self, object: id) -> Option<usize>;
+
+    /// Like `iter`, but double-ended and exactly sized.
+    unsafe fn array_iter(self) -> NSArrayIterator;
+    /// Fast-enumerates `reverseObjectEnumerator`.
+    unsafe fn iter_rev(self) -> NSFastIterator;
+}
+
+impl NSArray for id {
+    unsafe fn count(self) -> NSUInteger {
+        msg_send![self, count]
+    }
+
+    unsafe fn objectAtIndex_(self, index: NSUInteger) -> id {
+        msg_send![self, objectAtIndex:index]
+    }
+
+    unsafe fn firstObject(self) -> id {
+        msg_send![self, firstObject]
+    }
+
+    unsafe fn lastObject(self) -> id {
+        msg_send![self, lastObject]
+    }
+
+    unsafe fn containsObject_(self, anObject: id) -> BOOL {
+        msg_send![self, containsObject:anObject]
+    }
+
+    unsafe fn indexOfObject_(self, anObject: id) -> NSUInteger {
+        msg_send![self, indexOfObject:anObject]
+    }
+
+    unsafe fn reverseObjectEnumerator(self) -> id {
+        msg_send![self, reverseObjectEnumerator]
+    }
+
+    unsafe fn arrayByAddingObjectFromArray(self, object: id) -> id {
+        msg_send![self, arrayByAddingObjectFromArray:object]
+    }
+
+    unsafe fn arrayByAddingObjectsFromArray(self, objects: id) -> id {
+        msg_send![self, arrayByAddingObjectsFromArray:objects]
+    }
+
+    unsafe fn get(self, index: usize) -> Option<id> {
+        if index < NSArray::count(self) as usize {
+            Some(NSArray::objectAtIndex_(self, index as NSUInteger))
+        } else {
+            None
+        }
+    }
+
+    unsafe fn first(self) -> Option<id> {
+        non_nil(NSArray::firstObject(self))
+    }
+
+    unsafe fn last(self) -> Option<id> {
+        non_nil(NSArray::lastObject(self))
+    }
+
+    unsafe fn contains(self, object: id) -> bool {
+        NSArray::containsObject_(self, object) != NO
+    }
+
+    unsafe fn index_of(self, object: id) -> Option<usize> {
+        match NSArray::indexOfObject_(self, object) {
+            index if index == NSNotFound as NSUInteger => None,
+            index => Some(index as usize),
+        }
+    }
+
+    unsafe fn array_iter(self) -> NSArrayIterator {
+        let count = NSArray::count(self) as usize;
+        NSArrayIterator { array: self, front: self.iter(), count, start: 0, end: count }
+    }
+
+    unsafe fn iter_rev(self) -> NSFastIterator {
+        NSArray::reverseObjectEnumerator(self).iter()
+    }
+}
+
+fn non_nil(object: id) -> Option<id> {
+    if object.is_null() { None } else { Some(object) }
+}
+
+pub trait NSMutableArray: NSArray {
+    unsafe fn arrayWithCapacity_(_: Self, numItems: NSUInteger) -> id {
+        msg_send![class("NSMutableArray"), arrayWithCapacity:numItems]
+    }
+
+    unsafe fn initWithCapacity_(self, numItems: NSUInteger) -> id;
+
+    unsafe fn addObject_(self, anObject: id);
+    unsafe fn insertObject_atIndex_(self, anObject: id, index: NSUInteger);
+    unsafe fn removeObjectAtIndex_(self, index: NSUInteger);
+    unsafe fn removeLastObject(self);
+    unsafe fn removeAllObjects(self);
+    unsafe fn removeObject_(self, anObject: id);
+    unsafe fn replaceObjectAtIndex_withObject_(self, index: NSUInteger, anObject: id);
+    unsafe fn exchangeObjectAtIndex_withObjectAtIndex_(self, idx1: NSUInteger, idx2: NSUInteger);
+    unsafe fn sortUsingComparator_(self, cmptr: NSComparator);
+    unsafe fn sortUsingSelector_(self, comparator: SEL);
+
+    /// Inserts `object` at `index`, returning `false` where `insertObject:atIndex:` would raise.
+    unsafe fn insert(self, index: usize, object: id) -> bool;
+    /// Removes and returns the object at `index`, or `None` if it is out of bounds.
+    unsafe fn remove(self, index: usize) -> Option<Id>;
+    /// Replaces the object at `index`, returning the old one, or `None` if it is out of bounds.
+    unsafe fn replace_at(self, index: usize, object: id) -> Option<Id>;
+    unsafe fn sort_by<F>(self, compare: F) where F: Fn(id, id) -> Ordering;
+}
+
+impl NSMutableArray for id {
+    unsafe fn initWithCapacity_(self, numItems: NSUInteger) -> id {
+        msg_send![self, initWithCapacity:numItems]
+    }
+
+    unsafe fn addObject_(self, anObject: id) {
+        msg_send![self, addObject:anObject]
+    }
+
+    unsafe fn insertObject_atIndex_(self, anObject: id, index: NSUInteger) {
+        msg_send![self, insertObject:anObject atIndex:index]
+    }
+
+    unsafe fn removeObjectAtIndex_(self, index: NSUInteger) {
+        msg_send![self, removeObjectAtIndex:index]
+    }
+
+    unsafe fn removeLastObject(self) {
+        msg_send![self, removeLastObject]
+    }
+
+    unsafe fn removeAllObjects(self) {
+        msg_send![self, removeAllObjects]
+    }
+
+    unsafe fn removeObject_(self, anObject: id) {
+        msg_send![self, removeObject:anObject]
+    }
+
+    unsafe fn replaceObjectAtIndex_withObject_(self, index: NSUInteger, anObject: id) {
+        msg_send![self, replaceObjectAtIndex:index withObject:anObject]
+    }
+
+    unsafe fn exchangeObjectAtIndex_withObjectAtIndex_(self, idx1: NSUInteger, idx2: NSUInteger) {
+        msg_send![self, exchangeObjectAtIndex:idx1 withObjectAtIndex:idx2]
+    }
+
+    unsafe fn sortUsingComparator_(self, cmptr: NSComparator) {
+        msg_send![self, sortUsingComparator:cmptr]
+    }
+
+    unsafe fn sortUsingSelector_(self, comparator: SEL) {
+        msg_send![self, sortUsingSelector:comparator]
+    }
+
+    unsafe fn insert(self, index: usize, object: id) -> bool {
+        if index > NSArray::count(self) as usize {
+            return false;
+        }
+        self.insertObject_atIndex_(object, index as NSUInteger);
+        true
+    }
+
+    unsafe fn remove(self, index: usize) -> Option<Id> {
+        // Take a reference first, since the array may hold the only one.
+        let object = Id::from_unretained(NSArray::get(self, index)?);
+        self.removeObjectAtIndex_(index as NSUInteger);
+        Some(object)
+    }
+
+    unsafe fn replace_at(self, index: usize, object: id) -> Option<Id> {
+        let old = Id::from_unretained(NSArray::get(self, index)?);
+        self.replaceObjectAtIndex_withObject_(index as NSUInteger, object);
+        Some(old)
+    }
+
+    unsafe fn sort_by<F>(self, compare: F) where F: Fn(id, id) -> Ordering {
+        let block = ConcreteBlock::new(move |a: id, b: id| -> NSComparisonResult {
+            compare(a, b).into()
+        });
+        self.sortUsingComparator_(&*block as *const _ as *mut _)
+    }
+}
+
+/// An owned `NSArray`, for building arrays from Rust iterators and taking them apart again.
+pub struct ArrayId(Id);
+
+impl ArrayId {
+    /// Wraps `array`, which must be an `NSArray` or subclass.
+    pub unsafe fn new(array: Id) -> ArrayId {
+        ArrayId(array)
+    }
+
+    pub fn into_id(self) -> Id {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { NSArray::count(self.as_ptr()) as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Copies the array, keeping it mutable if it was, so that clones never share contents.
+impl Clone for ArrayId {
+    fn clone(&self) -> ArrayId {
+        unsafe {
+            let copy: id = if is_kind_of_class(self.as_ptr(), "NSMutableArray") {
+                msg_send![self.as_ptr(), mutableCopy]
+            } else {
+                msg_send![self.as_ptr(), copy]
+            };
+            ArrayId(Id::from_retained(copy))
+        }
+    }
+}
+
+impl Deref for ArrayId {
+    type Target = Id;
+
+    fn deref(&self) -> &Id {
+        &self.0
+    }
+}
+
+impl FromIterator<Id> for ArrayId {
+    fn from_iter<I: IntoIterator<Item = Id>>(iter: I) -> ArrayId {
+        let iter = iter.into_iter();
+        unsafe {
+            let array: id = msg_send![class("NSMutableArray"), alloc];
+            let capacity = iter.size_hint().0 as NSUInteger;
+            let array = Id::from_retained(NSMutableArray::initWithCapacity_(array, capacity));
+            for object in iter {
+                NSMutableArray::addObject_(*array, object.as_ptr());
+            }
+            ArrayId(array)
+        }
+    }
+}
+
+impl IntoIterator for ArrayId {
+    type Item = Id;
+    type IntoIter = ArrayIntoIter;
+
+    fn into_iter(self) -> ArrayIntoIter {
+        let end = self.len();
+        ArrayIntoIter { array: self, start: 0, end }
+    }
+}
+
+impl IntoIterator for &ArrayId {
+    type Item = id;
+    type IntoIter = NSArrayIterator;
+
+    fn into_iter(self) -> NSArrayIterator {
+        unsafe { self.as_ptr().array_iter() }
+    }
+}
+
+/// Yields retained references to the elements of an `ArrayId`.
+pub struct ArrayIntoIter {
+    array: ArrayId,
+    start: usize,
+    end: usize,
+}
+
+impl Iterator for ArrayIntoIter {
+    type Item = Id;
+
+    fn next(&mut self) -> Option<Id> {
+        if self.start == self.end {
+            return None;
+        }
+        self.start += 1;
+        unsafe { NSArray::get(self.array.as_ptr(), self.start - 1).map(|object| Id::from_unretained(object)) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.start, Some(self.end - self.start))
+    }
+}
+
+impl DoubleEndedIterator for ArrayIntoIter {
+    fn next_back(&mut self) -> Option<Id> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        unsafe { NSArray::get(self.array.as_ptr(), self.end).map(|object| Id::from_unretained(object)) }
+    }
+}
+
+impl ExactSizeIterator for ArrayIntoIter {}
+
+/// Owns the objects of an `NSArray` subclass that reads them straight from a Rust vector.
+struct VecArrayStorage {
+    objects: Vec<Id>,
+    // Never changes, since the array is immutable.
+    mutations: Cell<libc::c_ulong>,
+}
+
+const VEC_ARRAY_STORAGE: &str = "rustStorage";
+
+/// Registers one of this crate's own classes the first time `register` runs, and returns it.
+///
+/// The name carries the crate version, since another copy of the crate keeps its own types in
+/// the ivars of its classes. Finding the name taken anyway panics rather than share the class.
+fn private_class<F: FnOnce(&mut ClassDecl)>(register: &Once, name: &str, superclass: &str, declare: F) -> Class {
+    let version = env!("CARGO_PKG_VERSION").replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    let name = format!("{}_{}", name, version);
+    register.call_once(|| {
+        let mut decl = ClassDecl::new(&name, unsafe { &*class(superclass) })
+            .unwrap_or_else(|| panic!("the Objective-C class {} is already registered", name));
+        declare(&mut decl);
+        decl.register();
+    });
+    class(&name)
+}
+
+fn vec_array_class() -> Class {
+    static REGISTER: Once = Once::new();
+    private_class(&REGISTER, "CocoaRsVecArray", "NSArray", |decl| unsafe {
+        decl.add_ivar::<*mut libc::c_void>(VEC_ARRAY_STORAGE);
+        decl.add_method(sel!(count), vec_array_count as extern "C" fn(&Object, Sel) -> NSUInteger);
+        decl.add_method(sel!(objectAtIndex:),
+                        vec_array_object_at_index as extern "C" fn(&Object, Sel, NSUInteger) -> id);
+        decl.add_method(sel!(countByEnumeratingWithState:objects:count:),
+                        vec_array_count_by_enumerating
+                        as extern "C" fn(&Object, Sel, *mut libc::c_void, *mut libc::c_void, NSUInteger) -> NSUInteger);
+        decl.add_method(sel!(dealloc), vec_array_dealloc as extern "C" fn(&mut Object, Sel));
+    })
+}
+
+unsafe fn vec_array_storage(this: &Object) -> &VecArrayStorage {
+    &*(*this.get_ivar::<*mut libc::c_void>(VEC_ARRAY_STORAGE) as *const VecArrayStorage)
+}
+
+extern "C" fn vec_array_count(this: &Object, _: Sel) -> NSUInteger {
+    unsafe { vec_array_storage(this).objects.len() as NSUInteger }
+}
+
+// NSArray raises NSRangeException for an index out of bounds, but an exception must not unwind
+// through this Rust frame, so this aborts instead.
+extern "C" fn vec_array_object_at_index(this: &Object, _: Sel, index: NSUInteger) -> id {
+    let objects = unsafe { &vec_array_storage(this).objects };
+    match objects.get(index as usize) {
+        Some(object) => object.as_ptr(),
+        None => {
+            eprintln!("-[NSArray objectAtIndex:]: index {} beyond bounds of an array of {}", index, objects.len());
+            process::abort()
+        }
+    }
+}
+
+extern "C" fn vec_array_count_by_enumerating(this: &Object, _: Sel, state: *mut libc::c_void, _: *mut libc::c_void,
+                                         _: NSUInteger) -> NSUInteger {
+    unsafe {
+        let storage = vec_array_storage(this);
+        // `Id` is a transparent wrapper around `id`.
+        let items = slice::from_raw_parts(storage.objects.as_ptr() as *const id, storage.objects.len());
+        fast_enumeration::enumerate_slice(&mut *(state as *mut NSFastEnumerationState<Object>), items,
+                                          storage.mutations.as_ptr()) as NSUInteger
+    }
+}
+
+extern "C" fn vec_array_dealloc(this: &mut Object, _: Sel) {
+    unsafe {
+        let storage = *this.get_ivar::<*mut libc::c_void>(VEC_ARRAY_STORAGE);
+        if !storage.is_null() {
+            drop(Box::from_raw(storage as *mut VecArrayStorage));
+        }
+        let () = msg_send![super(this, &*class("NSArray")), dealloc];
+    }
+}
+
+/// Wraps the objects in an `NSArray` subclass that reads them from the vector in place, rather
+/// than copying them as `arrayWithObjects:count:` does. The vector is dropped with the array.
+///
+/// The array is immutable, and indexing it out of bounds aborts the process.
+impl From<Vec<Id>> for ArrayId {
+    fn from(objects: Vec<Id>) -> ArrayId {
+        unsafe {
+            let array: id = msg_send![vec_array_class(), alloc];
+            let storage = Box::new(VecArrayStorage { objects, mutations: Cell::new(0) });
+            (*array).set_ivar(VEC_ARRAY_STORAGE, Box::into_raw(storage) as *mut libc::c_void);
+            let array: id = msg_send![array, init];
+            ArrayId(Id::from_retained(array))
+        }
+    }
+}
+
+pub trait NSDictionary: Sized {
+    unsafe fn dictionary(_: Self) -> id {
+        msg_send![class("NSDictionary"), dictionary]
+    }
+
+    unsafe fn dictionaryWithContentsOfFile_(_: Self, path: id) -> id {
+        msg_send![class("NSDictionary"), dictionaryWithContentsOfFile:path]
+    }
+
+    unsafe fn dictionaryWithContentsOfURL_(_: Self, aURL: id) -> id {
+        msg_send![class("NSDictionary"), dictionaryWithContentsOfURL:aURL]
+    }
+
+    unsafe fn dictionaryWithDictionary_(_: Self, otherDictionary: id) -> id {
+        msg_send![class("NSDictionary"), dictionaryWithDictionary:otherDictionary]
+    }
+
+    unsafe fn dictionaryWithObject_forKey_(_: Self, anObject: id, aKey: id) -> id {
+        msg_send![class("NSDictionary"), dictionaryWithObject:anObject forKey:aKey]
+    }
+
+    unsafe fn dictionaryWithObjects_forKeys_(_: Self, objects: id, keys: id) -> id {
+        msg_send![class("NSDictionary"), dictionaryWithObjects:objects forKeys:keys]
+    }
+
+    unsafe fn dictionaryWithObjects_forKeys_count_(_: Self, objects: *const id, keys: *const id, count: NSUInteger) -> id {
+        msg_send![class("NSDictionary"), dictionaryWithObjects:objects forKeys:keys count:count]
+    }
+
+    unsafe fn dictionaryWithObjectsAndKeys_(_: Self, firstObject: id) -> id {
+        msg_send![class("NSDictionary"), dictionaryWithObjectsAndKeys:firstObject]
+    }
+
+    unsafe fn init(self) -> id;
+    unsafe fn initWithContentsOfFile_(self, path: id) -> id;
+    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id;
+    unsafe fn initWithDictionary_(self, otherDicitonary: id) -> id;
+    unsafe fn initWithDictionary_copyItems_(self, otherDicitonary: id, flag: BOOL) -> id;
+    unsafe fn initWithObjects_forKeys_(self, objects: id, keys: id) -> id;
+    unsafe fn initWithObjects_forKeys_count_(self, objects: id, keys: id, count: NSUInteger) -> id;
+    unsafe fn initWithObjectsAndKeys_(self, firstObject: id) -> id;
+
+    unsafe fn sharedKeySetForKeys_(_: Self, keys: id) -> id {
+        msg_send![class("NSDictionary"), sharedKeySetForKeys:keys]
+    }
+
+    unsafe fn count(self) -> NSUInteger;
+
+    unsafe fn isEqualToDictionary_(self, otherDictionary: id) -> BOOL;
+
+    unsafe fn allKeys(self) -> id;
+    unsafe fn allKeysForObject_(self, anObject: id) -> id;
+    unsafe fn allValues(self) -> id;
+    unsafe fn objectForKey_(self, aKey: id) -> id;
+    unsafe fn objectForKeyedSubscript_(self, key: id) -> id;
+    unsafe fn objectsForKeys_notFoundMarker_(self, keys: id, anObject: id) -> id;
+    unsafe fn valueForKey_(self, key: id) -> id;
+
+    unsafe fn keyEnumerator(self) -> id;
+    unsafe fn objectEnumerator(self) -> id;
+    unsafe fn enumerateKeysAndObjectsUsingBlock_(self, block: *mut Block<(id, id, *mut BOOL), ()>);
+    unsafe fn enumerateKeysAndObjectsWithOptions_usingBlock_(self, opts: NSEnumerationOptions,
+                                                             block: *mut Block<(id, id, *mut BOOL), ()>);
+
+    unsafe fn keysSortedByValueUsingSelector_(self, comparator: SEL) -> id;
+    unsafe fn keysSortedByValueUsingComparator_(self, cmptr: NSComparator) -> id;
+    unsafe fn keysSortedByValueWithOptions_usingComparator_(self, opts: NSEnumerationOptions, cmptr: NSComparator) -> id;
+
+    unsafe fn keysOfEntriesPassingTest_(self, predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id;
+    unsafe fn keysOfEntriesWithOptions_PassingTest_(self, opts: NSEnumerationOptions,
+                                                    predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id;
+
+    unsafe fn writeToFile_atomically_(self, path: id, flag: BOOL) -> BOOL;
+    unsafe fn writeToURL_atomically_(self, aURL: id, flag: BOOL) -> BOOL;
+
+    unsafe fn fileCreationDate(self) -> id;
+    unsafe fn fileExtensionHidden(self) -> BOOL;
+    unsafe fn fileGroupOwnerAccountID(self) -> id;
+    unsafe fn fileGroupOwnerAccountName(self) -> id;
+    unsafe fn fileIsAppendOnly(self) -> BOOL;
+    unsafe fn fileIsImmutable(self) -> BOOL;
+    unsafe fn fileModificationDate(self) -> id;
+    unsafe fn fileOwnerAccountID(self) -> id;
+    unsafe fn fileOwnerAccountName(self) -> id;
+    unsafe fn filePosixPermissions(self) -> NSUInteger;
+    unsafe fn fileSize(self) -> libc::c_ulonglong;
+    unsafe fn fileSystemFileNumber(self) -> NSUInteger;
+    unsafe fn fileSystemNumber(self) -> NSInteger;
+    unsafe fn fileType(self) -> id;
+
+    unsafe fn description(self) -> id;
+    unsafe fn descriptionInStringsFileFormat(self) -> id;
+    unsafe fn descriptionWithLocale_(self, locale: id) -> id;
+    unsafe fn descriptionWithLocale_indent_(self, locale: id, indent: NSUInteger) -> id;
+
+    /// Iterates over key/object pairs. The dictionary must not be mutated meanwhile.
+    unsafe fn pairs(self) -> NSDictionaryPairs;
+}
+
+impl NSDictionary for id {
+    unsafe fn init(self) -> id {
+        msg_send![self, init]
+    }
+
+    unsafe fn initWithContentsOfFile_(self, path: id) -> id {
+        msg_send![self, initWithContentsOfFile:path]
+    }
+
+    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id {
+        msg_send![self, initWithContentsOfURL:aURL]
+    }
+
+    unsafe fn initWithDictionary_(self, otherDictionary: id) -> id {
+        msg_send![self, initWithDictionary:otherDictionary]
+    }
+
+    unsafe fn initWithDictionary_copyItems_(self, otherDictionary: id, flag: BOOL) -> id {
+        msg_send![self, initWithDictionary:otherDictionary copyItems:flag]
+    }
+
+    unsafe fn initWithObjects_forKeys_(self, objects: id, keys: id) -> id {
+        msg_send![self, initWithObjects:objects forKeys:keys]
+    }
+
+    unsafe fn initWithObjects_forKeys_count_(self, objects: id, keys: id, count: NSUInteger) -> id {
+        msg_send![self, initWithObjects:objects forKeys:keys count:count]
+    }
+
+    unsafe fn initWithObjectsAndKeys_(self, firstObject: id) -> id {
+        msg_send![self, initWithObjectsAndKeys:firstObject]
+    }
+
+    unsafe fn count(self) -> NSUInteger {
+        msg_send![self, count]
+    }
+
+    unsafe fn isEqualToDictionary_(self, otherDictionary: id) -> BOOL {
+        msg_send![self, isEqualToDictionary:otherDictionary]
+    }
+
+    unsafe fn allKeys(self) -> id {
+        msg_send![self, allKeys]
+    }
+
+    unsafe fn allKeysForObject_(self, anObject: id) -> id {
+        msg_send![self, allKeysForObject:anObject]
+    }
+
+    unsafe fn allValues(self) -> id {
+        msg_send![self, allValues]
+    }
+
+    unsafe fn objectForKey_(self, aKey: id) -> id {
+        msg_send![self, objectForKey:aKey]
+    }
+
+    unsafe fn objectForKeyedSubscript_(self, key: id) -> id {
+        msg_send![self, objectForKeyedSubscript:key]
+    }
+
+    unsafe fn objectsForKeys_notFoundMarker_(self, keys: id, anObject: id) -> id {
+        msg_send![self, objectsForKeys:keys notFoundMarker:anObject]
+    }
+
+    unsafe fn valueForKey_(self, key: id) -> id {
+        msg_send![self, valueForKey:key]
+    }
+
+    unsafe fn keyEnumerator(self) -> id {
+        msg_send![self, keyEnumerator]
+    }
+
+    unsafe fn objectEnumerator(self) -> id {
+        msg_send![self, objectEnumerator]
+    }
+
+    unsafe fn enumerateKeysAndObjectsUsingBlock_(self, block: *mut Block<(id, id, *mut BOOL), ()>) {
+        msg_send![self, enumerateKeysAndObjectsUsingBlock:block]
+    }
+
+    unsafe fn enumerateKeysAndObjectsWithOptions_usingBlock_(self, opts: NSEnumerationOptions,
+                                                     block: *mut Block<(id, id, *mut BOOL), ()>) {
+        msg_send![self, enumerateKeysAndObjectsWithOptions:opts usingBlock:block]
+    }
+
+    unsafe fn keysSortedByValueUsingSelector_(self, comparator: SEL) -> id {
+        msg_send![self, keysSortedByValueUsingSelector:comparator]
+    }
+
+    unsafe fn keysSortedByValueUsingComparator_(self, cmptr: NSComparator) -> id {
+        msg_send![self, keysSortedByValueUsingComparator:cmptr]
+    }
+
+    unsafe fn keysSortedByValueWithOptions_usingComparator_(self, opts: NSEnumerationOptions, cmptr: NSComparator) -> id {
+        let rv: id = msg_send![self, keysSortedByValueWithOptions:opts usingComparator:cmptr];
+        rv
+    }
+
+    unsafe fn keysOfEntriesPassingTest_(self, predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id {
+        msg_send![self, keysOfEntriesPassingTest:predicate]
+    }
+
+    unsafe fn keysOfEntriesWithOptions_PassingTest_(self, opts: NSEnumerationOptions,
+                                                    predicate: *mut Block<(id, id, *mut BOOL), BOOL>) -> id {
+        msg_send![self, keysOfEntriesWithOptions:opts PassingTest:predicate]
+    }
+
+    unsafe fn writeToFile_atomically_(self, path: id, flag: BOOL) -> BOOL {
+        msg_send![self, writeToFile:path atomically:flag]
+    }
+
+    unsafe fn writeToURL_atomically_(self, aURL: id, flag: BOOL) -> BOOL {
+        msg_send![self, writeToURL:aURL atomically:flag]
+    }
+
+    unsafe fn fileCreationDate(self) -> id {
+        msg_send![self, fileCreationDate]
+    }
+
+    unsafe fn fileExtensionHidden(self) -> BOOL {
+        msg_send![self, fileExtensionHidden]
+    }
+
+    unsafe fn fileGroupOwnerAccountID(self) -> id {
+        msg_send![self, fileGroupOwnerAccountID]
+    }
+
+    unsafe fn fileGroupOwnerAccountName(self) -> id {
+        msg_send![self, fileGroupOwnerAccountName]
+    }
+
+    unsafe fn fileIsAppendOnly(self) -> BOOL {
+        msg_send![self, fileIsAppendOnly]
+    }
+
+    unsafe fn fileIsImmutable(self) -> BOOL {
+        msg_send![self, fileIsImmutable]
+    }
+
+    unsafe fn fileModificationDate(self) -> id {
+        msg_send![self, fileModificationDate]
+    }
+
+    unsafe fn fileOwnerAccountID(self) -> id {
+        msg_send![self, fileOwnerAccountID]
+    }
+
+    unsafe fn fileOwnerAccountName(self) -> id {
+        msg_send![self, fileOwnerAccountName]
+    }
+
+    unsafe fn filePosixPermissions(self) -> NSUInteger {
+        msg_send![self, filePosixPermissions]
+    }
+
+    unsafe fn fileSize(self) -> libc::c_ulonglong {
+        msg_send![self, fileSize]
+    }
+
+    unsafe fn fileSystemFileNumber(self) -> NSUInteger {
+        msg_send![self, fileSystemFileNumber]
+    }
+
+    unsafe fn fileSystemNumber(self) -> NSInteger {
+        msg_send![self, fileSystemNumber]
+    }
+
+    unsafe fn fileType(self) -> id {
+        msg_send![self, fileType]
+    }
+
+    unsafe fn description(self) -> id {
+        msg_send![self, description]
+    }
+
+    unsafe fn descriptionInStringsFileFormat(self) -> id {
+        msg_send![self, descriptionInStringsFileFormat]
+    }
+
+    unsafe fn descriptionWithLocale_(self, locale: id) -> id {
+        msg_send![self, descriptionWithLocale:locale]
+    }
+
+    unsafe fn descriptionWithLocale_indent_(self, locale: id, indent: NSUInteger) -> id {
+        msg_send![self, descriptionWithLocale:locale indent:indent]
+    }
+
+    unsafe fn pairs(self) -> NSDictionaryPairs {
+        NSDictionaryPairs { keys: self.iter(), dictionary: self }
+    }
+}
+
+/// Fast-enumerates the keys of a dictionary, looking up the object for each.
+pub struct NSDictionaryPairs {
+    keys: NSFastIterator,
+    dictionary: id,
+}
+
+impl Iterator for NSDictionaryPairs {
+    type Item = (id, id);
+
+    fn next(&mut self) -> Option<(id, id)> {
+        let key = self.keys.next()?;
+        Some((key, unsafe { self.dictionary.objectForKey_(key) }))
+    }
+}
+
+pub trait NSMutableDictionary: NSDictionary {
+    unsafe fn dictionaryWithCapacity_(_: Self, numItems: NSUInteger) -> id {
+        msg_send![class("NSMutableDictionary"), dictionaryWithCapacity:numItems]
+    }
+
+    unsafe fn initWithCapacity_(self, numItems: NSUInteger) -> id;
+
+    unsafe fn setObject_forKey_(self, anObject: id, aKey: id);
+    unsafe fn removeObjectForKey_(self, aKey: id);
+    unsafe fn removeObjectsForKeys_(self, keyArray: id);
+    unsafe fn removeAllObjects(self);
+    unsafe fn addEntriesFromDictionary_(self, otherDictionary: id);
+    unsafe fn setDictionary_(self, otherDictionary: id);
+}
+
+impl NSMutableDictionary for id {
+    unsafe fn initWithCapacity_(self, numItems: NSUInteger) -> id {
+        msg_send![self, initWithCapacity:numItems]
+    }
+
+    unsafe fn setObject_forKey_(self, anObject: id, aKey: id) {
+        msg_send![self, setObject:anObject forKey:aKey]
+    }
+
+    unsafe fn removeObjectForKey_(self, aKey: id) {
+        msg_send![self, removeObjectForKey:aKey]
+    }
+
+    unsafe fn removeObjectsForKeys_(self, keyArray: id) {
+        msg_send![self, removeObjectsForKeys:keyArray]
+    }
+
+    unsafe fn removeAllObjects(self) {
+        msg_send![self, removeAllObjects]
+    }
+
+    unsafe fn addEntriesFromDictionary_(self, otherDictionary: id) {
+        msg_send![self, addEntriesFromDictionary:otherDictionary]
+    }
+
+    unsafe fn setDictionary_(self, otherDictionary: id) {
+        msg_send![self, setDictionary:otherDictionary]
+    }
+}
+
+/// Selectors that `NSArray` also answers, such as `count` and `containsObject:`, are bound there
+/// only, so that calls on `id` stay unambiguous. `member_count`, `has_member` and `members` send
+/// them to the set directly.
+pub trait NSSet: Sized {
+    unsafe fn set(_: Self) -> id {
+        msg_send![class("NSSet"), set]
+    }
+
+    unsafe fn setWithObjects(_: Self, objects: &[id]) -> id {
+        msg_send![class("NSSet"), setWithObjects:objects.as_ptr()
+                                  count:objects.len()]
+    }
+
+    unsafe fn setWithArray_(_: Self, array: id) -> id {
+        msg_send![class("NSSet"), setWithArray:array]
+    }
+
+    unsafe fn setWithSet_(_: Self, set: id) -> id {
+        msg_send![class("NSSet"), setWithSet:set]
+    }
+
+    unsafe fn anyObject(self) -> id;
+    unsafe fn allObjects(self) -> id;
+    unsafe fn member_(self, object: id) -> id;
+
+    unsafe fn isSubsetOfSet_(self, otherSet: id) -> BOOL;
+    unsafe fn intersectsSet_(self, otherSet: id) -> BOOL;
+    unsafe fn isEqualToSet_(self, otherSet: id) -> BOOL;
+
+    unsafe fn setByAddingObject_(self, anObject: id) -> id;
+    unsafe fn setByAddingObjectsFromSet_(self, other: id) -> id;
+    unsafe fn setByAddingObjectsFromArray_(self, other: id) -> id;
+
+    unsafe fn member_count(self) -> usize;
+    /// Tests membership with `isEqual:` and `hash`.
+    unsafe fn has_member(self, object: id) -> bool;
+    /// Fast-enumerates the members, in no particular order.
+    unsafe fn members(self) -> Vec<id>;
+}
+
+impl NSSet for id {
+    unsafe fn anyObject(self) -> id {
+        msg_send![self, anyObject]
+    }
+
+    unsafe fn allObjects(self) -> id {
+        msg_send![self, allObjects]
+    }
+
+    unsafe fn member_(self, object: id) -> id {
+        msg_send![self, member:object]
+    }
+
+    unsafe fn isSubsetOfSet_(self, otherSet: id) -> BOOL {
+        msg_send![self, isSubsetOfSet:otherSet]
+    }
+
+    unsafe fn intersectsSet_(self, otherSet: id) -> BOOL {
+        msg_send![self, intersectsSet:otherSet]
+    }
+
+    unsafe fn isEqualToSet_(self, otherSet: id) -> BOOL {
+        msg_send![self, isEqualToSet:otherSet]
+    }
+
+    unsafe fn setByAddingObject_(self, anObject: id) -> id {
+        msg_send![self, setByAddingObject:anObject]
+    }
+
+    unsafe fn setByAddingObjectsFromSet_(self, other: id) -> id {
+        msg_send![self, setByAddingObjectsFromSet:other]
+    }
+
+    unsafe fn setByAddingObjectsFromArray_(self, other: id) -> id {
+        msg_send![self, setByAddingObjectsFromArray:other]
+    }
+
+    unsafe fn member_count(self) -> usize {
+        let count: NSUInteger = msg_send![self, count];
+        count as usize
+    }
+
+    unsafe fn has_member(self, object: id) -> bool {
+        !self.member_(object).is_null()
+    }
+
+    unsafe fn members(self) -> Vec<id> {
+        let mut members = Vec::with_capacity(self.member_count());
+        members.extend(self.iter());
+        members
+    }
+}
+
+/// As with `NSSet`, the selectors `NSMutableArray` shares are bound there, and sent here by
+/// `add_member`, `remove_member` and `remove_all_members`.
+pub trait NSMutableSet: NSSet {
+    unsafe fn setWithCapacity_(_: Self, numItems: NSUInteger) -> id {
+        msg_send![class("NSMutableSet"), setWithCapacity:numItems]
+    }
+
+    unsafe fn addObjectsFromArray_(self, array: id);
+    unsafe fn unionSet_(self, otherSet: id);
+    unsafe fn intersectSet_(self, otherSet: id);
+    unsafe fn minusSet_(self, otherSet: id);
+    unsafe fn setSet_(self, otherSet: id);
+
+    unsafe fn add_member(self, object: id);
+    unsafe fn remove_member(self, object: id);
+    unsafe fn remove_all_members(self);
+}
+
+impl NSMutableSet for id {
+    unsafe fn addObjectsFromArray_(self, array: id) {
+        msg_send![self, addObjectsFromArray:array]
+    }
+
+    unsafe fn unionSet_(self, otherSet: id) {
+        msg_send![self, unionSet:otherSet]
+    }
+
+    unsafe fn intersectSet_(self, otherSet: id) {
+        msg_send![self, intersectSet:otherSet]
+    }
+
+    unsafe fn minusSet_(self, otherSet: id) {
+        msg_send![self, minusSet:otherSet]
+    }
+
+    unsafe fn setSet_(self, otherSet: id) {
+        msg_send![self, setSet:otherSet]
+    }
+
+    unsafe fn add_member(self, object: id) {
+        msg_send![self, addObject:object]
+    }
+
+    unsafe fn remove_member(self, object: id) {
+        msg_send![self, removeObject:object]
+    }
+
+    unsafe fn remove_all_members(self) {
+        msg_send![self, removeAllObjects]
+    }
+}
+
+pub trait NSCountedSet: NSMutableSet {
+    unsafe fn alloc(_: Self) -> id {
+        msg_send![class("NSCountedSet"), alloc]
+    }
+
+    unsafe fn initWithArray_(self, array: id) -> id;
+    unsafe fn initWithSet_(self, set: id) -> id;
+
+    unsafe fn countForObject_(self, object: id) -> NSUInteger;
+}
+
+impl NSCountedSet for id {
+    unsafe fn initWithArray_(self, array: id) -> id {
+        msg_send![self, initWithArray:array]
+    }
+
+    unsafe fn initWithSet_(self, set: id) -> id {
+        msg_send![self, initWithSet:set]
+    }
+
+    unsafe fn countForObject_(self, object: id) -> NSUInteger {
+        msg_send![self, countForObject:object]
+    }
+}
+
+/// `count`, `objectAtIndex:` and the other selectors `NSArray` also answers are bound there; the
+/// `ordered_` helpers send them to the ordered set directly.
+pub trait NSOrderedSet: Sized {
+    unsafe fn orderedSet(_: Self) -> id {
+        msg_send![class("NSOrderedSet"), orderedSet]
+    }
+
+    unsafe fn orderedSetWithObjects(_: Self, objects: &[id]) -> id {
+        msg_send![class("NSOrderedSet"), orderedSetWithObjects:objects.as_ptr()
+                                         count:objects.len()]
+    }
+
+    unsafe fn orderedSetWithArray_(_: Self, array: id) -> id {
+        msg_send![class("NSOrderedSet"), orderedSetWithArray:array]
+    }
+
+    unsafe fn orderedSetWithSet_(_: Self, set: id) -> id {
+        msg_send![class("NSOrderedSet"), orderedSetWithSet:set]
+    }
+
+    unsafe fn isEqualToOrderedSet_(self, other: id) -> BOOL;
+    unsafe fn intersectsOrderedSet_(self, other: id) -> BOOL;
+    unsafe fn isSubsetOfOrderedSet_(self, other: id) -> BOOL;
+
+    unsafe fn array(self) -> id;
+    unsafe fn set(self) -> id;
+    unsafe fn reversedOrderedSet(self) -> id;
+
+    unsafe fn ordered_count(self) -> usize;
+    /// Returns the object at `index`, or `None` where `objectAtIndex:` would raise.
+    unsafe fn ordered_get(self, index: usize) -> Option<id>;
+    unsafe fn ordered_contains(self, object: id) -> bool;
+    unsafe fn ordered_index_of(self, object: id) -> Option<usize>;
+    /// Fast-enumerates the members in order.
+    unsafe fn ordered_members(self) -> Vec<id>;
+}
+
+impl NSOrderedSet for id {
+    unsafe fn isEqualToOrderedSet_(self, other: id) -> BOOL {
+        msg_send![self, isEqualToOrderedSet:other]
+    }
+
+    unsafe fn intersectsOrderedSet_(self, other: id) -> BOOL {
+        msg_send![self, intersectsOrderedSet:other]
+    }
+
+    unsafe fn isSubsetOfOrderedSet_(self, other: id) -> BOOL {
+        msg_send![self, isSubsetOfOrderedSet:other]
+    }
+
+    unsafe fn array(self) -> id {
+        msg_send![self, array]
+    }
+
+    unsafe fn set(self) -> id {
+        msg_send![self, set]
+    }
+
+    unsafe fn reversedOrderedSet(self) -> id {
+        msg_send![self, reversedOrderedSet]
+    }
+
+    unsafe fn ordered_count(self) -> usize {
+        let count: NSUInteger = msg_send![self, count];
+        count as usize
+    }
+
+    unsafe fn ordered_get(self, index: usize) -> Option<id> {
+        if index < self.ordered_count() {
+            Some(msg_send![self, objectAtIndex:index as NSUInteger])
+        } else {
+            None
+        }
+    }
+
+    unsafe fn ordered_contains(self, object: id) -> bool {
+        let contains: BOOL = msg_send![self, containsObject:object];
+        contains != NO
+    }
+
+    unsafe fn ordered_index_of(self, object: id) -> Option<usize> {
+        let index: NSUInteger = msg_send![self, indexOfObject:object];
+        if index == NSNotFound as NSUInteger { None } else { Some(index as usize) }
+    }
+
+    unsafe fn ordered_members(self) -> Vec<id> {
+        let mut members = Vec::with_capacity(self.ordered_count());
+        members.extend(self.iter());
+        members
+    }
+}
+
+/// Rust values with a Foundation counterpart. Strings become `NSString`s, numbers and booleans
+/// `NSNumber`s, sequences `NSArray`s, sets `NSSet`s and maps `NSDictionary`s.
+pub trait ToCocoa {
+    unsafe fn to_cocoa(&self) -> Id;
+}
+
+/// The reverse of `ToCocoa`. Returns `None` if `object` is nil, of the wrong class, or a number
+/// that does not fit.
+pub trait FromCocoa: Sized {
+    unsafe fn from_cocoa(object: id) -> Option<Self>;
+}
+
+pub(crate) unsafe fn is_kind_of_class(object: id, name: &str) -> bool {
+    let result: BOOL = msg_send![object, isKindOfClass:class(name)];
+    result != NO
+}
+
+impl<T: ToCocoa + ?Sized> ToCocoa for &T {
+    unsafe fn to_cocoa(&self) -> Id {
+        (**self).to_cocoa()
+    }
+}
+
+impl ToCocoa for Id {
+    unsafe fn to_cocoa(&self) -> Id {
+        self.clone()
+    }
+}
+
+impl FromCocoa for Id {
+    unsafe fn from_cocoa(object: id) -> Option<Id> {
+        non_nil(object).map(|object| Id::from_unretained(object))
+    }
+}
+
+impl ToCocoa for str {
+    unsafe fn to_cocoa(&self) -> Id {
+        Id::from_retained(NSString::alloc(nil).init_str(self))
+    }
+}
+
+impl ToCocoa for String {
+    unsafe fn to_cocoa(&self) -> Id {
+        self.as_str().to_cocoa()
+    }
+}
+
+impl FromCocoa for String {
+    unsafe fn from_cocoa(object: id) -> Option<String> {
+        if !is_kind_of_class(object, "NSString") {
+            return None;
+        }
+        // UTF8String is NULL for strings holding unpaired surrogates.
+        let bytes = object.UTF8String() as *const u8;
+        if bytes.is_null() {
+            return None;
+        }
+        String::from_utf8(slice::from_raw_parts(bytes, object.len()).to_vec()).ok()
+    }
+}
+
+macro_rules! number_cocoa_conversions {
+    ($($ty:ident),*) => {
+        $(
+            impl ToCocoa for $ty {
+                unsafe fn to_cocoa(&self) -> Id {
+                    Id::from_unretained(NSNumberValue::from(*self).into_number())
+                }
+            }
+
+            impl FromCocoa for $ty {
+                unsafe fn from_cocoa(object: id) -> Option<$ty> {
+                    if is_kind_of_class(object, "NSNumber") {
+                        $ty::try_from(object.value()).ok()
+                    } else {
+                        None
+                    }
+                }
+            }
+        )*
+    }
+}
+
+number_cocoa_conversions!(bool, i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+impl<T: ToCocoa> ToCocoa for [T] {
+    unsafe fn to_cocoa(&self) -> Id {
+        self.iter().map(|item| item.to_cocoa()).collect::<ArrayId>().into_id()
+    }
+}
+
+impl<T: ToCocoa> ToCocoa for Vec<T> {
+    unsafe fn to_cocoa(&self) -> Id {
+        self[..].to_cocoa()
+    }
+}
+
+/// Accepts an `NSArray` or an `NSOrderedSet`.
+impl<T: FromCocoa> FromCocoa for Vec<T> {
+    unsafe fn from_cocoa(object: id) -> Option<Vec<T>> {
+        if !is_kind_of_class(object, "NSArray") && !is_kind_of_class(object, "NSOrderedSet") {
+            return None;
+        }
+        object.iter().map(|item| T::from_cocoa(item)).collect()
+    }
+}
+
+unsafe fn dictionary_from_pairs<'a, K, V, I>(len: usize, pairs: I) -> Id
+    where K: ToCocoa + 'a, V: ToCocoa + 'a, I: Iterator<Item = (&'a K, &'a V)>
+{
+    let dictionary: id = msg_send![class("NSMutableDictionary"), alloc];
+    let dictionary = Id::from_retained(NSMutableDictionary::initWithCapacity_(dictionary, len as NSUInteger));
+    for (key, value) in pairs {
+        dictionary.setObject_forKey_(*value.to_cocoa(), *key.to_cocoa());
+    }
+    dictionary
+}
+
+unsafe fn pairs_from_dictionary<K, V, C>(object: id) -> Option<C>
+    where K: FromCocoa, V: FromCocoa, C: FromIterator<(K, V)>
+{
+    if !is_kind_of_class(object, "NSDictionary") {
+        return None;
+    }
+    object.pairs().map(|(key, value)| Some((K::from_cocoa(key)?, V::from_cocoa(value)?))).collect()
+}
+
+impl<K: ToCocoa, V: ToCocoa, S: BuildHasher> ToCocoa for HashMap<K, V, S> {
+    unsafe fn to_cocoa(&self) -> Id {
+        dictionary_from_pairs(self.len(), self.iter())
+    }
+}
+
+impl<K, V, S> FromCocoa for HashMap<K, V, S>
+    where K: FromCocoa + Eq + Hash, V: FromCocoa, S: BuildHasher + Default
+{
+    unsafe fn from_cocoa(object: id) -> Option<HashMap<K, V, S>> {
+        pairs_from_dictionary(object)
+    }
+}
+
+impl<K: ToCocoa, V: ToCocoa> ToCocoa for BTreeMap<K, V> {
+    unsafe fn to_cocoa(&self) -> Id {
+        dictionary_from_pairs(self.len(), self.iter())
+    }
+}
+
+impl<K: FromCocoa + Ord, V: FromCocoa> FromCocoa for BTreeMap<K, V> {
+    unsafe fn from_cocoa(object: id) -> Option<BTreeMap<K, V>> {
+        pairs_from_dictionary(object)
+    }
+}
+
+unsafe fn set_from_items<T: ToCocoa, I: Iterator<Item = T>>(len: usize, items: I) -> Id {
+    let set = Id::from_unretained(NSMutableSet::setWithCapacity_(nil, len as NSUInteger));
+    for item in items {
+        set.add_member(*item.to_cocoa());
+    }
+    set
+}
+
+unsafe fn items_from_set<T: FromCocoa, C: FromIterator<T>>(object: id) -> Option<C> {
+    if !is_kind_of_class(object, "NSSet") {
+        return None;
+    }
+    object.members().into_iter().map(|item| T::from_cocoa(item)).collect()
+}
+
+impl<T: ToCocoa, S: BuildHasher> ToCocoa for HashSet<T, S> {
+    unsafe fn to_cocoa(&self) -> Id {
+        set_from_items(self.len(), self.iter())
+    }
+}
+
+impl<T: FromCocoa + Eq + Hash, S: BuildHasher + Default> FromCocoa for HashSet<T, S> {
+    unsafe fn from_cocoa(object: id) -> Option<HashSet<T, S>> {
+        items_from_set(object)
+    }
+}
+
+impl<T: ToCocoa> ToCocoa for BTreeSet<T> {
+    unsafe fn to_cocoa(&self) -> Id {
+        set_from_items(self.len(), self.iter())
+    }
+}
+
+impl<T: FromCocoa + Ord> FromCocoa for BTreeSet<T> {
+    unsafe fn from_cocoa(object: id) -> Option<BTreeSet<T>> {
+        items_from_set(object)
+    }
+}
+
+/// Property list objects. UIDs are `{"CF$UID": n}` dictionaries, as in XML property lists.
+impl ToCocoa for plist::Value {
+    unsafe fn to_cocoa(&self) -> Id {
+        match *self {
+            plist::Value::String(ref s) => s.to_cocoa(),
+            plist::Value::Integer(i) => i.to_cocoa(),
+            plist::Value::Unsigned(u) => u.to_cocoa(),
+            plist::Value::Real(f) => f.to_cocoa(),
+            plist::Value::Boolean(b) => b.to_cocoa(),
+            plist::Value::Date(interval) => {
+                Id::from_unretained(NSDate::dateWithTimeIntervalSinceReferenceDate_(nil, interval))
+            }
+            plist::Value::Data(ref bytes) => {
+                let data = NSData::dataWithBytes_length_(nil, bytes.as_ptr() as *const libc::c_void,
+                                                         bytes.len() as NSUInteger);
+                Id::from_unretained(data)
+            }
+            plist::Value::Array(ref items) => items.to_cocoa(),
+            plist::Value::Dictionary(ref entries) => entries.to_cocoa(),
+            plist::Value::Uid(uid) => dictionary_from_pairs(1, Some((&"CF$UID", &uid)).into_iter()),
+        }
+    }
+}
+
+impl FromCocoa for plist::Value {
+    unsafe fn from_cocoa(object: id) -> Option<plist::Value> {
+        property_list_value(object).ok()
+    }
+}
+
+/// Converts a graph of property list objects, failing with the key path of the first object
+/// that is not one.
+unsafe fn property_list_value(object: id) -> Result<plist::Value, plist::Error> {
+    if is_kind_of_class(object, "NSString") {
+        String::from_cocoa(object).map(plist::Value::String)
+                                  .ok_or_else(|| plist::Error::Invalid("string is not valid Unicode".to_owned()))
+    } else if is_kind_of_class(object, "NSNumber") {
+        Ok(match object.value() {
+            NSNumberValue::Bool(b) => plist::Value::Boolean(b),
+            NSNumberValue::F32(f) => plist::Value::Real(f as f64),
+            NSNumberValue::F64(f) => plist::Value::Real(f),
+            NSNumberValue::U64(u) if u > i64::MAX as u64 => plist::Value::Unsigned(u),
+            value => plist::Value::Integer(i64::try_from(value).map_err(|_| {
+                plist::Error::Invalid("number out of range".to_owned())
+            })?),
+        })
+    } else if is_kind_of_class(object, "NSDate") {
+        Ok(plist::Value::Date(object.timeIntervalSinceReferenceDate()))
+    } else if is_kind_of_class(object, "NSData") {
+        Ok(plist::Value::Data(object.to_vec()))
+    } else if is_kind_of_class(object, "NSArray") {
+        object.iter()
+              .enumerate()
+              .map(|(index, item)| property_list_value(item).map_err(|e| e.at(plist::PathSegment::Index(index))))
+              .collect::<Result<_, _>>()
+              .map(plist::Value::Array)
+    } else if is_kind_of_class(object, "NSDictionary") {
+        let mut entries = BTreeMap::new();
+        for (key, value) in object.pairs() {
+            let key = String::from_cocoa(key).ok_or(plist::Error::Unsupported("non-string dictionary key"))?;
+            let value = property_list_value(value).map_err(|e| e.at(plist::PathSegment::Key(key.clone())))?;
+            entries.insert(key, value);
+        }
+        Ok(match entries.get("CF$UID") {
+            Some(&plist::Value::Integer(uid)) if entries.len() == 1 && uid >= 0 => plist::Value::Uid(uid as u64),
+            _ => plist::Value::Dictionary(entries),
+        })
+    } else if object.is_null() {
+        Err(plist::Error::Invalid("nil is not a property list object".to_owned()))
+    } else {
+        let name = String::from_cocoa(msg_send![object, className]).unwrap_or_default();
+        Err(plist::Error::Invalid(format!("{} is not a property list object", name)))
+    }
+}
+
+/// Serializes `value` with `plist::serde`, to a graph of `NSDictionary`, `NSArray`, `NSString`,
+/// `NSNumber`, `NSData` and `NSDate` objects.
+#[cfg(feature = "serde")]
+pub unsafe fn to_object<T: Serialize + ?Sized>(value: &T) -> Result<Id, plist::Error> {
+    Ok(plist::serde::to_value(value)?.to_cocoa())
+}
+
+/// Deserializes a graph of property list objects with `plist::serde`. Errors, including objects
+/// that are not property list objects, carry the key path to where they happened.
+#[cfg(feature = "serde")]
+pub unsafe fn from_object<T: DeserializeOwned>(object: id) -> Result<T, plist::Error> {
+    plist::serde::from_value(property_list_value(object)?)
+}
+
+pub trait NSNull: Sized {
+    unsafe fn null(_: Self) -> id {
+        msg_send![class("NSNull"), null]
+    }
+}
+
+impl NSNull for id {
+}
+
+bitflags! {
+    pub struct NSJSONReadingOptions: libc::c_ulonglong {
+        const NSJSONReadingMutableContainers = 1 << 0;
+        const NSJSONReadingMutableLeaves = 1 << 1;
+        const NSJSONReadingFragmentsAllowed = 1 << 2;
+        const NSJSONReadingJSON5Allowed = 1 << 3;
+        const NSJSONReadingTopLevelDictionaryAssumed = 1 << 4;
+    }
+}
+
+bitflags! {
+    pub struct NSJSONWritingOptions: libc::c_ulonglong {
+        const NSJSONWritingPrettyPrinted = 1 << 0;
+        const NSJSONWritingSortedKeys = 1 << 1;
+        const NSJSONWritingFragmentsAllowed = 1 << 2;
+        const NSJSONWritingWithoutEscapingSlashes = 1 << 3;
+    }
+}
+
+pub trait NSJSONSerialization: Sized {
+    /// Whether `obj` is an array or dictionary of strings, numbers, `NSNull`s, arrays and
+    /// dictionaries with string keys, with no NaN or infinite numbers.
+    unsafe fn isValidJSONObject_(_: Self, obj: id) -> BOOL {
+        msg_send![class("NSJSONSerialization"), isValidJSONObject:obj]
+    }
+
+    unsafe fn JSONObjectWithData_options_error_(_: Self, data: id, opt: NSJSONReadingOptions,
+                                                error: *mut id) -> id {
+        msg_send![class("NSJSONSerialization"), JSONObjectWithData:data options:opt.bits() error:error]
+    }
+
+    /// Raises `NSInvalidArgumentException` if `obj` is not valid JSON.
+    unsafe fn dataWithJSONObject_options_error_(_: Self, obj: id, opt: NSJSONWritingOptions,
+                                                error: *mut id) -> id {
+        msg_send![class("NSJSONSerialization"), dataWithJSONObject:obj options:opt.bits() error:error]
+    }
+
+    unsafe fn JSONObjectWithData_options_(_: Self, data: id, opt: NSJSONReadingOptions) -> Result<Id, NSError> {
+        object_or_error(|error| NSJSONSerialization::JSONObjectWithData_options_error_(nil, data, opt, error))
+            .map(|object| Id::from_unretained(object))
+    }
+
+    /// Checks `obj` first, failing with `NSPropertyListWriteInvalidError` rather than raising.
+    unsafe fn dataWithJSONObject_options_(_: Self, obj: id, opt: NSJSONWritingOptions) -> Result<Id, NSError> {
+        let valid = if opt.contains(NSJSONWritingFragmentsAllowed) {
+            // Only arrays and dictionaries are valid at the top level.
+            let array = NSArray::arrayWithObject(nil, obj);
+            NSJSONSerialization::isValidJSONObject_(nil, array)
+        } else {
+            NSJSONSerialization::isValidJSONObject_(nil, obj)
+        };
+        if valid == NO {
+            return Err(NSError::new("NSCocoaErrorDomain", NSPropertyListWriteInvalidError, nil));
+        }
+        object_or_error(|error| NSJSONSerialization::dataWithJSONObject_options_error_(nil, obj, opt, error))
+            .map(|data| Id::from_unretained(data))
+    }
+}
+
+impl NSJSONSerialization for id {
+}
+
+/// JSON values as `NSJSONSerialization` objects, with `null` as `NSNull`. Integers and floats
+/// become `NSNumber`s of integer and double type, so that 1 and 1.0 stay distinct.
+#[cfg(feature = "serde_json")]
+impl ToCocoa for serde_json::Value {
+    unsafe fn to_cocoa(&self) -> Id {
+        match *self {
+            serde_json::Value::Null => Id::from_unretained(NSNull::null(nil)),
+            serde_json::Value::Bool(b) => b.to_cocoa(),
+            serde_json::Value::Number(ref n) => {
+                if let Some(i) = n.as_i64() {
+                    i.to_cocoa()
+                } else if let Some(u) = n.as_u64() {
+                    u.to_cocoa()
+                } else {
+                    n.as_f64().unwrap_or(f64::NAN).to_cocoa()
+                }
+            }
+            serde_json::Value::String(ref s) => s.to_cocoa(),
+            serde_json::Value::Array(ref items) => items.to_cocoa(),
+            serde_json::Value::Object(ref entries) => dictionary_from_pairs(entries.len(), entries.iter()),
+        }
+    }
+}
+
+/// Returns `None` for objects that are not valid in JSON, such as dates and data, and for NaN
+/// and infinite numbers.
+#[cfg(feature = "serde_json")]
+impl FromCocoa for serde_json::Value {
+    unsafe fn from_cocoa(object: id) -> Option<serde_json::Value> {
+        if is_kind_of_class(object, "NSNull") {
+            Some(serde_json::Value::Null)
+        } else if is_kind_of_class(object, "NSString") {
+            String::from_cocoa(object).map(serde_json::Value::String)
+        } else if is_kind_of_class(object, "NSNumber") {
+            Some(match object.value() {
+                NSNumberValue::Bool(b) => serde_json::Value::Bool(b),
+                NSNumberValue::F32(f) => serde_json::Value::Number(serde_json::Number::from_f64(f as f64)?),
+                NSNumberValue::F64(f) => serde_json::Value::Number(serde_json::Number::from_f64(f)?),
+                NSNumberValue::U64(u) => serde_json::Value::from(u),
+                value => serde_json::Value::from(i64::try_from(value).ok()?),
+            })
+        } else if is_kind_of_class(object, "NSArray") {
+            Vec::from_cocoa(object).map(serde_json::Value::Array)
+        } else if is_kind_of_class(object, "NSDictionary") {
+            object.pairs()
+                  .map(|(key, value)| Some((String::from_cocoa(key)?, serde_json::Value::from_cocoa(value)?)))
+                  .collect::<Option<serde_json::Map<_, _>>>()
+                  .map(serde_json::Value::Object)
+        } else {
+            None
+        }
+    }
+}
+
+bitflags! {
+    pub struct NSEnumerationOptions: libc::c_ulonglong {
+        const NSEnumerationConcurrent = 1 << 0;
+        const NSEnumerationReverse = 1 << 1;
+    }
+}
+
+pub type NSComparator = *mut Block<(id, id), NSComparisonResult>;
+
+#[repr(isize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NSComparisonResult {
+    NSOrderedAscending = -1,
+    NSOrderedSame = 0,
+    NSOrderedDescending = 1
+}
+
+impl From<Ordering> for NSComparisonResult {
+    fn from(ordering: Ordering) -> NSComparisonResult {
+        match ordering {
+            Ordering::Less => NSComparisonResult::NSOrderedAscending,
+            Ordering::Equal => NSComparisonResult::NSOrderedSame,
+            Ordering::Greater => NSComparisonResult::NSOrderedDescending,
+        }
+    }
+}
+
+pub trait NSString: Sized {
+    unsafe fn alloc(_: Self) -> id {
+        msg_send![class("NSString"), alloc]
+    }
+
+    unsafe fn stringByAppendingString_(self, other: id) -> id;
+    unsafe fn init_str(self, string: &str) -> Self;
+    unsafe fn UTF8String(self) -> *const libc::c_char;
+    unsafe fn len(self) -> usize;
+    unsafe fn isEqualToString(self, &str) -> bool;
+}
+
+impl NSString for id {
+    unsafe fn isEqualToString(self, other: &str) -> bool {
+        let other = NSString::alloc(nil).init_str(other);
+        let rv: BOOL = msg_send![self, isEqualToString:other];
+        rv != 0
+    }
+
+    unsafe fn stringByAppendingString_(self, other: id) -> id {
+        msg_send![self, stringByAppendingString:other]
+    }
+
+    unsafe fn init_str(self, string: &str) -> id {
+        return msg_send![self,
+                         initWithBytes:string.as_ptr()
+                             length:string.len()
+                             encoding:UTF8_ENCODING as id];
+    }
+
+    unsafe fn len(self) -> usize {
+        msg_send![self, lengthOfBytesUsingEncoding:UTF8_ENCODING]
+    }
+
+    unsafe fn UTF8String(self) -> *const libc::c_char {
+        msg_send![self, UTF8String]
+    }
+}
+
+/// Decodes an `NSString`, replacing unpaired surrogates with U+FFFD so that UTF-16 offsets into
+/// the string stay the same.
+unsafe fn string_lossy(string: id) -> String {
+    let length: NSUInteger = msg_send![string, length];
+    let mut units = vec![0u16; length as usize];
+    let () = msg_send![string, getCharacters:units.as_mut_ptr() range:NSRange::new(0, length)];
+    String::from_utf16_lossy(&units)
+}
+
+/// An attribute name along with the Rust type of its values. `appkit` has the standard ones.
+pub trait AttributeKey {
+    type Value;
+
+    unsafe fn key() -> id;
+    unsafe fn to_object(value: &Self::Value) -> Id;
+    /// Returns `None` for objects of the wrong class.
+    unsafe fn from_object(object: id) -> Option<Self::Value>;
+}
+
+pub trait NSAttributedString: Sized {
+    unsafe fn alloc(_: Self) -> id {
+        msg_send![class("NSAttributedString"), alloc]
+    }
+
+    unsafe fn initWithString_(self, string: id) -> id;
+    unsafe fn initWithString_attributes_(self, string: id, attributes: id) -> id;
+    unsafe fn initWithAttributedString_(self, attributedString: id) -> id;
+    unsafe fn string(self) -> id;
+    unsafe fn attributesAtIndex_effectiveRange_(self, location: NSUInteger, range: *mut NSRange) -> id;
+    unsafe fn attributesAtIndex_longestEffectiveRange_inRange_(self, location: NSUInteger, range: *mut NSRange,
+                                                               rangeLimit: NSRange) -> id;
+    unsafe fn attribute_atIndex_effectiveRange_(self, attributeName: id, location: NSUInteger,
+                                                range: *mut NSRange) -> id;
+    unsafe fn attribute_atIndex_longestEffectiveRange_inRange_(self, attributeName: id, location: NSUInteger,
+                                                               range: *mut NSRange, rangeLimit: NSRange) -> id;
+    unsafe fn attributedSubstringFromRange_(self, range: NSRange) -> id;
+    unsafe fn isEqualToAttributedString_(self, other: id) -> BOOL;
+
+    /// The text, with unpaired surrogates replaced by U+FFFD.
+    unsafe fn text(self) -> String;
+    /// Splits the text into the longest runs with one value for `key`, which is nil where unset.
+    /// Ranges are in bytes of `text()`.
+    unsafe fn attribute_runs(self, key: id) -> Vec<(Range<usize>, id)>;
+    /// Splits the text into the longest runs with one attributes dictionary.
+    unsafe fn attributes_runs(self) -> Vec<(Range<usize>, id)>;
+    /// The value of `K` for the character starting at byte `index` of `text()`, or `None` if no
+    /// character starts there.
+    unsafe fn attribute<K: AttributeKey>(self, index: usize) -> Option<K::Value>;
+    /// The runs where `K` is set.
+    unsafe fn enumerate_attribute<K: AttributeKey>(self) -> Vec<(Range<usize>, K::Value)>;
+}
+
+/// Calls `f` with each location still to be covered, and a range to fill in with the run found
+/// there.
+unsafe fn attributed_string_runs<F>(string: id, mut f: F) -> Vec<(Range<usize>, id)>
+    where F: FnMut(NSUInteger, *mut NSRange, NSRange) -> id
+{
+    let index = TextIndex::new(&string.text());
+    let length: NSUInteger = msg_send![string, length];
+    let mut runs = vec![];
+    let mut location = 0;
+    while location < length {
+        let mut range = NSRange::new(location, 0);
+        let value = f(location, &mut range, NSRange::new(0, length));
+        runs.push((index.to_range(range), value));
+        location = (range.location + range.length).max(location + 1);
+    }
+    runs
+}
+
+impl NSAttributedString for id {
+    unsafe fn initWithString_(self, string: id) -> id {
+        msg_send![self, initWithString:string]
+    }
+
+    unsafe fn initWithString_attributes_(self, string: id, attributes: id) -> id {
+        msg_send![self, initWithString:string attributes:attributes]
+    }
+
+    unsafe fn initWithAttributedString_(self, attributedString: id) -> id {
+        msg_send![self, initWithAttributedString:attributedString]
+    }
+
+    unsafe fn string(self) -> id {
+        msg_send![self, string]
+    }
+
+    unsafe fn attributesAtIndex_effectiveRange_(self, location: NSUInteger, range: *mut NSRange) -> id {
+        msg_send![self, attributesAtIndex:location effectiveRange:range]
+    }
+
+    unsafe fn attributesAtIndex_longestEffectiveRange_inRange_(self, location: NSUInteger, range: *mut NSRange,
+                                                               rangeLimit: NSRange) -> id {
+        msg_send![self, attributesAtIndex:location longestEffectiveRange:range inRange:rangeLimit]
+    }
+
+    unsafe fn attribute_atIndex_effectiveRange_(self, attributeName: id, location: NSUInteger,
+                                                range: *mut NSRange) -> id {
+        msg_send![self, attribute:attributeName atIndex:location effectiveRange:range]
+    }
+
+    unsafe fn attribute_atIndex_longestEffectiveRange_inRange_(self, attributeName: id, location: NSUInteger,
+                                                               range: *mut NSRange, rangeLimit: NSRange) -> id {
+        msg_send![self, attribute:attributeName atIndex:location longestEffectiveRange:range inRange:rangeLimit]
+    }
+
+    unsafe fn attributedSubstringFromRange_(self, range: NSRange) -> id {
+        msg_send![self, attributedSubstringFromRange:range]
+    }
+
+    unsafe fn isEqualToAttributedString_(self, other: id) -> BOOL {
+        msg_send![self, isEqualToAttributedString:other]
+    }
+
+    unsafe fn text(self) -> String {
+        string_lossy(NSAttributedString::string(self))
+    }
+
+    unsafe fn attribute_runs(self, key: id) -> Vec<(Range<usize>, id)> {
+        attributed_string_runs(self, |location, range, limit| {
+            self.attribute_atIndex_longestEffectiveRange_inRange_(key, location, range, limit)
+        })
+    }
+
+    unsafe fn attributes_runs(self) -> Vec<(Range<usize>, id)> {
+        attributed_string_runs(self, |location, range, limit| {
+            self.attributesAtIndex_longestEffectiveRange_inRange_(location, range, limit)
+        })
+    }
+
+    unsafe fn attribute<K: AttributeKey>(self, index: usize) -> Option<K::Value> {
+        let text = self.text();
+        if index >= text.len() || !text.is_char_boundary(index) {
+            return None;
+        }
+        let location = TextIndex::new(&text).to_ns_range(index..index).location;
+        non_nil(self.attribute_atIndex_effectiveRange_(K::key(), location, ptr::null_mut()))
+            .and_then(|object| K::from_object(object))
+    }
+
+    unsafe fn enumerate_attribute<K: AttributeKey>(self) -> Vec<(Range<usize>, K::Value)> {
+        self.attribute_runs(K::key())
+            .into_iter()
+            .filter_map(|(range, object)| Some((range, K::from_object(non_nil(object)?)?)))
+            .collect()
+    }
+}
+
+pub trait NSMutableAttributedString: Sized {
+    unsafe fn alloc(_: Self) -> id {
+        msg_send![class("NSMutableAttributedString"), alloc]
+    }
+
+    unsafe fn setAttributes_range_(self, attributes: id, range: NSRange);
+    unsafe fn addAttribute_value_range_(self, name: id, value: id, range: NSRange);
+    unsafe fn addAttributes_range_(self, attributes: id, range: NSRange);
+    unsafe fn removeAttribute_range_(self, name: id, range: NSRange);
+    unsafe fn replaceCharactersInRange_withString_(self, range: NSRange, string: id);
+    unsafe fn appendAttributedString_(self, attributedString: id);
+    unsafe fn beginEditing(self);
+    unsafe fn endEditing(self);
+
+    /// Sets `K` over bytes `range` of the text. Panics unless both ends are character boundaries.
+    unsafe fn set_attribute<K: AttributeKey>(self, value: &K::Value, range: Range<usize>);
+    unsafe fn remove_attribute<K: AttributeKey>(self, range: Range<usize>);
+    /// Appends `text` with the dictionary `attributes`, which may be nil.
+    unsafe fn append_str(self, text: &str, attributes: id);
+}
+
+impl NSMutableAttributedString for id {
+    unsafe fn setAttributes_range_(self, attributes: id, range: NSRange) {
+        msg_send![self, setAttributes:attributes range:range]
+    }
+
+    unsafe fn addAttribute_value_range_(self, name: id, value: id, range: NSRange) {
+        msg_send![self, addAttribute:name value:value range:range]
+    }
+
+    unsafe fn addAttributes_range_(self, attributes: id, range: NSRange) {
+        msg_send![self, addAttributes:attributes range:range]
+    }
+
+    unsafe fn removeAttribute_range_(self, name: id, range: NSRange) {
+        msg_send![self, removeAttribute:name range:range]
+    }
+
+    unsafe fn replaceCharactersInRange_withString_(self, range: NSRange, string: id) {
+        msg_send![self, replaceCharactersInRange:range withString:string]
+    }
+
+    unsafe fn appendAttributedString_(self, attributedString: id) {
+        msg_send![self, appendAttributedString:attributedString]
+    }
+
+    unsafe fn beginEditing(self) {
+        msg_send![self, beginEditing]
+    }
+
+    unsafe fn endEditing(self) {
+        msg_send![self, endEditing]
+    }
+
+    unsafe fn set_attribute<K: AttributeKey>(self, value: &K::Value, range: Range<usize>) {
+        let range = TextIndex::new(&self.text()).to_ns_range(range);
+        self.addAttribute_value_range_(K::key(), *K::to_object(value), range)
+    }
+
+    unsafe fn remove_attribute<K: AttributeKey>(self, range: Range<usize>) {
+        let range = TextIndex::new(&self.text()).to_ns_range(range);
+        self.removeAttribute_range_(K::key(), range)
+    }
+
+    unsafe fn append_str(self, text: &str, attributes: id) {
+        let string = NSAttributedString::alloc(nil).initWithString_attributes_(*text.to_cocoa(), attributes);
+        self.appendAttributedString_(*Id::from_retained(string))
+    }
+}
+
+pub trait NSDate: Sized {
+    unsafe fn date(_: Self) -> id {
+        msg_send![class("NSDate"), date]
+    }
+
+    unsafe fn dateWithTimeIntervalSinceNow_(_: Self, secs: NSTimeInterval) -> id {
+        msg_send![class("NSDate"), dateWithTimeIntervalSinceNow:secs]
+    }
+
+    unsafe fn dateWithTimeIntervalSinceReferenceDate_(_: Self, ti: NSTimeInterval) -> id {
+        msg_send![class("NSDate"), dateWithTimeIntervalSinceReferenceDate:ti]
+    }
+
+    unsafe fn dateWithTimeIntervalSince1970_(_: Self, secs: NSTimeInterval) -> id {
+        msg_send![class("NSDate"), dateWithTimeIntervalSince1970:secs]
+    }
+
+    unsafe fn dateWithTimeInterval_sinceDate_(_: Self, secsToBeAdded: NSTimeInterval, date: id) -> id {
+        msg_send![class("NSDate"), dateWithTimeInterval:secsToBeAdded sinceDate:date]
+    }
+
+    unsafe fn distantPast(_: Self) -> id {
+        msg_send![class("NSDate"), distantPast]
+    }
+
+    unsafe fn distantFuture(_: Self) -> id {
+        msg_send![class("NSDate"), distantFuture]
+    }
+
+    unsafe fn dateWithSystemTime(_: Self, time: SystemTime) -> id {
+        NSDate::dateWithTimeIntervalSinceReferenceDate_(nil, date::reference_interval_from_system_time(time))
+    }
+
+    unsafe fn timeIntervalSinceNow(self) -> NSTimeInterval;
+    unsafe fn timeIntervalSinceReferenceDate(self) -> NSTimeInterval;
+    unsafe fn timeIntervalSince1970(self) -> NSTimeInterval;
+    unsafe fn timeIntervalSinceDate_(self, anotherDate: id) -> NSTimeInterval;
+    unsafe fn dateByAddingTimeInterval_(self, ti: NSTimeInterval) -> id;
+
+    unsafe fn compare_(self, other: id) -> NSComparisonResult;
+    unsafe fn isEqualToDate_(self, otherDate: id) -> BOOL;
+    unsafe fn earlierDate_(self, anotherDate: id) -> id;
+    unsafe fn laterDate_(self, anotherDate: id) -> id;
+
+    /// Returns `None` for dates that `SystemTime` cannot represent on this platform.
+    unsafe fn systemTime(self) -> Option<SystemTime>;
+}
+
+impl NSDate for id {
+    unsafe fn timeIntervalSinceNow(self) -> NSTimeInterval {
+        msg_send![self, timeIntervalSinceNow]
+    }
+
+    unsafe fn timeIntervalSinceReferenceDate(self) -> NSTimeInterval {
+        msg_send![self, timeIntervalSinceReferenceDate]
+    }
+
+    unsafe fn timeIntervalSince1970(self) -> NSTimeInterval {
+        msg_send![self, timeIntervalSince1970]
+    }
+
+    unsafe fn timeIntervalSinceDate_(self, anotherDate: id) -> NSTimeInterval {
+        msg_send![self, timeIntervalSinceDate:anotherDate]
+    }
+
+    unsafe fn dateByAddingTimeInterval_(self, ti: NSTimeInterval) -> id {
+        msg_send![self, dateByAddingTimeInterval:ti]
+    }
+
+    unsafe fn compare_(self, other: id) -> NSComparisonResult {
+        msg_send![self, compare:other]
+    }
+
+    unsafe fn isEqualToDate_(self, otherDate: id) -> BOOL {
+        msg_send![self, isEqualToDate:otherDate]
+    }
+
+    unsafe fn earlierDate_(self, anotherDate: id) -> id {
+        msg_send![self, earlierDate:anotherDate]
+    }
+
+    unsafe fn laterDate_(self, anotherDate: id) -> id {
+        msg_send![self, laterDate:anotherDate]
+    }
+
+    unsafe fn systemTime(self) -> Option<SystemTime> {
+        date::system_time_from_reference_interval(self.timeIntervalSinceReferenceDate())
+    }
+}
+
+impl FastEnumerable<Object> for id {
+    unsafe fn count_by_enumerating(&self, state: &mut NSFastEnumerationState<Object>, buffer: *mut id,
+                                   len: usize) -> usize {
+        msg_send![*self, countByEnumeratingWithState:state objects:buffer count:len]
+    }
+}
+
+pub type NSFastIterator = FastIterator<Object, id>;
+
+/// Converts each object with `FromCocoa`, yielding `None` for objects of another type.
+pub struct NSFastIteratorAs<T> {
+    inner: NSFastIterator,
+    marker: PhantomData<T>,
+}
+
+impl<T: FromCocoa> Iterator for NSFastIteratorAs<T> {
+    type Item = Option<T>;
+
+    fn next(&mut self) -> Option<Option<T>> {
+        self.inner.next().map(|object| unsafe { T::from_cocoa(object) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Iterates over an `NSArray`, fast-enumerating from the front and indexing from the back.
+pub struct NSArrayIterator {
+    array: id,
+    front: NSFastIterator,
+    count: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Iterator for NSArrayIterator {
+    type Item = id;
+
+    fn next(&mut self) -> Option<id> {
+        if self.start == self.end {
+            return None;
+        }
+        self.start += 1;
+        self.front.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.start, Some(self.end - self.start))
+    }
+}
+
+impl DoubleEndedIterator for NSArrayIterator {
+    fn next_back(&mut self) -> Option<id> {
+        if self.start == self.end {
+            return None;
+        }
+        unsafe {
+            if NSArray::count(self.array) as usize != self.count {
+                panic!("{}", MutatedDuringEnumeration);
+            }
+            self.end -= 1;
+            Some(NSArray::objectAtIndex_(self.array, self.end as NSUInteger))
+        }
+    }
+}
+
+impl ExactSizeIterator for NSArrayIterator {}
+
+pub trait NSFastEnumeration: Sized {
+    unsafe fn iter(self) -> NSFastIterator;
+    unsafe fn iter_as<T: FromCocoa>(self) -> NSFastIteratorAs<T>;
+}
+
+impl NSFastEnumeration for id {
+    unsafe fn iter(self) -> NSFastIterator {
+        FastIterator::new(self)
+    }
+
+    unsafe fn iter_as<T: FromCocoa>(self) -> NSFastIteratorAs<T> {
+        NSFastIteratorAs { inner: self.iter(), marker: PhantomData }
+    }
+}
+
+#[link(name = "Foundation", kind = "framework")]
+extern {
+    fn NSInsetRect(rect: NSRect, x: CGFloat, y: CGFloat) -> NSRect;
+}
+
+#[repr(isize)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum NSQualityOfService {
+    NSQualityOfServiceUserInteractive = 0x21,
+    NSQualityOfServiceUserInitiated = 0x19,
+    NSQualityOfServiceUtility = 0x11,
+    NSQualityOfServiceBackground = 0x09,
+    NSQualityOfServiceDefault = -1,
+}
+
+impl NSQualityOfService {
+    fn from_raw(raw: NSInteger) -> NSQualityOfService {
+        match raw {
+            0x21 => NSQualityOfService::NSQualityOfServiceUserInteractive,
+            0x19 => NSQualityOfService::NSQualityOfServiceUserInitiated,
+            0x11 => NSQualityOfService::NSQualityOfServiceUtility,
+            0x09 => NSQualityOfService::NSQualityOfServiceBackground,
+            _ => NSQualityOfService::NSQualityOfServiceDefault,
+        }
+    }
+}
+
+pub trait NSThread: Sized {
+    unsafe fn currentThread(_: Self) -> id {
+        msg_send![class("NSThread"), currentThread]
+    }
+
+    unsafe fn mainThread(_: Self) -> id {
+        msg_send![class("NSThread"), mainThread]
+    }
+
+    unsafe fn isMainThread(_: Self) -> BOOL {
+        msg_send![class("NSThread"), isMainThread]
+    }
+
+    unsafe fn isMultiThreaded(_: Self) -> BOOL {
+        msg_send![class("NSThread"), isMultiThreaded]
+    }
+
+    unsafe fn sleepForTimeInterval_(_: Self, ti: NSTimeInterval) {
+        msg_send![class("NSThread"), sleepForTimeInterval:ti]
+    }
+
+    unsafe fn alloc(_: Self) -> id {
+        msg_send![class("NSThread"), alloc]
+    }
+
+    /// Returns a thread, not yet started, that runs `f` inside its own autorelease pool. A panic
+    /// in `f` ends the thread, as with `std::thread`.
+    unsafe fn with_closure<F: FnOnce() + Send + 'static>(_: Self, f: F) -> Id {
+        let f = Mutex::new(Some(f));
+        let block = ConcreteBlock::new(move || {
+            let f = f.lock().unwrap().take();
+            if let Some(f) = f {
+                let pool = NSAutoreleasePool::new(nil);
+                // The hook has already reported the panic, which must not unwind into Foundation.
+                let _ = panic::catch_unwind(panic::AssertUnwindSafe(f));
+                pool.drain();
+            }
+        }).copy();
+        Id::from_retained(NSThread::alloc(nil).initWithBlock_(&*block as *const _ as *mut _))
+    }
+
+    /// Starts a thread running `f` inside its own autorelease pool.
+    unsafe fn detach<F: FnOnce() + Send + 'static>(_: Self, f: F) {
+        NSThread::with_closure(nil, f).start()
+    }
+
+    /// Runs `f` on the main thread's run loop, in its common modes, optionally waiting for it to
+    /// finish. It runs immediately if called on the main thread with `wait` set.
+    ///
+    /// A panic in `f` resumes in the caller if it waits, and is otherwise only reported.
+    unsafe fn perform_on_main_thread<F: FnOnce() + Send + 'static>(_: Self, f: F, wait: bool) {
+        let call = thread_call(f);
+        let () = msg_send![*call, performSelectorOnMainThread:sel!(run)
+                                                   withObject:nil
+                                                waitUntilDone:wait as BOOL];
+        if wait {
+            resume_thread_call_panic(&call);
+        }
+    }
+
+    unsafe fn initWithBlock_(self, block: *mut Block<(), ()>) -> id;
+    unsafe fn start(self);
+    unsafe fn cancel(self);
+    unsafe fn isExecuting(self) -> BOOL;
+    unsafe fn isFinished(self) -> BOOL;
+    unsafe fn isCancelled(self) -> BOOL;
+    unsafe fn name(self) -> id;
+    unsafe fn setName_(self, name: id);
+    unsafe fn stackSize(self) -> NSUInteger;
+    unsafe fn setStackSize_(self, stackSize: NSUInteger);
+    unsafe fn qualityOfService(self) -> NSQualityOfService;
+    unsafe fn setQualityOfService_(self, qualityOfService: NSQualityOfService);
+    unsafe fn threadDictionary(self) -> id;
+
+    /// Runs `f` on this thread's run loop, in its default mode, optionally waiting for it to
+    /// finish. The run loop must be run for `f` to be called.
+    ///
+    /// A panic in `f` resumes in the caller if it waits, and is otherwise only reported.
+    unsafe fn perform<F: FnOnce() + Send + 'static>(self, f: F, wait: bool);
+}
+
+impl NSThread for id {
+    unsafe fn initWithBlock_(self, block: *mut Block<(), ()>) -> id {
+        msg_send![self, initWithBlock:block]
+    }
+
+    unsafe fn start(self) {
+        msg_send![self, start]
+    }
+
+    unsafe fn cancel(self) {
+        msg_send![self, cancel]
+    }
+
+    unsafe fn isExecuting(self) -> BOOL {
+        msg_send![self, isExecuting]
+    }
+
+    unsafe fn isFinished(self) -> BOOL {
+        msg_send![self, isFinished]
+    }
+
+    unsafe fn isCancelled(self) -> BOOL {
+        msg_send![self, isCancelled]
+    }
+
+    unsafe fn name(self) -> id {
+        msg_send![self, name]
+    }
+
+    unsafe fn setName_(self, name: id) {
+        msg_send![self, setName:name]
+    }
+
+    unsafe fn stackSize(self) -> NSUInteger {
+        msg_send![self, stackSize]
+    }
+
+    unsafe fn setStackSize_(self, stackSize: NSUInteger) {
+        msg_send![self, setStackSize:stackSize]
+    }
+
+    unsafe fn qualityOfService(self) -> NSQualityOfService {
+        let raw: NSInteger = msg_send![self, qualityOfService];
+        NSQualityOfService::from_raw(raw)
+    }
+
+    unsafe fn setQualityOfService_(self, qualityOfService: NSQualityOfService) {
+        msg_send![self, setQualityOfService:qualityOfService as NSInteger]
+    }
+
+    unsafe fn threadDictionary(self) -> id {
+        msg_send![self, threadDictionary]
+    }
+
+    unsafe fn perform<F: FnOnce() + Send + 'static>(self, f: F, wait: bool) {
+        let call = thread_call(f);
+        let () = msg_send![*call, performSelector:sel!(run)
+                                         onThread:self
+                                       withObject:nil
+                                    waitUntilDone:wait as BOOL];
+        if wait {
+            resume_thread_call_panic(&call);
+        }
+    }
+}
+
+struct ThreadCall {
+    f: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    /// What `f` panicked with, for a waiting caller to resume.
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+const THREAD_CALL_CLOSURE: &str = "rustClosure";
+
+fn thread_call_class() -> Class {
+    static REGISTER: Once = Once::new();
+    private_class(&REGISTER, "CocoaRsThreadCall", "NSObject", |decl| unsafe {
+        decl.add_ivar::<*mut libc::c_void>(THREAD_CALL_CLOSURE);
+        decl.add_method(sel!(run), thread_call_run as extern "C" fn(&Object, Sel));
+        decl.add_method(sel!(dealloc), thread_call_dealloc as extern "C" fn(&mut Object, Sel));
+    })
+}
+
+/// Boxes `f` in an object whose `run` method calls it, for the `performSelector:` family.
+unsafe fn thread_call<F: FnOnce() + Send + 'static>(f: F) -> Id {
+    let call: id = msg_send![thread_call_class(), new];
+    let closure = Box::new(ThreadCall { f: Mutex::new(Some(Box::new(f))), panic: Mutex::new(None) });
+    (*call).set_ivar(THREAD_CALL_CLOSURE, Box::into_raw(closure) as *mut libc::c_void);
+    Id::from_retained(call)
+}
+
+unsafe fn thread_call_storage(this: &Object) -> &ThreadCall {
+    &*(*this.get_ivar::<*mut libc::c_void>(THREAD_CALL_CLOSURE) as *const ThreadCall)
+}
+
+/// Resumes, in the thread that waited for `call`, a panic from its closure.
+unsafe fn resume_thread_call_panic(call: &Id) {
+    let payload = thread_call_storage(&*call.as_ptr()).panic.lock().unwrap().take();
+    if let Some(payload) = payload {
+        panic::resume_unwind(payload);
+    }
+}
+
+extern "C" fn thread_call_run(this: &Object, _: Sel) {
+    let call = unsafe { thread_call_storage(this) };
+    let f = call.f.lock().unwrap().take();
+    if let Some(f) = f {
+        // The hook has already reported the panic, which must not unwind into the run loop.
+        if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+            *call.panic.lock().unwrap() = Some(payload);
+        }
+    }
+}
+
+extern "C" fn thread_call_dealloc(this: &mut Object, _: Sel) {
+    unsafe {
+        let closure = *this.get_ivar::<*mut libc::c_void>(THREAD_CALL_CLOSURE);
+        if !closure.is_null() {
+            drop(Box::from_raw(closure as *mut ThreadCall));
+        }
+        let () = msg_send![super(this, &*class("NSObject")), dealloc];
+    }
+}
+
+pub trait NSRunLoop: Sized {
+    unsafe fn currentRunLoop() -> Self;
+    unsafe fn mainRunLoop() -> Self;
+
+    unsafe fn currentMode(self) -> id;
+    unsafe fn getCFRunLoop(self) -> CFRunLoopRef;
+    unsafe fn runMode_beforeDate_(self, mode: id, limitDate: id) -> BOOL;
+    unsafe fn runUntilDate_(self, limitDate: id);
+    unsafe fn limitDateForMode_(self, mode: id) -> id;
+
+    unsafe fn performSelector_target_argument_order_modes_(self,
+                                                           aSelector: SEL,
+                                                           target: id,
+                                                           anArgument: id,
+                                                           order: NSUInteger,
+                                                           modes: id);
+    unsafe fn addTimer_forMode_(self, timer: id, mode: id);
+}
+
+impl NSRunLoop for id {
+    unsafe fn currentRunLoop() -> id {
+        msg_send![class("NSRunLoop"), currentRunLoop]
+    }
+
+    unsafe fn mainRunLoop() -> id {
+        msg_send![class("NSRunLoop"), mainRunLoop]
+    }
+
+    unsafe fn currentMode(self) -> id {
+        msg_send![self, currentMode]
+    }
+
+    unsafe fn getCFRunLoop(self) -> CFRunLoopRef {
+        msg_send![self, getCFRunLoop]
+    }
+
+    unsafe fn runMode_beforeDate_(self, mode: id, limitDate: id) -> BOOL {
+        msg_send![self, runMode:mode beforeDate:limitDate]
+    }
+
+    unsafe fn runUntilDate_(self, limitDate: id) {
+        msg_send![self, runUntilDate:limitDate]
+    }
+
+    unsafe fn limitDateForMode_(self, mode: id) -> id {
+        msg_send![self, limitDateForMode:mode]
+    }
+
+    unsafe fn performSelector_target_argument_order_modes_(self,
+                                                           aSelector: SEL,
+                                                           target: id,
+                                                           anArgument: id,
+                                                           order: NSUInteger,
+                                                           modes: id) {
+        msg_send![self, performSelector:aSelector
+                                 target:target
+                               argument:anArgument
+                                  order:order
+                                  modes:modes]
+    }
+
+    unsafe fn addTimer_forMode_(self, timer: id, mode: id) {
+        msg_send![self, addTimer:timer forMode:mode]
+    }
+}
+
+pub type CFRunLoopRef = *mut libc::c_void;
+pub type CFRunLoopObserverRef = *mut libc::c_void;
+pub type CFRunLoopSourceRef = *mut libc::c_void;
+pub type CFIndex = libc::c_long;
+
+bitflags! {
+    pub struct CFRunLoopActivity: libc::c_ulong {
+        const kCFRunLoopEntry = 1 << 0;
+        const kCFRunLoopBeforeTimers = 1 << 1;
+        const kCFRunLoopBeforeSources = 1 << 2;
+        const kCFRunLoopBeforeWaiting = 1 << 5;
+        const kCFRunLoopAfterWaiting = 1 << 6;
+        const kCFRunLoopExit = 1 << 7;
+        const kCFRunLoopAllActivities = 0x0FFFFFFF;
+    }
+}
+
+#[repr(C)]
+struct CFRunLoopObserverContext {
+    version: CFIndex,
+    info: *mut libc::c_void,
+    retain: Option<extern "C" fn(*const libc::c_void) -> *const libc::c_void>,
+    release: Option<extern "C" fn(*const libc::c_void)>,
+    copyDescription: Option<extern "C" fn(*const libc::c_void) -> id>,
+}
+
+#[repr(C)]
+struct CFRunLoopSourceContext {
+    version: CFIndex,
+    info: *mut libc::c_void,
+    retain: Option<extern "C" fn(*const libc::c_void) -> *const libc::c_void>,
+    release: Option<extern "C" fn(*const libc::c_void)>,
+    copyDescription: Option<extern "C" fn(*const libc::c_void) -> id>,
+    equal: Option<extern "C" fn(*const libc::c_void, *const libc::c_void) -> u8>,
+    hash: Option<extern "C" fn(*const libc::c_void) -> libc::c_ulong>,
+    schedule: Option<extern "C" fn(*mut libc::c_void, CFRunLoopRef, id)>,
+    cancel: Option<extern "C" fn(*mut libc::c_void, CFRunLoopRef, id)>,
+    perform: Option<extern "C" fn(*mut libc::c_void)>,
+}
+
+type CFRunLoopObserverCallBack = extern "C" fn(CFRunLoopObserverRef, libc::c_ulong, *mut libc::c_void);
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern {
+    pub static kCFRunLoopDefaultMode: id;
+    pub static kCFRunLoopCommonModes: id;
+
+    pub fn CFRunLoopGetCurrent() -> CFRunLoopRef;
+    pub fn CFRunLoopGetMain() -> CFRunLoopRef;
+    pub fn CFRunLoopStop(rl: CFRunLoopRef);
+    pub fn CFRunLoopWakeUp(rl: CFRunLoopRef);
+
+    fn CFRetain(cf: *const libc::c_void) -> *const libc::c_void;
+    fn CFRelease(cf: *const libc::c_void);
+
+    fn CFRunLoopObserverCreate(allocator: *const libc::c_void, activities: libc::c_ulong, repeats: u8,
+                               order: CFIndex, callout: CFRunLoopObserverCallBack,
+                               context: *mut CFRunLoopObserverContext) -> CFRunLoopObserverRef;
+    fn CFRunLoopAddObserver(rl: CFRunLoopRef, observer: CFRunLoopObserverRef, mode: id);
+    fn CFRunLoopRemoveObserver(rl: CFRunLoopRef, observer: CFRunLoopObserverRef, mode: id);
+    fn CFRunLoopObserverInvalidate(observer: CFRunLoopObserverRef);
+
+    fn CFRunLoopSourceCreate(allocator: *const libc::c_void, order: CFIndex,
+                             context: *mut CFRunLoopSourceContext) -> CFRunLoopSourceRef;
+    fn CFRunLoopAddSource(rl: CFRunLoopRef, source: CFRunLoopSourceRef, mode: id);
+    fn CFRunLoopRemoveSource(rl: CFRunLoopRef, source: CFRunLoopSourceRef, mode: id);
+    fn CFRunLoopSourceSignal(source: CFRunLoopSourceRef);
+    fn CFRunLoopSourceInvalidate(source: CFRunLoopSourceRef);
+}
+
+extern "C" fn release_boxed_callback<T>(info: *const libc::c_void) {
+    unsafe { drop(Box::from_raw(info as *mut T)) }
+}
+
+extern "C" fn observer_callback<F: FnMut(CFRunLoopActivity)>(_: CFRunLoopObserverRef, activity: libc::c_ulong,
+                                                             info: *mut libc::c_void) {
+    let f = unsafe { &*(info as *const RefCell<F>) };
+    // A nested run loop inside the callback reports its own activities, which are dropped.
+    if let Ok(mut f) = f.try_borrow_mut() {
+        (*f)(CFRunLoopActivity::from_bits_truncate(activity));
+    }
+}
+
+// Not guarded against re-entry, so that a nested run loop inside the closure still performs it.
+extern "C" fn source_perform<F: Fn()>(info: *mut libc::c_void) {
+    let f = unsafe { &*(info as *const F) };
+    f();
+}
+
+/// A `CFRunLoopObserver` calling a Rust closure at the chosen points of each run loop pass. It is
+/// invalidated on drop.
+pub struct RunLoopObserver {
+    observer: CFRunLoopObserverRef,
+}
+
+impl RunLoopObserver {
+    /// Observers with a lower `order` are called first. A non-repeating observer is invalidated
+    /// after its first call.
+    pub fn new<F: FnMut(CFRunLoopActivity) + 'static>(activities: CFRunLoopActivity, repeats: bool, order: isize,
+                                                      f: F) -> RunLoopObserver {
+        let mut context = CFRunLoopObserverContext {
+            version: 0,
+            info: Box::into_raw(Box::new(RefCell::new(f))) as *mut libc::c_void,
+            retain: None,
+            release: Some(release_boxed_callback::<RefCell<F>>),
+            copyDescription: None,
+        };
+        unsafe {
+            let observer = CFRunLoopObserverCreate(ptr::null(), activities.bits(), repeats as u8, order as CFIndex,
+                                                   observer_callback::<F>, &mut context);
+            assert!(!observer.is_null(), "CFRunLoopObserverCreate failed");
+            RunLoopObserver { observer }
+        }
+    }
+
+    /// Adds the observer to the `NSRunLoop` `run_loop` in `mode`.
+    pub unsafe fn add(&self, run_loop: id, mode: id) {
+        CFRunLoopAddObserver(run_loop.getCFRunLoop(), self.observer, mode)
+    }
+
+    pub unsafe fn remove(&self, run_loop: id, mode: id) {
+        CFRunLoopRemoveObserver(run_loop.getCFRunLoop(), self.observer, mode)
+    }
+
+    pub fn as_ptr(&self) -> CFRunLoopObserverRef {
+        self.observer
+    }
+}
+
+impl Drop for RunLoopObserver {
+    fn drop(&mut self) {
+        unsafe {
+            CFRunLoopObserverInvalidate(self.observer);
+            CFRelease(self.observer);
+        }
+    }
+}
+
+/// A version 0 `CFRunLoopSource` calling a Rust closure on the run loop after being signalled.
+/// It is invalidated on drop.
+pub struct RunLoopSource {
+    source: CFRunLoopSourceRef,
+    target: Arc<SignalTarget>,
+}
+
+impl RunLoopSource {
+    /// Sources with a lower `order` are performed first when several are signalled. `f` may be
+    /// called again before it returns, if it runs the run loop itself.
+    pub fn new<F: Fn() + 'static>(order: isize, f: F) -> RunLoopSource {
+        let mut context = CFRunLoopSourceContext {
+            version: 0,
+            info: Box::into_raw(Box::new(f)) as *mut libc::c_void,
+            retain: None,
+            release: Some(release_boxed_callback::<F>),
+            copyDescription: None,
+            equal: None,
+            hash: None,
+            schedule: None,
+            cancel: None,
+            perform: Some(source_perform::<F>),
+        };
+        unsafe {
+            let source = CFRunLoopSourceCreate(ptr::null(), order as CFIndex, &mut context);
+            assert!(!source.is_null(), "CFRunLoopSourceCreate failed");
+            RunLoopSource { source, target: Arc::new(SignalTarget(Mutex::new(source))) }
+        }
+    }
+
+    /// Adds the source to the `NSRunLoop` `run_loop` in `mode`.
+    pub unsafe fn add(&self, run_loop: id, mode: id) {
+        CFRunLoopAddSource(run_loop.getCFRunLoop(), self.source, mode)
+    }
+
+    pub unsafe fn remove(&self, run_loop: id, mode: id) {
+        CFRunLoopRemoveSource(run_loop.getCFRunLoop(), self.source, mode)
+    }
+
+    /// Marks the source as ready. The closure runs on the next pass of a run loop the source was
+    /// added to, which must be woken if it is waiting on another thread.
+    pub fn signal(&self) {
+        unsafe { CFRunLoopSourceSignal(self.source) }
+    }
+
+    /// Returns a handle that signals the source from any thread and wakes up `run_loop`, which
+    /// should be one the source was added to.
+    pub unsafe fn signaller(&self, run_loop: id) -> RunLoopSignaller {
+        let run_loop = run_loop.getCFRunLoop();
+        RunLoopSignaller {
+            target: self.target.clone(),
+            run_loop: CFRetain(run_loop) as CFRunLoopRef,
+        }
+    }
+
+    pub fn as_ptr(&self) -> CFRunLoopSourceRef {
+        self.source
+    }
+}
+
+impl Drop for RunLoopSource {
+    fn drop(&mut self) {
+        *self.target.0.lock().unwrap() = ptr::null_mut();
+        unsafe {
+            CFRunLoopSourceInvalidate(self.source);
+            CFRelease(self.source);
+        }
+    }
+}
+
+// The source a `RunLoopSignaller` signals, or null once the `RunLoopSource` is dropped. Signallers
+// never retain the source, since its last release would drop the closure on their thread.
+struct SignalTarget(Mutex<CFRunLoopSourceRef>);
+
+// The source is only signalled under the lock, which the owner takes before releasing it.
+unsafe impl Send for SignalTarget {}
+unsafe impl Sync for SignalTarget {}
+
+/// Signals a `RunLoopSource` from any thread. Signalling after the source is dropped does
+/// nothing.
+pub struct RunLoopSignaller {
+    target: Arc<SignalTarget>,
+    run_loop: CFRunLoopRef,
+}
+
+// Run loops may be woken, retained and released from any thread.
+unsafe impl Send for RunLoopSignaller {}
+unsafe impl Sync for RunLoopSignaller {}
+
+impl RunLoopSignaller {
+    pub fn signal(&self) {
+        let source = self.target.0.lock().unwrap();
+        if !source.is_null() {
+            unsafe {
+                CFRunLoopSourceSignal(*source);
+                CFRunLoopWakeUp(self.run_loop);
+            }
+        }
+    }
+}
+
+impl Clone for RunLoopSignaller {
+    fn clone(&self) -> RunLoopSignaller {
+        unsafe {
+            RunLoopSignaller {
+                target: self.target.clone(),
+                run_loop: CFRetain(self.run_loop) as CFRunLoopRef,
+            }
+        }
+    }
+}
+
+impl Drop for RunLoopSignaller {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.run_loop) }
+    }
+}
+
+pub trait NSTimer: Sized {
+    unsafe fn timerWithTimeInterval_repeats_block_(_: Self, interval: NSTimeInterval, repeats: BOOL,
+                                                   block: *mut Block<(id,), ()>) -> id {
+        msg_send![class("NSTimer"), timerWithTimeInterval:interval repeats:repeats block:block]
+    }
+
+    unsafe fn scheduledTimerWithTimeInterval_repeats_block_(_: Self, interval: NSTimeInterval, repeats: BOOL,
+                                                            block: *mut Block<(id,), ()>) -> id {
+        msg_send![class("NSTimer"), scheduledTimerWithTimeInterval:interval repeats:repeats block:block]
+    }
+
+    unsafe fn timerWithTimeInterval_target_selector_userInfo_repeats_(_: Self, ti: NSTimeInterval, aTarget: id,
+                                                                      aSelector: SEL, userInfo: id, yesOrNo: BOOL)
+                                                                      -> id {
+        msg_send![class("NSTimer"), timerWithTimeInterval:ti
+                                                   target:aTarget
+                                                 selector:aSelector
+                                                 userInfo:userInfo
+                                                  repeats:yesOrNo]
+    }
+
+    unsafe fn scheduledTimerWithTimeInterval_target_selector_userInfo_repeats_(_: Self, ti: NSTimeInterval,
+                                                                               aTarget: id, aSelector: SEL,
+                                                                               userInfo: id, yesOrNo: BOOL) -> id {
+        msg_send![class("NSTimer"), scheduledTimerWithTimeInterval:ti
+                                                            target:aTarget
+                                                          selector:aSelector
+                                                          userInfo:userInfo
+                                                           repeats:yesOrNo]
+    }
+
+    unsafe fn fire(self);
+    unsafe fn invalidate(self);
+    unsafe fn isValid(self) -> BOOL;
+    unsafe fn fireDate(self) -> id;
+    unsafe fn setFireDate_(self, date: id);
+    unsafe fn timeInterval(self) -> NSTimeInterval;
+    unsafe fn tolerance(self) -> NSTimeInterval;
+    unsafe fn setTolerance_(self, tolerance: NSTimeInterval);
+}
+
+impl NSTimer for id {
+    unsafe fn fire(self) {
+        msg_send![self, fire]
+    }
+
+    unsafe fn invalidate(self) {
+        msg_send![self, invalidate]
+    }
+
+    unsafe fn isValid(self) -> BOOL {
+        msg_send![self, isValid]
+    }
+
+    unsafe fn fireDate(self) -> id {
+        msg_send![self, fireDate]
+    }
+
+    unsafe fn setFireDate_(self, date: id) {
+        msg_send![self, setFireDate:date]
+    }
+
+    unsafe fn timeInterval(self) -> NSTimeInterval {
+        msg_send![self, timeInterval]
+    }
+
+    unsafe fn tolerance(self) -> NSTimeInterval {
+        msg_send![self, tolerance]
+    }
+
+    unsafe fn setTolerance_(self, tolerance: NSTimeInterval) {
+        msg_send![self, setTolerance:tolerance]
+    }
+}
+
+/// An `NSTimer` that calls a Rust closure. It does nothing until scheduled on a run loop, and is
+/// invalidated on drop.
+pub struct Timer {
+    timer: Id,
+    _block: RcBlock<(id,), ()>,
+}
+
+impl Timer {
+    /// Creates a timer that first fires `interval` after being scheduled. The closure is passed
+    /// the `NSTimer`, so a repeating timer can invalidate itself.
+    pub fn new<F: FnMut(id) + 'static>(interval: Duration, repeats: bool, f: F) -> Timer {
+        let f = RefCell::new(f);
+        let block = ConcreteBlock::new(move |timer: id| {
+            // A nested run loop inside the closure could fire the timer again.
+            if let Ok(mut f) = f.try_borrow_mut() {
+                (*f)(timer);
+            }
+        }).copy();
+        unsafe {
+            let timer = NSTimer::timerWithTimeInterval_repeats_block_(nil, date::interval_from_duration(interval),
+                                                                      repeats as BOOL,
+                                                                      &*block as *const _ as *mut _);
+            Timer {
+                timer: Id::from_unretained(timer),
+                _block: block,
+            }
+        }
+    }
+
+    pub fn once<F: FnOnce() + 'static>(delay: Duration, f: F) -> Timer {
+        let mut f = Some(f);
+        Timer::new(delay, false, move |_| {
+            if let Some(f) = f.take() {
+                f()
+            }
+        })
+    }
+
+    pub fn repeating<F: FnMut() + 'static>(interval: Duration, mut f: F) -> Timer {
+        Timer::new(interval, true, move |_| f())
+    }
+
+    /// Lets the system fire the timer up to `tolerance` late, to save power.
+    pub fn with_tolerance(self, tolerance: Duration) -> Timer {
+        unsafe { self.timer.setTolerance_(date::interval_from_duration(tolerance)) }
+        self
+    }
+
+    /// Adds the timer to `run_loop` in each of `modes`, such as `NSDefaultRunLoopMode`,
+    /// `NSEventTrackingRunLoopMode` or `NSRunLoopCommonModes`.
+    pub unsafe fn schedule(&self, run_loop: id, modes: &[id]) {
+        for &mode in modes {
+            run_loop.addTimer_forMode_(*self.timer, mode);
+        }
+    }
+
+    /// Adds the timer to the current thread's run loop in its common modes, so it keeps firing
+    /// while menus are tracked and windows resized.
+    pub fn schedule_on_current_run_loop(&self) {
+        unsafe { self.schedule(NSRunLoop::currentRunLoop(), &[NSRunLoopCommonModes]) }
+    }
+
+    /// Fires the timer now. A one-shot timer is then invalidated.
+    pub fn fire(&self) {
+        unsafe { self.timer.fire() }
+    }
+
+    pub fn invalidate(&self) {
+        unsafe { self.timer.invalidate() }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        unsafe { self.timer.isValid() != NO }
+    }
+
+    pub fn as_id(&self) -> id {
+        self.timer.as_ptr()
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.invalidate()
+    }
+}
+
+impl executor::Signal for RunLoopSignaller {
+    fn signal(&self) {
+        RunLoopSignaller::signal(self)
+    }
+}
+
+struct RunLoopExecutorInner {
+    executor: executor::Executor<RunLoopSignaller>,
+    source: RunLoopSource,
+    run_loop: Id,
+}
+
+impl Drop for RunLoopExecutorInner {
+    fn drop(&mut self) {
+        unsafe { self.source.remove(*self.run_loop, NSRunLoopCommonModes) }
+    }
+}
+
+/// Polls `!Send` futures on a thread's run loop, in its common modes, whenever they are woken.
+/// Wakers may be used from any thread.
+#[derive(Clone)]
+pub struct RunLoopExecutor {
+    inner: Rc<RunLoopExecutorInner>,
+}
+
+impl RunLoopExecutor {
+    /// Creates an executor for the current thread's run loop, which must be run, for example by
+    /// `NSApplication::run`, for tasks to make progress.
+    pub fn current() -> RunLoopExecutor {
+        let inner = Rc::new_cyclic(|inner: &Weak<RunLoopExecutorInner>| unsafe {
+            let inner = inner.clone();
+            let source = RunLoopSource::new(0, move || {
+                if let Some(inner) = inner.upgrade() {
+                    inner.executor.run_ready();
+                }
+            });
+            let run_loop: id = NSRunLoop::currentRunLoop();
+            source.add(run_loop, NSRunLoopCommonModes);
+            RunLoopExecutorInner {
+                executor: executor::Executor::new(source.signaller(run_loop)),
+                source,
+                run_loop: Id::from_unretained(run_loop),
+            }
+        });
+        RunLoopExecutor { inner }
+    }
+
+    /// Creates an executor for the main run loop.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless called on the main thread.
+    pub fn main() -> RunLoopExecutor {
+        unsafe {
+            assert!(CFRunLoopGetCurrent() == CFRunLoopGetMain(), "not on the main thread");
+        }
+        RunLoopExecutor::current()
+    }
+
+    pub fn spawn<F: Future + 'static>(&self, future: F) -> JoinHandle<F::Output> {
+        self.inner.executor.spawn(future)
+    }
+
+    /// Runs the run loop in the default mode until `future` completes, for use outside of an
+    /// application's own event loop.
+    pub fn block_on<F: Future + 'static>(&self, future: F) -> F::Output {
+        let run_loop = *self.inner.run_loop;
+        self.inner.executor.block_on(future, || unsafe {
+            run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, NSDate::distantFuture(nil));
+        })
+    }
+
+    /// The number of tasks that have not yet completed.
+    pub fn len(&self) -> usize {
+        self.inner.executor.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.executor.is_empty()
+    }
+}
+
+/// Resolves once an `NSTimer` scheduled on the current run loop fires.
+pub struct Sleep {
+    receiver: executor::Receiver<()>,
+    _timer: Timer,
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    let (sender, receiver) = executor::oneshot();
+    let timer = Timer::once(duration, move || sender.send(()));
+    timer.schedule_on_current_run_loop();
+    Sleep { receiver, _timer: timer }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        Pin::new(&mut self.receiver).poll(cx).map(|_| ())
+    }
+}
+
+/// Resolves to the next notification named `name` from `object`, either of which may be nil to
+/// match any.
+pub struct NextNotification {
+    receiver: executor::Receiver<Id>,
+    _observer: NotificationObserver,
+}
+
+/// # Safety
+///
+/// As with `NSNotificationCenter::observe`, the notification must be posted on this thread.
+pub unsafe fn next_notification(center: id, name: id, object: id) -> NextNotification {
+    let (sender, receiver) = executor::oneshot();
+    let sender = Mutex::new(Some(sender));
+    let observer = center.observe(name, object, move |notification| {
+        if let Some(sender) = sender.lock().unwrap().take() {
+            sender.send(Id::from_unretained(notification));
+        }
+    });
+    NextNotification { receiver, _observer: observer }
+}
+
+impl Future for NextNotification {
+    type Output = Id;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Id> {
+        // The observer holds the sender until it is used.
+        Pin::new(&mut self.receiver).poll(cx).map(|notification| notification.unwrap())
+    }
+}
+
+bitflags! {
+    pub struct NSKeyValueObservingOptions: libc::c_ulonglong {
+        const NSKeyValueObservingOptionNew = 1 << 0;
+        const NSKeyValueObservingOptionOld = 1 << 1;
+        const NSKeyValueObservingOptionInitial = 1 << 2;
+        const NSKeyValueObservingOptionPrior = 1 << 3;
+    }
+}
+
+#[link(name = "Foundation", kind = "framework")]
+extern {
+    pub static NSKeyValueChangeKindKey: id;
+    pub static NSKeyValueChangeNewKey: id;
+    pub static NSKeyValueChangeOldKey: id;
+    pub static NSKeyValueChangeIndexesKey: id;
+}
+
+type KeyValueSender = Mutex<Option<executor::Sender<Id>>>;
+
+const KEY_VALUE_OBSERVER_SENDER: &str = "rustSender";
+
+fn key_value_observer_class() -> Class {
+    static REGISTER: Once = Once::new();
+    private_class(&REGISTER, "CocoaRsKeyValueObserver", "NSObject", |decl| unsafe {
+        decl.add_ivar::<*mut libc::c_void>(KEY_VALUE_OBSERVER_SENDER);
+        decl.add_method(sel!(observeValueForKeyPath:ofObject:change:context:),
+                        key_value_observer_observe as extern "C" fn(&Object, Sel, id, id, id, *mut libc::c_void));
+        decl.add_method(sel!(dealloc), key_value_observer_dealloc as extern "C" fn(&mut Object, Sel));
+    })
+}
+
+extern "C" fn key_value_observer_observe(this: &Object, _: Sel, _: id, _: id, change: id, _: *mut libc::c_void) {
+    unsafe {
+        let sender = &*(*this.get_ivar::<*mut libc::c_void>(KEY_VALUE_OBSERVER_SENDER) as *const KeyValueSender);
+        if let Some(sender) = sender.lock().unwrap().take() {
+            sender.send(Id::from_unretained(change));
+        }
+    }
+}
+
+extern "C" fn key_value_observer_dealloc(this: &mut Object, _: Sel) {
+    unsafe {
+        let sender = *this.get_ivar::<*mut libc::c_void>(KEY_VALUE_OBSERVER_SENDER);
+        if !sender.is_null() {
+            drop(Box::from_raw(sender as *mut KeyValueSender));
+        }
+        let () = msg_send![super(this, &*class("NSObject")), dealloc];
+    }
+}
+
+/// Resolves to the change dictionary of the next key-value observing notification for a key
+/// path, keyed by `NSKeyValueChangeNewKey` and the like.
+pub struct KeyValueChange {
+    receiver: executor::Receiver<Id>,
+    observer: Id,
+    object: Id,
+    key_path: Id,
+}
+
+pub unsafe fn key_value_change(object: id, key_path: &str, options: NSKeyValueObservingOptions)
+                               -> KeyValueChange {
+    let (sender, receiver) = executor::oneshot();
+    let observer: id = msg_send![key_value_observer_class(), new];
+    let sender: Box<KeyValueSender> = Box::new(Mutex::new(Some(sender)));
+    (*observer).set_ivar(KEY_VALUE_OBSERVER_SENDER, Box::into_raw(sender) as *mut libc::c_void);
+    let key_path = key_path.to_cocoa();
+    let () = msg_send![object, addObserver:observer
+                                forKeyPath:*key_path
+                                   options:options.bits()
+                                   context:ptr::null_mut::<libc::c_void>()];
+    KeyValueChange {
+        receiver,
+        observer: Id::from_retained(observer),
+        object: Id::from_unretained(object),
+        key_path,
+    }
+}
+
+impl Future for KeyValueChange {
+    type Output = Id;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Id> {
+        // The observer holds the sender until it is used.
+        Pin::new(&mut self.receiver).poll(cx).map(|change| change.unwrap())
+    }
+}
+
+impl Drop for KeyValueChange {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![*self.object, removeObserver:*self.observer forKeyPath:*self.key_path];
+        }
+    }
+}
+
+/// Resolves to what a completion handler block was called with, or `None` if the block was
+/// released without being called.
+pub struct Completion<T> {
+    receiver: executor::Receiver<T>,
+}
+
+impl<T> Future for Completion<T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        Pin::new(&mut self.receiver).poll(cx)
+    }
+}
+
+/// Returns a `void (^)(id)` block to pass as a completion handler, and a future for its argument.
+/// The block may be called on any thread.
+pub fn completion_handler() -> (RcBlock<(id,), ()>, Completion<Id>) {
+    let (sender, receiver) = executor::oneshot();
+    let sender = Mutex::new(Some(sender));
+    let block = ConcreteBlock::new(move |object: id| {
+        if let Some(sender) = sender.lock().unwrap().take() {
+            sender.send(unsafe { Id::from_unretained(object) });
+        }
+    }).copy();
+    (block, Completion { receiver })
+}
+
+type ErrorCompletionHandler = RcBlock<(id, id), ()>;
+
+/// Returns a `void (^)(id, NSError *)` block to pass as a completion handler, and a future for
+/// its result.
+pub fn completion_handler_with_error() -> (ErrorCompletionHandler, Completion<Result<Id, NSError>>) {
+    let (sender, receiver) = executor::oneshot();
+    let sender = Mutex::new(Some(sender));
+    let block = ConcreteBlock::new(move |object: id, error: id| {
+        if let Some(sender) = sender.lock().unwrap().take() {
+            sender.send(unsafe {
+                if error.is_null() {
+                    Ok(Id::from_unretained(object))
+                } else {
+                    Err(NSError::from_id(error))
+                }
+            });
+        }
+    }).copy();
+    (block, Completion { receiver })
+}
+
+#[link(name = "Foundation", kind = "framework")]
+extern {
+    pub static NSCocoaErrorDomain: id;
+    pub static NSPOSIXErrorDomain: id;
+    pub static NSOSStatusErrorDomain: id;
+    pub static NSUnderlyingErrorKey: id;
+    pub static NSLocalizedDescriptionKey: id;
+    pub static NSLocalizedFailureReasonErrorKey: id;
+    pub static NSFilePathErrorKey: id;
+}
+
+// Error codes in `NSCocoaErrorDomain`.
+pub const NSFileNoSuchFileError: NSInteger = 4;
+pub const NSFileLockingError: NSInteger = 255;
+pub const NSFileReadUnknownError: NSInteger = 256;
+pub const NSFileReadNoPermissionError: NSInteger = 257;
+pub const NSFileReadInvalidFileNameError: NSInteger = 258;
+pub const NSFileReadCorruptFileError: NSInteger = 259;
+pub const NSFileReadNoSuchFileError: NSInteger = 260;
+pub const NSFileReadInapplicableStringEncodingError: NSInteger = 261;
+pub const NSFileReadUnsupportedSchemeError: NSInteger = 262;
+pub const NSFileReadTooLargeError: NSInteger = 263;
+pub const NSFileWriteUnknownError: NSInteger = 512;
+pub const NSFileWriteNoPermissionError: NSInteger = 513;
+pub const NSFileWriteInvalidFileNameError: NSInteger = 514;
+pub const NSFileWriteFileExistsError: NSInteger = 516;
+pub const NSFileWriteInapplicableStringEncodingError: NSInteger = 517;
+pub const NSFileWriteUnsupportedSchemeError: NSInteger = 518;
+pub const NSFileWriteOutOfSpaceError: NSInteger = 640;
+pub const NSFileWriteVolumeReadOnlyError: NSInteger = 642;
+pub const NSUserCancelledError: NSInteger = 3072;
+pub const NSPropertyListReadCorruptError: NSInteger = 3840;
+pub const NSPropertyListWriteInvalidError: NSInteger = 3852;
+
+/// The domain of the error that stands in when a method fails without setting its error pointer.
+pub const MISSING_ERROR_DOMAIN: &str = "CocoaRsMissingErrorDomain";
+
+/// An owned `NSError`, usable as a Rust error.
+pub struct NSError {
+    error: Id,
+    underlying: Option<Box<NSError>>,
+}
+
+impl NSError {
+    /// Retains `error`, which must be a non-nil `NSError`.
+    pub unsafe fn from_id(error: id) -> NSError {
+        let underlying: id = msg_send![NSErrorUserInfo::userInfo(error), objectForKey:NSUnderlyingErrorKey];
+        NSError {
+            error: Id::from_unretained(error),
+            underlying: non_nil(underlying).map(|underlying| Box::new(NSError::from_id(underlying))),
+        }
+    }
+
+    pub unsafe fn new(domain: &str, code: NSInteger, userInfo: id) -> NSError {
+        let error: id = msg_send![class("NSError"), errorWithDomain:*domain.to_cocoa() code:code userInfo:userInfo];
+        NSError::from_id(error)
+    }
+
+    /// Converts what a failed out-error method left in its error pointer.
+    unsafe fn from_out_ptr(error: id) -> NSError {
+        if error.is_null() {
+            // The method broke its contract by failing without saying why.
+            let description = "The operation failed without reporting an error.".to_cocoa();
+            let user_info = NSDictionary::dictionaryWithObject_forKey_(nil, *description, NSLocalizedDescriptionKey);
+            NSError::new(MISSING_ERROR_DOMAIN, 0, user_info)
+        } else {
+            NSError::from_id(error)
+        }
+    }
+
+    pub fn as_id(&self) -> id {
+        self.error.as_ptr()
+    }
+
+    pub fn domain(&self) -> String {
+        unsafe { String::from_cocoa(msg_send![*self.error, domain]).unwrap_or_default() }
+    }
+
+    pub fn code(&self) -> NSInteger {
+        unsafe { msg_send![*self.error, code] }
+    }
+
+    pub fn localized_description(&self) -> String {
+        unsafe { String::from_cocoa(msg_send![*self.error, localizedDescription]).unwrap_or_default() }
+    }
+
+    pub fn localized_failure_reason(&self) -> Option<String> {
+        unsafe { String::from_cocoa(msg_send![*self.error, localizedFailureReason]) }
+    }
+
+    /// The `NSDictionary` of extra information, which may be nil.
+    pub fn user_info(&self) -> id {
+        unsafe { NSErrorUserInfo::userInfo(*self.error) }
+    }
+
+    /// The error under `NSUnderlyingErrorKey`, which is also this error's `source`.
+    pub fn underlying_error(&self) -> Option<&NSError> {
+        self.underlying.as_deref()
+    }
+
+    /// All underlying errors: the one under `NSUnderlyingErrorKey`, followed by those under
+    /// `NSMultipleUnderlyingErrorsKey`.
+    pub fn underlying_errors(&self) -> Vec<NSError> {
+        unsafe {
+            let mut errors: Vec<NSError> = self.underlying_error()
+                .map(|underlying| NSError::from_id(underlying.as_id()))
+                .into_iter()
+                .collect();
+            let multiple: id = msg_send![self.user_info(), objectForKey:*"NSMultipleUnderlyingErrorsKey".to_cocoa()];
+            if let Some(multiple) = Vec::<Id>::from_cocoa(multiple) {
+                errors.extend(multiple.iter().map(|error| NSError::from_id(**error)));
+            }
+            errors
+        }
+    }
+
+    /// Maps POSIX errors and the Cocoa file errors to their `io::ErrorKind`, falling back on the
+    /// underlying error's kind and then on `Other`.
+    pub fn io_error_kind(&self) -> io::ErrorKind {
+        let kind = match (&*self.domain(), self.code()) {
+            ("NSPOSIXErrorDomain", code) => io::Error::from_raw_os_error(code as i32).kind(),
+            ("NSCocoaErrorDomain", code) => match code {
+                NSFileNoSuchFileError | NSFileReadNoSuchFileError => io::ErrorKind::NotFound,
+                NSFileReadNoPermissionError | NSFileWriteNoPermissionError |
+                NSFileWriteVolumeReadOnlyError => io::ErrorKind::PermissionDenied,
+                NSFileWriteFileExistsError => io::ErrorKind::AlreadyExists,
+                NSFileReadInvalidFileNameError | NSFileWriteInvalidFileNameError |
+                NSFileReadUnsupportedSchemeError | NSFileWriteUnsupportedSchemeError => io::ErrorKind::InvalidInput,
+                NSFileReadCorruptFileError | NSFileReadInapplicableStringEncodingError |
+                NSFileWriteInapplicableStringEncodingError => io::ErrorKind::InvalidData,
+                _ => io::ErrorKind::Other,
+            },
+            _ => io::ErrorKind::Other,
+        };
+        match (kind, self.underlying_error()) {
+            (io::ErrorKind::Other, Some(underlying)) => underlying.io_error_kind(),
+            _ => kind,
+        }
+    }
+}
+
+impl Clone for NSError {
+    fn clone(&self) -> NSError {
+        unsafe { NSError::from_id(self.as_id()) }
+    }
+}
+
+impl fmt::Debug for NSError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NSError")
+            .field("domain", &self.domain())
+            .field("code", &self.code())
+            .field("description", &self.localized_description())
+            .finish()
+    }
+}
+
+impl fmt::Display for NSError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.localized_description())
+    }
+}
+
+impl error::Error for NSError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.underlying.as_ref().map(|underlying| &**underlying as &(dyn error::Error + 'static))
+    }
+}
+
+/// `NSError` is not `Send`, so only its kind and description carry over.
+impl From<NSError> for io::Error {
+    fn from(error: NSError) -> io::Error {
+        io::Error::new(error.io_error_kind(), error.to_string())
+    }
+}
+
+trait NSErrorUserInfo {
+    unsafe fn userInfo(self) -> id;
+}
+
+impl NSErrorUserInfo for id {
+    unsafe fn userInfo(self) -> id {
+        msg_send![self, userInfo]
+    }
+}
+
+/// Calls an out-error method that returns nil on failure.
+unsafe fn object_or_error<F: FnOnce(*mut id) -> id>(f: F) -> Result<id, NSError> {
+    let mut error = nil;
+    let object = f(&mut error);
+    non_nil(object).ok_or_else(|| NSError::from_out_ptr(error))
+}
+
+/// Calls an out-error method that returns NO on failure.
+unsafe fn success_or_error<F: FnOnce(*mut id) -> BOOL>(f: F) -> Result<(), NSError> {
+    let mut error = nil;
+    if f(&mut error) != NO { Ok(()) } else { Err(NSError::from_out_ptr(error)) }
+}
+
+pub trait NSData: Sized {
+    unsafe fn data(_: Self) -> id {
+        msg_send![class("NSData"), data]
+    }
+
+    unsafe fn dataWithBytes_length_(_: Self, bytes: *const libc::c_void, length: NSUInteger) -> id {
+        msg_send![class("NSData"), dataWithBytes:bytes length:length]
+    }
+
+    unsafe fn dataWithBytesNoCopy_length_(_: Self, bytes: *const libc::c_void, length: NSUInteger) -> id {
+        msg_send![class("NSData"), dataWithBytesNoCopy:bytes length:length]
+    }
+
+    unsafe fn dataWithBytesNoCopy_length_freeWhenDone_(_: Self, bytes: *const libc::c_void,
+                                                      length: NSUInteger, freeWhenDone: BOOL) -> id {
+        msg_send![class("NSData"), dataWithBytesNoCopy:bytes length:length freeWhenDone:freeWhenDone]
+    }
+
+    unsafe fn dataWithContentsOfFile_(_: Self, path: id) -> id {
+        msg_send![class("NSData"), dataWithContentsOfFile:path]
+    }
+
+    unsafe fn dataWithContentsOfFile_options_error_(_: Self, path: id, mask: NSDataReadingOptions,
+                                                    errorPtr: *mut id) -> id {
+        msg_send![class("NSData"), dataWithContentsOfFile:path options:mask error:errorPtr]
+    }
+
+    unsafe fn dataWithContentsOfURL_(_: Self, aURL: id) -> id {
+        msg_send![class("NSData"), dataWithContentsOfURL:aURL]
+    }
+
+    unsafe fn dataWithContentsOfURL_options_error_(_: Self, aURL: id, mask: NSDataReadingOptions,
+                                                   errorPtr: *mut id) -> id {
+        msg_send![class("NSData"), dataWithContentsOfURL:aURL options:mask error:errorPtr]
+    }
+
+    unsafe fn dataWithData_(_: Self, aData: id) -> id {
+        msg_send![class("NSData"), dataWithData:aData]
+    }
+
+    unsafe fn dataWithContentsOfFile_options_(_: Self, path: id, mask: NSDataReadingOptions) -> Result<Id, NSError> {
+        object_or_error(|error| NSData::dataWithContentsOfFile_options_error_(nil, path, mask, error))
+            .map(|data| Id::from_unretained(data))
+    }
+
+    unsafe fn dataWithContentsOfURL_options_(_: Self, aURL: id, mask: NSDataReadingOptions) -> Result<Id, NSError> {
+        object_or_error(|error| NSData::dataWithContentsOfURL_options_error_(nil, aURL, mask, error))
+            .map(|data| Id::from_unretained(data))
+    }
+
+    /// Copies `bytes` into a new autoreleased `NSData`.
+    unsafe fn from_slice(_: Self, bytes: &[u8]) -> id {
+        NSData::dataWithBytes_length_(nil, bytes.as_ptr() as *const libc::c_void, bytes.len() as NSUInteger)
+    }
+
+    /// Hands `bytes` to a new autoreleased `NSData` without copying. The vector is freed by the
+    /// data's deallocator block when the data is released.
+    unsafe fn from_vec(_: Self, bytes: Vec<u8>) -> id {
+        let mut bytes = mem::ManuallyDrop::new(bytes);
+        let (ptr, length, capacity) = (bytes.as_mut_ptr(), bytes.len(), bytes.capacity());
+        let deallocator = ConcreteBlock::new(move |ptr: *const libc::c_void, length: NSUInteger| {
+            drop(Vec::from_raw_parts(ptr as *mut u8, length as usize, capacity));
+        }).copy();
+        let data: id = msg_send![class("NSData"), alloc];
+        let data = data.initWithBytesNoCopy_length_deallocator_(ptr as *const libc::c_void,
+                                                                length as NSUInteger,
+                                                                &*deallocator as *const _ as *mut _);
+        msg_send![data, autorelease]
+    }
+
+    unsafe fn initWithBase64EncodedData_options_(self, base64Data: id, options: NSDataBase64DecodingOptions)
+                                                 -> id;
+    unsafe fn initWithBase64EncodedString_options_(self, base64String: id, options: NSDataBase64DecodingOptions)
+                                                   -> id;
+    unsafe fn initWithBytes_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id;
+    unsafe fn initWithBytesNoCopy_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id;
+    unsafe fn initWithBytesNoCopy_length_deallocator_(self, bytes: *const libc::c_void, length: NSUInteger,
+                                                      deallocator: *mut Block<(*const libc::c_void, NSUInteger), ()>)
+                                                      -> id;
+    unsafe fn initWithBytesNoCopy_length_freeWhenDone_(self, bytes: *const libc::c_void,
+                                                       length: NSUInteger, freeWhenDone: BOOL) -> id;
+    unsafe fn initWithContentsOfFile_(self, path: id) -> id;
+    unsafe fn initWithContentsOfFile_options_error(self, path: id, mask: NSDataReadingOptions, errorPtr: *mut id)
+                                                   -> id;
+    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id;
+    unsafe fn initWithContentsOfURL_options_error_(self, aURL: id, mask: NSDataReadingOptions, errorPtr: *mut id)
+                                                   -> id;
+    unsafe fn initWithData_(self, data: id) -> id;
+    unsafe fn initWithContentsOfFile_options_(self, path: id, mask: NSDataReadingOptions) -> Result<Id, NSError>;
+    unsafe fn initWithContentsOfURL_options_(self, aURL: id, mask: NSDataReadingOptions) -> Result<Id, NSError>;
+
+    unsafe fn bytes(self) -> *const libc::c_void;
+    unsafe fn description(self) -> id;
+    unsafe fn enumerateByteRangesUsingBlock_(self, block: *mut Block<(*const libc::c_void, NSRange, *mut BOOL), ()>);
+    unsafe fn getBytes_length_(self, buffer: *mut libc::c_void, length: NSUInteger);
+    unsafe fn getBytes_range_(self, buffer: *mut libc::c_void, range: NSRange);
+    unsafe fn subdataWithRange_(self, range: NSRange) -> id;
+    unsafe fn rangeOfData_options_range_(self, dataToFind: id, options: NSDataSearchOptions, searchRange: NSRange)
+                                         -> NSRange;
+
+    unsafe fn base64EncodedDataWithOptions_(self, options: NSDataBase64EncodingOptions) -> id;
+    unsafe fn base64EncodedStringWithOptions_(self, options: NSDataBase64EncodingOptions) -> id;
+
+    unsafe fn isEqualToData_(self, otherData: id) -> id;
+    unsafe fn length(self) -> NSUInteger;
+
+    /// Borrows the contents for a lifetime the caller picks, so the caller must keep the data
+    /// alive for `'a` and, for `NSMutableData`, not change it while the slice is in use.
+    unsafe fn as_slice<'a>(self) -> &'a [u8];
+    unsafe fn to_vec(self) -> Vec<u8>;
+    /// Searches like `rangeOfData:options:range:` for bytes that need not be in an `NSData`,
+    /// returning `None` rather than `{NSNotFound, 0}`.
+    unsafe fn range_of_bytes(self, bytes: &[u8], options: NSDataSearchOptions, searchRange: NSRange)
+                             -> Option<NSRange>;
+
+    unsafe fn writeToFile_atomically_(self, path: id, atomically: BOOL) -> BOOL;
+    unsafe fn writeToFile_options_error_(self, path: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL;
+    unsafe fn writeToURL_atomically_(self, aURL: id, atomically: BOOL) -> BOOL;
+    unsafe fn writeToURL_options_error_(self, aURL: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL;
+    unsafe fn writeToFile_options_(self, path: id, mask: NSDataWritingOptions) -> Result<(), NSError>;
+    unsafe fn writeToURL_options_(self, aURL: id, mask: NSDataWritingOptions) -> Result<(), NSError>;
+}
+
+impl NSData for id {
+    unsafe fn initWithBase64EncodedData_options_(self, base64Data: id, options: NSDataBase64DecodingOptions)
+                                                 -> id {
+        msg_send![self, initWithBase64EncodedData:base64Data options:options]
+    }
+
+    unsafe fn initWithBase64EncodedString_options_(self, base64String: id, options: NSDataBase64DecodingOptions)
+                                                   -> id {
+        msg_send![self, initWithBase64EncodedString:base64String options:options]
+    }
+
+    unsafe fn initWithBytes_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id {
+        msg_send![self,initWithBytes:bytes length:length]
+    }
+
+    unsafe fn initWithBytesNoCopy_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id {
+        msg_send![self, initWithBytesNoCopy:bytes length:length]
+    }
+
+    unsafe fn initWithBytesNoCopy_length_deallocator_(self, bytes: *const libc::c_void, length: NSUInteger,
+                                                      deallocator: *mut Block<(*const libc::c_void, NSUInteger), ()>)
+                                                      -> id {
+        msg_send![self, initWithBytesNoCopy:bytes length:length deallocator:deallocator]
+    }
+
+    unsafe fn initWithBytesNoCopy_length_freeWhenDone_(self, bytes: *const libc::c_void,
+                                                       length: NSUInteger, freeWhenDone: BOOL) -> id {
+        msg_send![self, initWithBytesNoCopy:bytes length:length freeWhenDone:freeWhenDone]
+    }
+
+    unsafe fn initWithContentsOfFile_(self, path: id) -> id {
+        msg_send![self, initWithContentsOfFile:path]
+    }
+
+    unsafe fn initWithContentsOfFile_options_error(self, path: id, mask: NSDataReadingOptions, errorPtr: *mut id)
+                                                   -> id {
+        msg_send![self, initWithContentsOfFile:path options:mask error:errorPtr]
+    }
+
+    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id {
+        msg_send![self, initWithContentsOfURL:aURL]
+    }
+
+    unsafe fn initWithContentsOfURL_options_error_(self, aURL: id, mask: NSDataReadingOptions, errorPtr: *mut id)
+                                                   -> id {
+        msg_send![self, initWithContentsOfURL:aURL options:mask error:errorPtr]
+    }
+
+    unsafe fn initWithContentsOfFile_options_(self, path: id, mask: NSDataReadingOptions) -> Result<Id, NSError> {
+        object_or_error(|error| self.initWithContentsOfFile_options_error(path, mask, error))
+            .map(|data| Id::from_retained(data))
+    }
+
+    unsafe fn initWithContentsOfURL_options_(self, aURL: id, mask: NSDataReadingOptions) -> Result<Id, NSError> {
+        object_or_error(|error| self.initWithContentsOfURL_options_error_(aURL, mask, error))
+            .map(|data| Id::from_retained(data))
+    }
+
+    unsafe fn initWithData_(self, data: id) -> id {
+        msg_send![self, initWithData:data]
+    }
+
+    unsafe fn bytes(self) -> *const libc::c_void {
+        msg_send![self, bytes]
     }
 
     unsafe fn description(self) -> id {
         msg_send![self, description]
     }
 
-    unsafe fn descriptionInStringsFileFormat(self) -> id {
-        msg_send![self, descriptionInStringsFileFormat]
+    unsafe fn enumerateByteRangesUsingBlock_(self, block: *mut Block<(*const libc::c_void, NSRange, *mut BOOL), ()>) {
+        msg_send![self, enumerateByteRangesUsingBlock:block]
+    }
+
+    unsafe fn getBytes_length_(self, buffer: *mut libc::c_void, length: NSUInteger) {
+        msg_send![self, getBytes:buffer length:length]
+    }
+
+    unsafe fn getBytes_range_(self, buffer: *mut libc::c_void, range: NSRange) {
+        msg_send![self, getBytes:buffer range:range]
+    }
+
+    unsafe fn subdataWithRange_(self, range: NSRange) -> id {
+        msg_send![self, subdataWithRange:range]
+    }
+
+    unsafe fn rangeOfData_options_range_(self, dataToFind: id, options: NSDataSearchOptions, searchRange: NSRange)
+                                         -> NSRange {
+        msg_send![self, rangeOfData:dataToFind options:options range:searchRange]
+    }
+
+    unsafe fn base64EncodedDataWithOptions_(self, options: NSDataBase64EncodingOptions) -> id {
+        msg_send![self, base64EncodedDataWithOptions:options]
+    }
+
+    unsafe fn base64EncodedStringWithOptions_(self, options: NSDataBase64EncodingOptions) -> id {
+        msg_send![self, base64EncodedStringWithOptions:options]
+    }
+
+    unsafe fn isEqualToData_(self, otherData: id) -> id {
+        msg_send![self, isEqualToData:otherData]
+    }
+
+    unsafe fn length(self) -> NSUInteger {
+        msg_send![self, length]
+    }
+
+    unsafe fn as_slice<'a>(self) -> &'a [u8] {
+        let length = self.length() as usize;
+        if length == 0 {
+            // Empty data may return a null pointer from `bytes`.
+            return &[];
+        }
+        slice::from_raw_parts(self.bytes() as *const u8, length)
+    }
+
+    unsafe fn to_vec(self) -> Vec<u8> {
+        self.as_slice().to_vec()
+    }
+
+    unsafe fn range_of_bytes(self, bytes: &[u8], options: NSDataSearchOptions, searchRange: NSRange)
+                             -> Option<NSRange> {
+        data::range_of_data(self.as_slice(), bytes, options, searchRange)
+    }
+
+    unsafe fn writeToFile_atomically_(self, path: id, atomically: BOOL) -> BOOL {
+        msg_send![self, writeToFile:path atomically:atomically]
+    }
+
+    unsafe fn writeToFile_options_error_(self, path: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL {
+        msg_send![self, writeToFile:path options:mask error:errorPtr]
+    }
+
+    unsafe fn writeToURL_atomically_(self, aURL: id, atomically: BOOL) -> BOOL {
+        msg_send![self, writeToURL:aURL atomically:atomically]
+    }
+
+    unsafe fn writeToURL_options_error_(self, aURL: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL {
+        msg_send![self, writeToURL:aURL options:mask error:errorPtr]
+    }
+
+    unsafe fn writeToFile_options_(self, path: id, mask: NSDataWritingOptions) -> Result<(), NSError> {
+        success_or_error(|error| self.writeToFile_options_error_(path, mask, error))
+    }
+
+    unsafe fn writeToURL_options_(self, aURL: id, mask: NSDataWritingOptions) -> Result<(), NSError> {
+        success_or_error(|error| self.writeToURL_options_error_(aURL, mask, error))
+    }
+}
+
+pub trait NSMutableData: NSData {
+    unsafe fn dataWithCapacity_(_: Self, aNumItems: NSUInteger) -> id {
+        msg_send![class("NSMutableData"), dataWithCapacity:aNumItems]
+    }
+
+    unsafe fn dataWithLength_(_: Self, length: NSUInteger) -> id {
+        msg_send![class("NSMutableData"), dataWithLength:length]
+    }
+
+    /// Copies `bytes` into a new autoreleased `NSMutableData`.
+    unsafe fn mutable_from_slice(_: Self, bytes: &[u8]) -> id {
+        msg_send![class("NSMutableData"), dataWithBytes:bytes.as_ptr() length:bytes.len() as NSUInteger]
+    }
+
+    unsafe fn initWithCapacity_(self, capacity: NSUInteger) -> id;
+    unsafe fn initWithLength_(self, length: NSUInteger) -> id;
+
+    unsafe fn mutableBytes(self) -> *mut libc::c_void;
+    unsafe fn setLength_(self, length: NSUInteger);
+    unsafe fn increaseLengthBy_(self, extraLength: NSUInteger);
+
+    unsafe fn appendBytes_length_(self, bytes: *const libc::c_void, length: NSUInteger);
+    unsafe fn appendData_(self, otherData: id);
+    unsafe fn replaceBytesInRange_withBytes_(self, range: NSRange, bytes: *const libc::c_void);
+    unsafe fn replaceBytesInRange_withBytes_length_(self, range: NSRange, replacementBytes: *const libc::c_void,
+                                                    replacementLength: NSUInteger);
+    unsafe fn resetBytesInRange_(self, range: NSRange);
+    unsafe fn setData_(self, data: id);
+
+    unsafe fn append_slice(self, bytes: &[u8]);
+    /// Replaces the bytes in `range`, which may differ in length from `bytes`.
+    unsafe fn replace_range(self, range: Range<usize>, bytes: &[u8]);
+    /// Borrows the contents mutably for a lifetime the caller picks. The caller must keep the
+    /// data alive for `'a`, not change its length, and not hold any other slice of it meanwhile.
+    unsafe fn as_mut_slice<'a>(self) -> &'a mut [u8];
+}
+
+impl NSMutableData for id {
+    unsafe fn initWithCapacity_(self, capacity: NSUInteger) -> id {
+        msg_send![self, initWithCapacity:capacity]
+    }
+
+    unsafe fn initWithLength_(self, length: NSUInteger) -> id {
+        msg_send![self, initWithLength:length]
+    }
+
+    unsafe fn mutableBytes(self) -> *mut libc::c_void {
+        msg_send![self, mutableBytes]
+    }
+
+    unsafe fn setLength_(self, length: NSUInteger) {
+        msg_send![self, setLength:length]
+    }
+
+    unsafe fn increaseLengthBy_(self, extraLength: NSUInteger) {
+        msg_send![self, increaseLengthBy:extraLength]
+    }
+
+    unsafe fn appendBytes_length_(self, bytes: *const libc::c_void, length: NSUInteger) {
+        msg_send![self, appendBytes:bytes length:length]
+    }
+
+    unsafe fn appendData_(self, otherData: id) {
+        msg_send![self, appendData:otherData]
+    }
+
+    unsafe fn replaceBytesInRange_withBytes_(self, range: NSRange, bytes: *const libc::c_void) {
+        msg_send![self, replaceBytesInRange:range withBytes:bytes]
+    }
+
+    unsafe fn replaceBytesInRange_withBytes_length_(self, range: NSRange, replacementBytes: *const libc::c_void,
+                                                    replacementLength: NSUInteger) {
+        msg_send![self, replaceBytesInRange:range withBytes:replacementBytes length:replacementLength]
+    }
+
+    unsafe fn resetBytesInRange_(self, range: NSRange) {
+        msg_send![self, resetBytesInRange:range]
+    }
+
+    unsafe fn setData_(self, data: id) {
+        msg_send![self, setData:data]
+    }
+
+    unsafe fn append_slice(self, bytes: &[u8]) {
+        self.appendBytes_length_(bytes.as_ptr() as *const libc::c_void, bytes.len() as NSUInteger)
+    }
+
+    unsafe fn replace_range(self, range: Range<usize>, bytes: &[u8]) {
+        assert!(range.start <= range.end && range.end <= self.length() as usize,
+                "range {:?} out of bounds", range);
+        let range = NSRange::new(range.start as NSUInteger, (range.end - range.start) as NSUInteger);
+        self.replaceBytesInRange_withBytes_length_(range, bytes.as_ptr() as *const libc::c_void,
+                                                   bytes.len() as NSUInteger)
+    }
+
+    unsafe fn as_mut_slice<'a>(self) -> &'a mut [u8] {
+        let length = self.length() as usize;
+        if length == 0 {
+            return &mut [];
+        }
+        slice::from_raw_parts_mut(self.mutableBytes() as *mut u8, length)
+    }
+}
+
+bitflags! {
+    pub struct NSDataReadingOptions: libc::c_ulonglong {
+       const NSDataReadingMappedIfSafe = 1 << 0;
+       const NSDataReadingUncached = 1 << 1;
+       const NSDataReadingMappedAlways = 1 << 3;
+    }
+}
+
+bitflags! {
+    pub struct NSDataWritingOptions: libc::c_ulonglong {
+        const NSDataWritingAtomic = 1 << 0;
+        const NSDataWritingWithoutOverwriting = 1 << 1;
+    }
+}
+
+bitflags! {
+    pub struct NSURLBookmarkCreationOptions: libc::c_ulonglong {
+        const NSURLBookmarkCreationMinimalBookmark = 1 << 9;
+        const NSURLBookmarkCreationSuitableForBookmarkFile = 1 << 10;
+        const NSURLBookmarkCreationWithSecurityScope = 1 << 11;
+        const NSURLBookmarkCreationSecurityScopeAllowOnlyReadAccess = 1 << 12;
+        const NSURLBookmarkCreationWithoutImplicitSecurityScope = 1 << 29;
     }
+}
 
-    unsafe fn descriptionWithLocale_(self, locale: id) -> id {
-        msg_send![self, descriptionWithLocale:locale]
+bitflags! {
+    pub struct NSURLBookmarkResolutionOptions: libc::c_ulonglong {
+        const NSURLBookmarkResolutionWithoutUI = 1 << 8;
+        const NSURLBookmarkResolutionWithoutMounting = 1 << 9;
+        const NSURLBookmarkResolutionWithSecurityScope = 1 << 10;
+        const NSURLBookmarkResolutionWithoutImplicitStartAccessing = 1 << 15;
     }
+}
 
-    unsafe fn descriptionWithLocale_indent_(self, locale: id, indent: NSUInteger) -> id {
-        msg_send![self, descriptionWithLocale:locale indent:indent]
+/// Why a path or string could not be converted to an `NSURL`, or an `NSURL` back.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NSURLConversionError {
+    /// The path contains a NUL byte.
+    InvalidPath(PathBuf),
+    /// The string is not a URL that `NSURL` or `url::Url` accepts.
+    InvalidURL(String),
+    /// The URL has a scheme other than `file`, so it has no path.
+    NotAFileURL(String),
+}
+
+impl fmt::Display for NSURLConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NSURLConversionError::InvalidPath(ref path) => write!(f, "invalid path {:?}", path),
+            NSURLConversionError::InvalidURL(ref url) => write!(f, "invalid URL {:?}", url),
+            NSURLConversionError::NotAFileURL(ref url) => write!(f, "not a file URL: {}", url),
+        }
+    }
+}
+
+impl error::Error for NSURLConversionError {}
+
+pub trait NSURL: Sized {
+    unsafe fn alloc(_: Self) -> id {
+        msg_send![class("NSURL"), alloc]
+    }
+
+    unsafe fn fileURLWithPath_(_: Self, path: id) -> id {
+        msg_send![class("NSURL"), fileURLWithPath:path]
+    }
+
+    unsafe fn fileURLWithPath_isDirectory_(_: Self, path: id, isDir: BOOL) -> id {
+        msg_send![class("NSURL"), fileURLWithPath:path isDirectory:isDir]
+    }
+
+    unsafe fn fileURLWithFileSystemRepresentation_isDirectory_relativeToURL_(_: Self, path: *const libc::c_char,
+                                                                              isDir: BOOL, baseURL: id) -> id {
+        msg_send![class("NSURL"), fileURLWithFileSystemRepresentation:path isDirectory:isDir relativeToURL:baseURL]
+    }
+
+    unsafe fn URLWithString_(_: Self, URLString: id) -> id {
+        msg_send![class("NSURL"), URLWithString:URLString]
+    }
+
+    unsafe fn URLWithString_relativeToURL_(_: Self, URLString: id, baseURL: id) -> id {
+        msg_send![class("NSURL"), URLWithString:URLString relativeToURL:baseURL]
+    }
+
+    unsafe fn URLByResolvingBookmarkData_options_relativeToURL_bookmarkDataIsStale_error_(
+        _: Self, bookmarkData: id, options: NSURLBookmarkResolutionOptions, relativeURL: id, isStale: *mut BOOL,
+        error: *mut id) -> id {
+        msg_send![class("NSURL"), URLByResolvingBookmarkData:bookmarkData
+                                  options:options
+                                  relativeToURL:relativeURL
+                                  bookmarkDataIsStale:isStale
+                                  error:error]
+    }
+
+    /// Resolves a bookmark, also returning whether it is stale and should be recreated.
+    unsafe fn URLByResolvingBookmarkData_options_relativeToURL_(
+        _: Self, bookmarkData: id, options: NSURLBookmarkResolutionOptions, relativeURL: id)
+        -> Result<(Id, bool), NSError> {
+        let mut stale = NO;
+        let url = object_or_error(|error| {
+            NSURL::URLByResolvingBookmarkData_options_relativeToURL_bookmarkDataIsStale_error_(
+                nil, bookmarkData, options, relativeURL, &mut stale, error)
+        })?;
+        Ok((Id::from_unretained(url), stale != NO))
+    }
+
+    /// Creates an autoreleased file URL. Relative paths are resolved against the current
+    /// directory.
+    unsafe fn from_path(_: Self, path: &Path) -> Result<id, NSURLConversionError> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| NSURLConversionError::InvalidPath(path.to_owned()))?;
+        let is_dir = path.as_os_str().as_bytes().ends_with(b"/") || path.is_dir();
+        let url = NSURL::fileURLWithFileSystemRepresentation_isDirectory_relativeToURL_(nil, c_path.as_ptr(),
+                                                                                        is_dir as BOOL, nil);
+        non_nil(url).ok_or_else(|| NSURLConversionError::InvalidPath(path.to_owned()))
+    }
+
+    /// Parses `string` with `URLWithString:`, returning an autoreleased URL.
+    unsafe fn from_url_str(_: Self, string: &str) -> Result<id, NSURLConversionError> {
+        let url = NSURL::URLWithString_(nil, *string.to_cocoa());
+        non_nil(url).ok_or_else(|| NSURLConversionError::InvalidURL(string.to_owned()))
+    }
+
+    #[cfg(feature = "url")]
+    unsafe fn from_url(_: Self, url: &Url) -> Result<id, NSURLConversionError> {
+        NSURL::from_url_str(nil, url.as_str())
+    }
+
+    unsafe fn initFileURLWithPath_(self, path: id) -> id;
+    unsafe fn initWithString_(self, URLString: id) -> id;
+
+    unsafe fn absoluteString(self) -> id;
+    unsafe fn absoluteURL(self) -> id;
+    unsafe fn baseURL(self) -> id;
+    unsafe fn relativeString(self) -> id;
+    unsafe fn scheme(self) -> id;
+    unsafe fn user(self) -> id;
+    unsafe fn password(self) -> id;
+    unsafe fn host(self) -> id;
+    unsafe fn port(self) -> id;
+    unsafe fn path(self) -> id;
+    unsafe fn query(self) -> id;
+    unsafe fn fragment(self) -> id;
+    unsafe fn lastPathComponent(self) -> id;
+    unsafe fn pathExtension(self) -> id;
+    unsafe fn pathComponents(self) -> id;
+    unsafe fn fileSystemRepresentation(self) -> *const libc::c_char;
+    unsafe fn isFileURL(self) -> BOOL;
+    unsafe fn hasDirectoryPath(self) -> BOOL;
+    unsafe fn standardizedURL(self) -> id;
+    unsafe fn URLByAppendingPathComponent_(self, pathComponent: id) -> id;
+    unsafe fn URLByDeletingLastPathComponent(self) -> id;
+    unsafe fn URLByAppendingPathExtension_(self, pathExtension: id) -> id;
+
+    unsafe fn bookmarkDataWithOptions_includingResourceValuesForKeys_relativeToURL_error_(
+        self, options: NSURLBookmarkCreationOptions, keys: id, relativeURL: id, error: *mut id) -> id;
+    unsafe fn bookmarkDataWithOptions_includingResourceValuesForKeys_relativeToURL_(
+        self, options: NSURLBookmarkCreationOptions, keys: id, relativeURL: id) -> Result<Id, NSError>;
+    unsafe fn startAccessingSecurityScopedResource(self) -> BOOL;
+    unsafe fn stopAccessingSecurityScopedResource(self);
+
+    /// Returns the path of a file URL.
+    unsafe fn to_path_buf(self) -> Result<PathBuf, NSURLConversionError>;
+    unsafe fn to_url_string(self) -> String;
+    #[cfg(feature = "url")]
+    unsafe fn to_url(self) -> Result<Url, NSURLConversionError>;
+}
+
+impl NSURL for id {
+    unsafe fn initFileURLWithPath_(self, path: id) -> id {
+        msg_send![self, initFileURLWithPath:path]
+    }
+
+    unsafe fn initWithString_(self, URLString: id) -> id {
+        msg_send![self, initWithString:URLString]
+    }
+
+    unsafe fn absoluteString(self) -> id {
+        msg_send![self, absoluteString]
+    }
+
+    unsafe fn absoluteURL(self) -> id {
+        msg_send![self, absoluteURL]
+    }
+
+    unsafe fn baseURL(self) -> id {
+        msg_send![self, baseURL]
+    }
+
+    unsafe fn relativeString(self) -> id {
+        msg_send![self, relativeString]
+    }
+
+    unsafe fn scheme(self) -> id {
+        msg_send![self, scheme]
+    }
+
+    unsafe fn user(self) -> id {
+        msg_send![self, user]
+    }
+
+    unsafe fn password(self) -> id {
+        msg_send![self, password]
+    }
+
+    unsafe fn host(self) -> id {
+        msg_send![self, host]
+    }
+
+    unsafe fn port(self) -> id {
+        msg_send![self, port]
+    }
+
+    unsafe fn path(self) -> id {
+        msg_send![self, path]
+    }
+
+    unsafe fn query(self) -> id {
+        msg_send![self, query]
+    }
+
+    unsafe fn fragment(self) -> id {
+        msg_send![self, fragment]
+    }
+
+    unsafe fn lastPathComponent(self) -> id {
+        msg_send![self, lastPathComponent]
+    }
+
+    unsafe fn pathExtension(self) -> id {
+        msg_send![self, pathExtension]
+    }
+
+    unsafe fn pathComponents(self) -> id {
+        msg_send![self, pathComponents]
+    }
+
+    unsafe fn fileSystemRepresentation(self) -> *const libc::c_char {
+        msg_send![self, fileSystemRepresentation]
+    }
+
+    unsafe fn isFileURL(self) -> BOOL {
+        msg_send![self, isFileURL]
+    }
+
+    unsafe fn hasDirectoryPath(self) -> BOOL {
+        msg_send![self, hasDirectoryPath]
+    }
+
+    unsafe fn standardizedURL(self) -> id {
+        msg_send![self, standardizedURL]
+    }
+
+    unsafe fn URLByAppendingPathComponent_(self, pathComponent: id) -> id {
+        msg_send![self, URLByAppendingPathComponent:pathComponent]
+    }
+
+    unsafe fn URLByDeletingLastPathComponent(self) -> id {
+        msg_send![self, URLByDeletingLastPathComponent]
+    }
+
+    unsafe fn URLByAppendingPathExtension_(self, pathExtension: id) -> id {
+        msg_send![self, URLByAppendingPathExtension:pathExtension]
+    }
+
+    unsafe fn bookmarkDataWithOptions_includingResourceValuesForKeys_relativeToURL_error_(
+        self, options: NSURLBookmarkCreationOptions, keys: id, relativeURL: id, error: *mut id) -> id {
+        msg_send![self, bookmarkDataWithOptions:options
+                        includingResourceValuesForKeys:keys
+                        relativeToURL:relativeURL
+                        error:error]
+    }
+
+    unsafe fn bookmarkDataWithOptions_includingResourceValuesForKeys_relativeToURL_(
+        self, options: NSURLBookmarkCreationOptions, keys: id, relativeURL: id) -> Result<Id, NSError> {
+        object_or_error(|error| {
+            self.bookmarkDataWithOptions_includingResourceValuesForKeys_relativeToURL_error_(options, keys,
+                                                                                               relativeURL, error)
+        }).map(|data| Id::from_unretained(data))
+    }
+
+    unsafe fn startAccessingSecurityScopedResource(self) -> BOOL {
+        msg_send![self, startAccessingSecurityScopedResource]
+    }
+
+    unsafe fn stopAccessingSecurityScopedResource(self) {
+        msg_send![self, stopAccessingSecurityScopedResource]
+    }
+
+    unsafe fn to_path_buf(self) -> Result<PathBuf, NSURLConversionError> {
+        if self.isFileURL() == NO {
+            return Err(NSURLConversionError::NotAFileURL(self.to_url_string()));
+        }
+        // The file system representation keeps bytes that are not valid UTF-8.
+        let path = self.fileSystemRepresentation();
+        if path.is_null() {
+            return Err(NSURLConversionError::InvalidURL(self.to_url_string()));
+        }
+        Ok(PathBuf::from(OsStr::from_bytes(CStr::from_ptr(path).to_bytes())))
+    }
+
+    unsafe fn to_url_string(self) -> String {
+        String::from_cocoa(self.absoluteString()).unwrap_or_default()
+    }
+
+    #[cfg(feature = "url")]
+    unsafe fn to_url(self) -> Result<Url, NSURLConversionError> {
+        let string = self.to_url_string();
+        Url::parse(&string).map_err(|_| NSURLConversionError::InvalidURL(string))
+    }
+}
+
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NSSearchPathDirectory {
+    NSApplicationDirectory = 1,
+    NSDemoApplicationDirectory = 2,
+    NSDeveloperApplicationDirectory = 3,
+    NSAdminApplicationDirectory = 4,
+    NSLibraryDirectory = 5,
+    NSDeveloperDirectory = 6,
+    NSUserDirectory = 7,
+    NSDocumentationDirectory = 8,
+    NSDocumentDirectory = 9,
+    NSCoreServiceDirectory = 10,
+    NSAutosavedInformationDirectory = 11,
+    NSDesktopDirectory = 12,
+    NSCachesDirectory = 13,
+    NSApplicationSupportDirectory = 14,
+    NSDownloadsDirectory = 15,
+    NSInputMethodsDirectory = 16,
+    NSMoviesDirectory = 17,
+    NSMusicDirectory = 18,
+    NSPicturesDirectory = 19,
+    NSPrinterDescriptionDirectory = 20,
+    NSSharedPublicDirectory = 21,
+    NSPreferencePanesDirectory = 22,
+    NSItemReplacementDirectory = 99,
+    NSAllApplicationsDirectory = 100,
+    NSAllLibrariesDirectory = 101,
+    NSTrashDirectory = 102,
+}
+
+bitflags! {
+    pub struct NSSearchPathDomainMask: libc::c_ulonglong {
+        const NSUserDomainMask = 1 << 0;
+        const NSLocalDomainMask = 1 << 1;
+        const NSNetworkDomainMask = 1 << 2;
+        const NSSystemDomainMask = 1 << 3;
+        const NSAllDomainsMask = 0x0ffff;
+    }
+}
+
+bitflags! {
+    pub struct NSDirectoryEnumerationOptions: libc::c_ulonglong {
+        const NSDirectoryEnumerationSkipsSubdirectoryDescendants = 1 << 0;
+        const NSDirectoryEnumerationSkipsPackageDescendants = 1 << 1;
+        const NSDirectoryEnumerationSkipsHiddenFiles = 1 << 2;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileType {
+    Regular,
+    Directory,
+    SymbolicLink,
+    Socket,
+    CharacterSpecial,
+    BlockSpecial,
+    Unknown,
+}
+
+impl FileType {
+    /// Decodes one of the `NSFileType*` strings.
+    pub unsafe fn from_cocoa_string(fileType: id) -> FileType {
+        match String::from_cocoa(fileType).as_deref() {
+            Some("NSFileTypeRegular") => FileType::Regular,
+            Some("NSFileTypeDirectory") => FileType::Directory,
+            Some("NSFileTypeSymbolicLink") => FileType::SymbolicLink,
+            Some("NSFileTypeSocket") => FileType::Socket,
+            Some("NSFileTypeCharacterSpecial") => FileType::CharacterSpecial,
+            Some("NSFileTypeBlockSpecial") => FileType::BlockSpecial,
+            _ => FileType::Unknown,
+        }
+    }
+}
+
+/// The attributes `attributesOfItemAtPath:error:` reports, decoded from the dictionary.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileAttributes {
+    pub file_type: FileType,
+    pub size: u64,
+    pub posix_permissions: u32,
+    pub owner_account_name: Option<String>,
+    pub owner_account_id: Option<u32>,
+    pub group_owner_account_name: Option<String>,
+    pub group_owner_account_id: Option<u32>,
+    pub creation_date: Option<SystemTime>,
+    pub modification_date: Option<SystemTime>,
+    pub system_number: NSInteger,
+    pub system_file_number: NSUInteger,
+    pub extension_hidden: bool,
+    pub immutable: bool,
+    pub append_only: bool,
+}
+
+impl FileAttributes {
+    pub unsafe fn from_dictionary(attributes: id) -> FileAttributes {
+        let date = |date: id| non_nil(date).and_then(|date| date.systemTime());
+        FileAttributes {
+            file_type: FileType::from_cocoa_string(attributes.fileType()),
+            size: attributes.fileSize(),
+            posix_permissions: attributes.filePosixPermissions() as u32,
+            owner_account_name: String::from_cocoa(attributes.fileOwnerAccountName()),
+            owner_account_id: u32::from_cocoa(attributes.fileOwnerAccountID()),
+            group_owner_account_name: String::from_cocoa(attributes.fileGroupOwnerAccountName()),
+            group_owner_account_id: u32::from_cocoa(attributes.fileGroupOwnerAccountID()),
+            creation_date: date(attributes.fileCreationDate()),
+            modification_date: date(attributes.fileModificationDate()),
+            system_number: attributes.fileSystemNumber(),
+            system_file_number: attributes.fileSystemFileNumber(),
+            extension_hidden: attributes.fileExtensionHidden() != NO,
+            immutable: attributes.fileIsImmutable() != NO,
+            append_only: attributes.fileIsAppendOnly() != NO,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::Regular
+    }
+}
+
+/// Converts a path-valued `NSString`, keeping bytes that are not valid UTF-8.
+unsafe fn path_from_string(string: id) -> Option<PathBuf> {
+    let path: *const libc::c_char = msg_send![string, fileSystemRepresentation];
+    if path.is_null() {
+        None
+    } else {
+        Some(PathBuf::from(OsStr::from_bytes(CStr::from_ptr(path).to_bytes())))
+    }
+}
+
+unsafe fn file_url(path: &Path) -> Result<id, NSError> {
+    NSURL::from_path(nil, path).map_err(|_| NSError::new("NSCocoaErrorDomain", NSFileReadInvalidFileNameError, nil))
+}
+
+pub trait NSFileManager: Sized {
+    unsafe fn defaultManager(_: Self) -> id {
+        msg_send![class("NSFileManager"), defaultManager]
+    }
+
+    unsafe fn stringWithFileSystemRepresentation_length_(self, string: *const libc::c_char, len: NSUInteger) -> id;
+
+    unsafe fn attributesOfItemAtPath_error_(self, path: id, error: *mut id) -> id;
+    unsafe fn contentsOfDirectoryAtPath_error_(self, path: id, error: *mut id) -> id;
+    unsafe fn contentsOfDirectoryAtURL_includingPropertiesForKeys_options_error_(
+        self, url: id, keys: id, mask: NSDirectoryEnumerationOptions, error: *mut id) -> id;
+    unsafe fn enumeratorAtPath_(self, path: id) -> id;
+    unsafe fn enumeratorAtURL_includingPropertiesForKeys_options_errorHandler_(
+        self, url: id, keys: id, mask: NSDirectoryEnumerationOptions, handler: *mut Block<(id, id), BOOL>) -> id;
+    unsafe fn createDirectoryAtPath_withIntermediateDirectories_attributes_error_(
+        self, path: id, createIntermediates: BOOL, attributes: id, error: *mut id) -> BOOL;
+    unsafe fn createDirectoryAtURL_withIntermediateDirectories_attributes_error_(
+        self, url: id, createIntermediates: BOOL, attributes: id, error: *mut id) -> BOOL;
+    unsafe fn createFileAtPath_contents_attributes_(self, path: id, data: id, attributes: id) -> BOOL;
+    unsafe fn copyItemAtPath_toPath_error_(self, srcPath: id, dstPath: id, error: *mut id) -> BOOL;
+    unsafe fn copyItemAtURL_toURL_error_(self, srcURL: id, dstURL: id, error: *mut id) -> BOOL;
+    unsafe fn moveItemAtPath_toPath_error_(self, srcPath: id, dstPath: id, error: *mut id) -> BOOL;
+    unsafe fn moveItemAtURL_toURL_error_(self, srcURL: id, dstURL: id, error: *mut id) -> BOOL;
+    unsafe fn removeItemAtPath_error_(self, path: id, error: *mut id) -> BOOL;
+    unsafe fn removeItemAtURL_error_(self, url: id, error: *mut id) -> BOOL;
+    unsafe fn trashItemAtURL_resultingItemURL_error_(self, url: id, outResultingURL: *mut id, error: *mut id) -> BOOL;
+    unsafe fn fileExistsAtPath_(self, path: id) -> BOOL;
+    unsafe fn fileExistsAtPath_isDirectory_(self, path: id, isDirectory: *mut BOOL) -> BOOL;
+    unsafe fn isReadableFileAtPath_(self, path: id) -> BOOL;
+    unsafe fn isWritableFileAtPath_(self, path: id) -> BOOL;
+    unsafe fn isDeletableFileAtPath_(self, path: id) -> BOOL;
+    unsafe fn URLsForDirectory_inDomains_(self, directory: NSSearchPathDirectory,
+                                          domainMask: NSSearchPathDomainMask) -> id;
+    unsafe fn URLForDirectory_inDomain_appropriateForURL_create_error_(
+        self, directory: NSSearchPathDirectory, domain: NSSearchPathDomainMask, url: id, shouldCreate: BOOL,
+        error: *mut id) -> id;
+    unsafe fn currentDirectoryPath(self) -> id;
+    unsafe fn changeCurrentDirectoryPath_(self, path: id) -> BOOL;
+    unsafe fn temporaryDirectory(self) -> id;
+    unsafe fn homeDirectoryForCurrentUser(self) -> id;
+
+    /// Returns an autoreleased `NSString` for `path`, which `NSFileManager` maps back to the same
+    /// bytes even if they are not valid UTF-8.
+    unsafe fn path_string(self, path: &Path) -> id;
+
+    unsafe fn attributes_of_item(self, path: &Path) -> Result<FileAttributes, NSError>;
+    /// Lists a directory's entries, not including `.` and `..`, as paths joined onto `path`.
+    unsafe fn contents_of_directory(self, path: &Path) -> Result<Vec<PathBuf>, NSError>;
+    /// Walks a directory recursively, unless `options` says otherwise.
+    unsafe fn walk_directory(self, path: &Path, options: NSDirectoryEnumerationOptions)
+                             -> Result<NSDirectoryEntries, NSError>;
+    unsafe fn create_directory(self, path: &Path, intermediates: bool) -> Result<(), NSError>;
+    unsafe fn copy_item(self, from: &Path, to: &Path) -> Result<(), NSError>;
+    unsafe fn move_item(self, from: &Path, to: &Path) -> Result<(), NSError>;
+    unsafe fn remove_item(self, path: &Path) -> Result<(), NSError>;
+    /// Moves an item to the Trash, returning where it ended up.
+    unsafe fn trash_item(self, path: &Path) -> Result<PathBuf, NSError>;
+    unsafe fn file_exists(self, path: &Path) -> bool;
+
+    unsafe fn urls_for_directory(self, directory: NSSearchPathDirectory, domains: NSSearchPathDomainMask)
+                                 -> Vec<PathBuf>;
+    /// The user's Application Support directory, which may not exist yet.
+    unsafe fn application_support_directory(self) -> Option<PathBuf>;
+    /// The user's Caches directory.
+    unsafe fn caches_directory(self) -> Option<PathBuf>;
+}
+
+impl NSFileManager for id {
+    unsafe fn stringWithFileSystemRepresentation_length_(self, string: *const libc::c_char, len: NSUInteger) -> id {
+        msg_send![self, stringWithFileSystemRepresentation:string length:len]
+    }
+
+    unsafe fn attributesOfItemAtPath_error_(self, path: id, error: *mut id) -> id {
+        msg_send![self, attributesOfItemAtPath:path error:error]
+    }
+
+    unsafe fn contentsOfDirectoryAtPath_error_(self, path: id, error: *mut id) -> id {
+        msg_send![self, contentsOfDirectoryAtPath:path error:error]
     }
+
+    unsafe fn contentsOfDirectoryAtURL_includingPropertiesForKeys_options_error_(
+        self, url: id, keys: id, mask: NSDirectoryEnumerationOptions, error: *mut id) -> id {
+        msg_send![self, contentsOfDirectoryAtURL:url includingPropertiesForKeys:keys options:mask error:error]
+    }
+
+    unsafe fn enumeratorAtPath_(self, path: id) -> id {
+        msg_send![self, enumeratorAtPath:path]
+    }
+
+    unsafe fn enumeratorAtURL_includingPropertiesForKeys_options_errorHandler_(
+        self, url: id, keys: id, mask: NSDirectoryEnumerationOptions, handler: *mut Block<(id, id), BOOL>) -> id {
+        msg_send![self, enumeratorAtURL:url includingPropertiesForKeys:keys options:mask errorHandler:handler]
+    }
+
+    unsafe fn createDirectoryAtPath_withIntermediateDirectories_attributes_error_(
+        self, path: id, createIntermediates: BOOL, attributes: id, error: *mut id) -> BOOL {
+        msg_send![self, createDirectoryAtPath:path
+                        withIntermediateDirectories:createIntermediates
+                        attributes:attributes
+                        error:error]
+    }
+
+    unsafe fn createDirectoryAtURL_withIntermediateDirectories_attributes_error_(
+        self, url: id, createIntermediates: BOOL, attributes: id, error: *mut id) -> BOOL {
+        msg_send![self, createDirectoryAtURL:url
+                        withIntermediateDirectories:createIntermediates
+                        attributes:attributes
+                        error:error]
+    }
+
+    unsafe fn createFileAtPath_contents_attributes_(self, path: id, data: id, attributes: id) -> BOOL {
+        msg_send![self, createFileAtPath:path contents:data attributes:attributes]
+    }
+
+    unsafe fn copyItemAtPath_toPath_error_(self, srcPath: id, dstPath: id, error: *mut id) -> BOOL {
+        msg_send![self, copyItemAtPath:srcPath toPath:dstPath error:error]
+    }
+
+    unsafe fn copyItemAtURL_toURL_error_(self, srcURL: id, dstURL: id, error: *mut id) -> BOOL {
+        msg_send![self, copyItemAtURL:srcURL toURL:dstURL error:error]
+    }
+
+    unsafe fn moveItemAtPath_toPath_error_(self, srcPath: id, dstPath: id, error: *mut id) -> BOOL {
+        msg_send![self, moveItemAtPath:srcPath toPath:dstPath error:error]
+    }
+
+    unsafe fn moveItemAtURL_toURL_error_(self, srcURL: id, dstURL: id, error: *mut id) -> BOOL {
+        msg_send![self, moveItemAtURL:srcURL toURL:dstURL error:error]
+    }
+
+    unsafe fn removeItemAtPath_error_(self, path: id, error: *mut id) -> BOOL {
+        msg_send![self, removeItemAtPath:path error:error]
+    }
+
+    unsafe fn removeItemAtURL_error_(self, url: id, error: *mut id) -> BOOL {
+        msg_send![self, removeItemAtURL:url error:error]
+    }
+
+    unsafe fn trashItemAtURL_resultingItemURL_error_(self, url: id, outResultingURL: *mut id, error: *mut id) -> BOOL {
+        msg_send![self, trashItemAtURL:url resultingItemURL:outResultingURL error:error]
+    }
+
+    unsafe fn fileExistsAtPath_(self, path: id) -> BOOL {
+        msg_send![self, fileExistsAtPath:path]
+    }
+
+    unsafe fn fileExistsAtPath_isDirectory_(self, path: id, isDirectory: *mut BOOL) -> BOOL {
+        msg_send![self, fileExistsAtPath:path isDirectory:isDirectory]
+    }
+
+    unsafe fn isReadableFileAtPath_(self, path: id) -> BOOL {
+        msg_send![self, isReadableFileAtPath:path]
+    }
+
+    unsafe fn isWritableFileAtPath_(self, path: id) -> BOOL {
+        msg_send![self, isWritableFileAtPath:path]
+    }
+
+    unsafe fn isDeletableFileAtPath_(self, path: id) -> BOOL {
+        msg_send![self, isDeletableFileAtPath:path]
+    }
+
+    unsafe fn URLsForDirectory_inDomains_(self, directory: NSSearchPathDirectory,
+                                          domainMask: NSSearchPathDomainMask) -> id {
+        msg_send![self, URLsForDirectory:directory inDomains:domainMask]
+    }
+
+    unsafe fn URLForDirectory_inDomain_appropriateForURL_create_error_(
+        self, directory: NSSearchPathDirectory, domain: NSSearchPathDomainMask, url: id, shouldCreate: BOOL,
+        error: *mut id) -> id {
+        msg_send![self, URLForDirectory:directory
+                        inDomain:domain
+                        appropriateForURL:url
+                        create:shouldCreate
+                        error:error]
+    }
+
+    unsafe fn currentDirectoryPath(self) -> id {
+        msg_send![self, currentDirectoryPath]
+    }
+
+    unsafe fn changeCurrentDirectoryPath_(self, path: id) -> BOOL {
+        msg_send![self, changeCurrentDirectoryPath:path]
+    }
+
+    unsafe fn temporaryDirectory(self) -> id {
+        msg_send![self, temporaryDirectory]
+    }
+
+    unsafe fn homeDirectoryForCurrentUser(self) -> id {
+        msg_send![self, homeDirectoryForCurrentUser]
+    }
+
+    unsafe fn path_string(self, path: &Path) -> id {
+        let bytes = path.as_os_str().as_bytes();
+        self.stringWithFileSystemRepresentation_length_(bytes.as_ptr() as *const libc::c_char,
+                                                         bytes.len() as NSUInteger)
+    }
+
+    unsafe fn attributes_of_item(self, path: &Path) -> Result<FileAttributes, NSError> {
+        let path = self.path_string(path);
+        let attributes = object_or_error(|error| self.attributesOfItemAtPath_error_(path, error))?;
+        Ok(FileAttributes::from_dictionary(attributes))
+    }
+
+    unsafe fn contents_of_directory(self, path: &Path) -> Result<Vec<PathBuf>, NSError> {
+        let string = self.path_string(path);
+        let names = object_or_error(|error| self.contentsOfDirectoryAtPath_error_(string, error))?;
+        Ok(names.array_iter().filter_map(|name| path_from_string(name)).map(|name| path.join(name)).collect())
+    }
+
+    unsafe fn walk_directory(self, path: &Path, options: NSDirectoryEnumerationOptions)
+                             -> Result<NSDirectoryEntries, NSError> {
+        let url = file_url(path)?;
+        let error = Rc::new(RefCell::new(None));
+        let handler_error = error.clone();
+        let handler = ConcreteBlock::new(move |_: id, error: id| -> BOOL {
+            *handler_error.borrow_mut() = Some(NSError::from_out_ptr(error));
+            NO
+        }).copy();
+        let enumerator = self.enumeratorAtURL_includingPropertiesForKeys_options_errorHandler_(
+            url, nil, options, &*handler as *const _ as *mut _);
+        let enumerator = non_nil(enumerator).ok_or_else(|| {
+            error.borrow_mut().take().unwrap_or_else(|| NSError::from_out_ptr(nil))
+        })?;
+        Ok(NSDirectoryEntries {
+            enumerator: Id::from_unretained(enumerator),
+            error,
+            _handler: handler,
+            finished: false,
+        })
+    }
+
+    unsafe fn create_directory(self, path: &Path, intermediates: bool) -> Result<(), NSError> {
+        let path = self.path_string(path);
+        success_or_error(|error| {
+            self.createDirectoryAtPath_withIntermediateDirectories_attributes_error_(path, intermediates as BOOL,
+                                                                                     nil, error)
+        })
+    }
+
+    unsafe fn copy_item(self, from: &Path, to: &Path) -> Result<(), NSError> {
+        let (from, to) = (self.path_string(from), self.path_string(to));
+        success_or_error(|error| self.copyItemAtPath_toPath_error_(from, to, error))
+    }
+
+    unsafe fn move_item(self, from: &Path, to: &Path) -> Result<(), NSError> {
+        let (from, to) = (self.path_string(from), self.path_string(to));
+        success_or_error(|error| self.moveItemAtPath_toPath_error_(from, to, error))
+    }
+
+    unsafe fn remove_item(self, path: &Path) -> Result<(), NSError> {
+        let path = self.path_string(path);
+        success_or_error(|error| self.removeItemAtPath_error_(path, error))
+    }
+
+    unsafe fn trash_item(self, path: &Path) -> Result<PathBuf, NSError> {
+        let url = file_url(path)?;
+        let mut resulting = nil;
+        success_or_error(|error| self.trashItemAtURL_resultingItemURL_error_(url, &mut resulting, error))?;
+        non_nil(resulting).and_then(|url| url.to_path_buf().ok())
+            .ok_or_else(|| NSError::from_out_ptr(nil))
+    }
+
+    unsafe fn file_exists(self, path: &Path) -> bool {
+        self.fileExistsAtPath_(self.path_string(path)) != NO
+    }
+
+    unsafe fn urls_for_directory(self, directory: NSSearchPathDirectory, domains: NSSearchPathDomainMask)
+                                 -> Vec<PathBuf> {
+        self.URLsForDirectory_inDomains_(directory, domains).array_iter()
+            .filter_map(|url| url.to_path_buf().ok())
+            .collect()
+    }
+
+    unsafe fn application_support_directory(self) -> Option<PathBuf> {
+        self.urls_for_directory(NSSearchPathDirectory::NSApplicationSupportDirectory, NSUserDomainMask)
+            .into_iter().next()
+    }
+
+    unsafe fn caches_directory(self) -> Option<PathBuf> {
+        self.urls_for_directory(NSSearchPathDirectory::NSCachesDirectory, NSUserDomainMask).into_iter().next()
+    }
+}
+
+pub trait NSDirectoryEnumerator: Sized {
+    unsafe fn nextObject(self) -> id;
+    unsafe fn skipDescendants(self);
+    unsafe fn level(self) -> NSUInteger;
+    unsafe fn fileAttributes(self) -> id;
+    unsafe fn directoryAttributes(self) -> id;
+}
+
+impl NSDirectoryEnumerator for id {
+    unsafe fn nextObject(self) -> id {
+        msg_send![self, nextObject]
+    }
+
+    unsafe fn skipDescendants(self) {
+        msg_send![self, skipDescendants]
+    }
+
+    unsafe fn level(self) -> NSUInteger {
+        msg_send![self, level]
+    }
+
+    unsafe fn fileAttributes(self) -> id {
+        msg_send![self, fileAttributes]
+    }
+
+    unsafe fn directoryAttributes(self) -> id {
+        msg_send![self, directoryAttributes]
+    }
+}
+
+/// The paths under a directory, from `NSFileManager::walk_directory`. Enumeration stops at the
+/// first error.
+pub struct NSDirectoryEntries {
+    enumerator: Id,
+    error: Rc<RefCell<Option<NSError>>>,
+    _handler: RcBlock<(id, id), BOOL>,
+    finished: bool,
+}
+
+impl NSDirectoryEntries {
+    /// Stops the walk from descending into the directory that was just returned.
+    pub fn skip_descendants(&self) {
+        unsafe { self.enumerator.skipDescendants() }
+    }
+
+    /// How deep the last returned path is, starting from 1.
+    pub fn depth(&self) -> usize {
+        unsafe { self.enumerator.level() as usize }
+    }
+}
+
+impl Iterator for NSDirectoryEntries {
+    type Item = Result<PathBuf, NSError>;
+
+    fn next(&mut self) -> Option<Result<PathBuf, NSError>> {
+        while !self.finished {
+            let url = unsafe { self.enumerator.nextObject() };
+            if let Some(error) = self.error.borrow_mut().take() {
+                self.finished = true;
+                return Some(Err(error));
+            }
+            if url.is_null() {
+                self.finished = true;
+            } else if let Ok(path) = unsafe { url.to_path_buf() } {
+                return Some(Ok(path));
+            }
+        }
+        None
+    }
+}
+
+pub trait NSNotification: Sized {
+    unsafe fn name(self) -> id;
+    unsafe fn object(self) -> id;
+    unsafe fn userInfo(self) -> id;
+}
+
+impl NSNotification for id {
+    unsafe fn name(self) -> id {
+        msg_send![self, name]
+    }
+
+    unsafe fn object(self) -> id {
+        msg_send![self, object]
+    }
+
+    unsafe fn userInfo(self) -> id {
+        msg_send![self, userInfo]
+    }
+}
+
+pub trait NSNotificationCenter: Sized {
+    unsafe fn defaultCenter(_: Self) -> id {
+        msg_send![class("NSNotificationCenter"), defaultCenter]
+    }
+
+    unsafe fn addObserver_selector_name_object_(self, observer: id, aSelector: SEL, aName: id, anObject: id);
+    unsafe fn addObserverForName_object_queue_usingBlock_(self, name: id, obj: id, queue: id,
+                                                          block: *mut Block<(id,), ()>) -> id;
+    unsafe fn removeObserver_(self, observer: id);
+    unsafe fn removeObserver_name_object_(self, observer: id, aName: id, anObject: id);
+    unsafe fn postNotification_(self, notification: id);
+    unsafe fn postNotificationName_object_(self, aName: id, anObject: id);
+    unsafe fn postNotificationName_object_userInfo_(self, aName: id, anObject: id, aUserInfo: id);
+
+    /// Calls `f` with each notification named `name` from `object`, either of which may be nil to
+    /// match any, until the returned observer is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `f` runs on the posting thread but need not be `Send`, so the caller must make sure that
+    /// matching notifications are only posted on the thread that calls `observe`.
+    unsafe fn observe<F: Fn(id) + 'static>(self, name: id, object: id, f: F) -> NotificationObserver;
+}
+
+impl NSNotificationCenter for id {
+    unsafe fn addObserver_selector_name_object_(self, observer: id, aSelector: SEL, aName: id, anObject: id) {
+        msg_send![self, addObserver:observer selector:aSelector name:aName object:anObject]
+    }
+
+    unsafe fn addObserverForName_object_queue_usingBlock_(self, name: id, obj: id, queue: id,
+                                                          block: *mut Block<(id,), ()>) -> id {
+        msg_send![self, addObserverForName:name object:obj queue:queue usingBlock:block]
+    }
+
+    unsafe fn removeObserver_(self, observer: id) {
+        msg_send![self, removeObserver:observer]
+    }
+
+    unsafe fn removeObserver_name_object_(self, observer: id, aName: id, anObject: id) {
+        msg_send![self, removeObserver:observer name:aName object:anObject]
+    }
+
+    unsafe fn postNotification_(self, notification: id) {
+        msg_send![self, postNotification:notification]
+    }
+
+    unsafe fn postNotificationName_object_(self, aName: id, anObject: id) {
+        msg_send![self, postNotificationName:aName object:anObject]
+    }
+
+    unsafe fn postNotificationName_object_userInfo_(self, aName: id, anObject: id, aUserInfo: id) {
+        msg_send![self, postNotificationName:aName object:anObject userInfo:aUserInfo]
+    }
+
+    unsafe fn observe<F: Fn(id) + 'static>(self, name: id, object: id, f: F) -> NotificationObserver {
+        let block = ConcreteBlock::new(move |notification: id| f(notification)).copy();
+        let token = self.addObserverForName_object_queue_usingBlock_(name, object, nil,
+                                                                     &*block as *const _ as *mut _);
+        NotificationObserver {
+            center: Id::from_unretained(self),
+            token: Id::from_unretained(token),
+            _block: block,
+        }
+    }
+}
+
+/// A block-based notification observer, removed from its center on drop.
+pub struct NotificationObserver {
+    center: Id,
+    token: Id,
+    _block: RcBlock<(id,), ()>,
 }
 
-bitflags! {
-    pub struct NSEnumerationOptions: libc::c_ulonglong {
-        const NSEnumerationConcurrent = 1 << 0;
-        const NSEnumerationReverse = 1 << 1;
+impl Drop for NotificationObserver {
+    fn drop(&mut self) {
+        unsafe { self.center.removeObserver_(*self.token) }
     }
 }
 
-pub type NSComparator = *mut Block<(id, id), NSComparisonResult>;
-
-#[repr(isize)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum NSComparisonResult {
-    NSOrderedAscending = -1,
-    NSOrderedSame = 0,
-    NSOrderedDescending = 1
+#[link(name = "Foundation", kind = "framework")]
+extern {
+    pub static NSUserDefaultsDidChangeNotification: id;
+    pub static NSGlobalDomain: id;
+    pub static NSArgumentDomain: id;
+    pub static NSRegistrationDomain: id;
 }
 
-pub trait NSString: Sized {
-    unsafe fn alloc(_: Self) -> id {
-        msg_send![class("NSString"), alloc]
+/// `objectForKey:`, `setObject:forKey:` and `removeObjectForKey:` are bound on `NSDictionary` and
+/// `NSMutableDictionary`, which send the same selectors; `get_value`, `set_value` and
+/// `remove_value` wrap them.
+pub trait NSUserDefaults: Sized {
+    unsafe fn standardUserDefaults(_: Self) -> id {
+        msg_send![class("NSUserDefaults"), standardUserDefaults]
     }
 
-    unsafe fn stringByAppendingString_(self, other: id) -> id;
-    unsafe fn init_str(self, string: &str) -> Self;
-    unsafe fn UTF8String(self) -> *const libc::c_char;
-    unsafe fn len(self) -> usize;
-    unsafe fn isEqualToString(self, &str) -> bool;
+    unsafe fn resetStandardUserDefaults(_: Self) {
+        msg_send![class("NSUserDefaults"), resetStandardUserDefaults]
+    }
+
+    unsafe fn alloc(_: Self) -> id {
+        msg_send![class("NSUserDefaults"), alloc]
+    }
+
+    /// Opens the defaults of a suite, such as an app group. Returns `None` for names that
+    /// `initWithSuiteName:` rejects: the app's own bundle identifier and `NSGlobalDomain`.
+    unsafe fn with_suite_name(_: Self, name: &str) -> Option<Id> {
+        let defaults = NSUserDefaults::alloc(nil).initWithSuiteName_(*name.to_cocoa());
+        non_nil(defaults).map(|defaults| Id::from_retained(defaults))
+    }
+
+    unsafe fn initWithSuiteName_(self, suitename: id) -> id;
+
+    unsafe fn stringForKey_(self, defaultName: id) -> id;
+    unsafe fn arrayForKey_(self, defaultName: id) -> id;
+    unsafe fn dictionaryForKey_(self, defaultName: id) -> id;
+    unsafe fn dataForKey_(self, defaultName: id) -> id;
+    unsafe fn stringArrayForKey_(self, defaultName: id) -> id;
+    unsafe fn integerForKey_(self, defaultName: id) -> NSInteger;
+    unsafe fn floatForKey_(self, defaultName: id) -> f32;
+    unsafe fn doubleForKey_(self, defaultName: id) -> f64;
+    unsafe fn boolForKey_(self, defaultName: id) -> BOOL;
+    unsafe fn URLForKey_(self, defaultName: id) -> id;
+    unsafe fn setInteger_forKey_(self, value: NSInteger, defaultName: id);
+    unsafe fn setFloat_forKey_(self, value: f32, defaultName: id);
+    unsafe fn setDouble_forKey_(self, value: f64, defaultName: id);
+    unsafe fn setBool_forKey_(self, value: BOOL, defaultName: id);
+    unsafe fn setURL_forKey_(self, url: id, defaultName: id);
+    unsafe fn registerDefaults_(self, registrationDictionary: id);
+    unsafe fn addSuiteNamed_(self, suiteName: id);
+    unsafe fn removeSuiteNamed_(self, suiteName: id);
+    unsafe fn dictionaryRepresentation(self) -> id;
+    unsafe fn volatileDomainNames(self) -> id;
+    unsafe fn persistentDomainForName_(self, domainName: id) -> id;
+    unsafe fn setPersistentDomain_forName_(self, domain: id, domainName: id);
+    unsafe fn removePersistentDomainForName_(self, domainName: id);
+    unsafe fn objectIsForcedForKey_(self, key: id) -> BOOL;
+    unsafe fn synchronize(self) -> BOOL;
+
+    /// Returns `None` if the key is unset or its value does not convert to `T`.
+    unsafe fn get_value<T: FromCocoa>(self, key: &str) -> Option<T>;
+    unsafe fn set_value<T: ToCocoa + ?Sized>(self, key: &str, value: &T);
+    unsafe fn remove_value(self, key: &str);
+    unsafe fn get_data(self, key: &str) -> Option<Vec<u8>>;
+    unsafe fn set_data(self, key: &str, bytes: &[u8]);
+    /// Sets fallback values, which are not saved, for keys that are unset. `defaults` must convert
+    /// to a dictionary.
+    unsafe fn register_defaults<T: ToCocoa + ?Sized>(self, defaults: &T);
+    /// Calls `f` whenever these defaults change, until the returned observer is dropped.
+    ///
+    /// # Safety
+    ///
+    /// As with `NSNotificationCenter::observe`, the defaults must only be changed on this thread.
+    unsafe fn observe_changes<F: Fn() + 'static>(self, f: F) -> NotificationObserver;
+
+    /// Reads a value stored by `set_struct`, returning `Ok(None)` if the key is unset.
+    #[cfg(feature = "serde")]
+    unsafe fn get_struct<T: DeserializeOwned>(self, key: &str) -> Result<Option<T>, plist::Error>;
+    /// Stores `value` as a property list, so structs become dictionaries.
+    #[cfg(feature = "serde")]
+    unsafe fn set_struct<T: Serialize + ?Sized>(self, key: &str, value: &T) -> Result<(), plist::Error>;
 }
 
-impl NSString for id {
-    unsafe fn isEqualToString(self, other: &str) -> bool {
-        let other = NSString::alloc(nil).init_str(other);
-        let rv: BOOL = msg_send![self, isEqualToString:other];
-        rv != 0
+impl NSUserDefaults for id {
+    unsafe fn initWithSuiteName_(self, suitename: id) -> id {
+        msg_send![self, initWithSuiteName:suitename]
     }
 
-    unsafe fn stringByAppendingString_(self, other: id) -> id {
-        msg_send![self, stringByAppendingString:other]
+    unsafe fn stringForKey_(self, defaultName: id) -> id {
+        msg_send![self, stringForKey:defaultName]
     }
 
-    unsafe fn init_str(self, string: &str) -> id {
-        return msg_send![self,
-                         initWithBytes:string.as_ptr()
-                             length:string.len()
-                             encoding:UTF8_ENCODING as id];
+    unsafe fn arrayForKey_(self, defaultName: id) -> id {
+        msg_send![self, arrayForKey:defaultName]
     }
 
-    unsafe fn len(self) -> usize {
-        msg_send![self, lengthOfBytesUsingEncoding:UTF8_ENCODING]
+    unsafe fn dictionaryForKey_(self, defaultName: id) -> id {
+        msg_send![self, dictionaryForKey:defaultName]
     }
 
-    unsafe fn UTF8String(self) -> *const libc::c_char {
-        msg_send![self, UTF8String]
+    unsafe fn dataForKey_(self, defaultName: id) -> id {
+        msg_send![self, dataForKey:defaultName]
     }
-}
 
-pub trait NSDate: Sized {
-    unsafe fn distantPast(_: Self) -> id {
-        msg_send![class("NSDate"), distantPast]
+    unsafe fn stringArrayForKey_(self, defaultName: id) -> id {
+        msg_send![self, stringArrayForKey:defaultName]
     }
 
-    unsafe fn distantFuture(_: Self) -> id {
-        msg_send![class("NSDate"), distantFuture]
+    unsafe fn integerForKey_(self, defaultName: id) -> NSInteger {
+        msg_send![self, integerForKey:defaultName]
     }
-}
 
-impl NSDate for id {
+    unsafe fn floatForKey_(self, defaultName: id) -> f32 {
+        msg_send![self, floatForKey:defaultName]
+    }
 
-}
+    unsafe fn doubleForKey_(self, defaultName: id) -> f64 {
+        msg_send![self, doubleForKey:defaultName]
+    }
 
-#[repr(C)]
-struct NSFastEnumerationState {
-    pub state: libc::c_ulong,
-    pub items_ptr: *mut id,
-    pub mutations_ptr: *mut libc::c_ulong,
-    pub extra: [libc::c_ulong; 5]
-}
+    unsafe fn boolForKey_(self, defaultName: id) -> BOOL {
+        msg_send![self, boolForKey:defaultName]
+    }
 
-const NS_FAST_ENUM_BUF_SIZE: usize = 16;
+    unsafe fn URLForKey_(self, defaultName: id) -> id {
+        msg_send![self, URLForKey:defaultName]
+    }
 
-pub struct NSFastIterator {
-    state: NSFastEnumerationState,
-    buffer: [id; NS_FAST_ENUM_BUF_SIZE],
-    mut_val: Option<libc::c_ulong>,
-    len: usize,
-    idx: usize,
-    object: id
-}
+    unsafe fn setInteger_forKey_(self, value: NSInteger, defaultName: id) {
+        msg_send![self, setInteger:value forKey:defaultName]
+    }
 
-impl Iterator for NSFastIterator {
-    type Item = id;
+    unsafe fn setFloat_forKey_(self, value: f32, defaultName: id) {
+        msg_send![self, setFloat:value forKey:defaultName]
+    }
 
-    fn next(&mut self) -> Option<id> {
-        if self.idx >= self.len {
-            self.len = unsafe {
-                msg_send![self.object, countByEnumeratingWithState:&mut self.state objects:self.buffer.as_mut_ptr() count:NS_FAST_ENUM_BUF_SIZE]
-            };
-            self.idx = 0;
-        }
+    unsafe fn setDouble_forKey_(self, value: f64, defaultName: id) {
+        msg_send![self, setDouble:value forKey:defaultName]
+    }
 
-        let new_mut = unsafe {
-            *self.state.mutations_ptr
-        };
+    unsafe fn setBool_forKey_(self, value: BOOL, defaultName: id) {
+        msg_send![self, setBool:value forKey:defaultName]
+    }
 
-        if let Some(old_mut) = self.mut_val {
-            assert!(old_mut == new_mut, "The collection was mutated while being enumerated");
-        }
+    unsafe fn setURL_forKey_(self, url: id, defaultName: id) {
+        msg_send![self, setURL:url forKey:defaultName]
+    }
 
-        if self.idx < self.len {
-            let object = unsafe {
-                *self.state.items_ptr.offset(self.idx as isize)
-            };
-            self.mut_val = Some(new_mut);
-            self.idx += 1;
-            Some(object)
-        } else {
-            None
-        }
+    unsafe fn registerDefaults_(self, registrationDictionary: id) {
+        msg_send![self, registerDefaults:registrationDictionary]
     }
-}
 
-pub trait NSFastEnumeration: Sized {
-    unsafe fn iter(self) -> NSFastIterator;
-}
+    unsafe fn addSuiteNamed_(self, suiteName: id) {
+        msg_send![self, addSuiteNamed:suiteName]
+    }
 
-impl NSFastEnumeration for id {
-    unsafe fn iter(self) -> NSFastIterator {
-        NSFastIterator {
-            state: NSFastEnumerationState {
-                state: 0,
-                items_ptr: ptr::null_mut(),
-                mutations_ptr: ptr::null_mut(),
-                extra: [0; 5]
-            },
-            buffer: [nil; NS_FAST_ENUM_BUF_SIZE],
-            mut_val: None,
-            len: 0,
-            idx: 0,
-            object: self
-        }
+    unsafe fn removeSuiteNamed_(self, suiteName: id) {
+        msg_send![self, removeSuiteNamed:suiteName]
     }
-}
 
-#[link(name = "Foundation", kind = "framework")]
-extern {
-    fn NSInsetRect(rect: NSRect, x: CGFloat, y: CGFloat) -> NSRect;
-}
+    unsafe fn dictionaryRepresentation(self) -> id {
+        msg_send![self, dictionaryRepresentation]
+    }
 
-pub trait NSRunLoop: Sized {
-    unsafe fn currentRunLoop() -> Self;
+    unsafe fn volatileDomainNames(self) -> id {
+        msg_send![self, volatileDomainNames]
+    }
 
-    unsafe fn performSelector_target_argument_order_modes_(self,
-                                                           aSelector: SEL,
-                                                           target: id,
-                                                           anArgument: id,
-                                                           order: NSUInteger,
-                                                           modes: id);
-}
+    unsafe fn persistentDomainForName_(self, domainName: id) -> id {
+        msg_send![self, persistentDomainForName:domainName]
+    }
 
-impl NSRunLoop for id {
-    unsafe fn currentRunLoop() -> id {
-        msg_send![class("NSRunLoop"), currentRunLoop]
+    unsafe fn setPersistentDomain_forName_(self, domain: id, domainName: id) {
+        msg_send![self, setPersistentDomain:domain forName:domainName]
     }
 
-    unsafe fn performSelector_target_argument_order_modes_(self,
-                                                           aSelector: SEL,
-                                                           target: id,
-                                                           anArgument: id,
-                                                           order: NSUInteger,
-                                                           modes: id) {
-        msg_send![self, performSelector:aSelector
-                                 target:target
-                               argument:anArgument
-                                  order:order
-                                  modes:modes]
+    unsafe fn removePersistentDomainForName_(self, domainName: id) {
+        msg_send![self, removePersistentDomainForName:domainName]
     }
-}
 
-pub trait NSData: Sized {
-    unsafe fn data(_: Self) -> id {
-        msg_send![class("NSData"), data]
+    unsafe fn objectIsForcedForKey_(self, key: id) -> BOOL {
+        msg_send![self, objectIsForcedForKey:key]
     }
 
-    unsafe fn dataWithBytes_length_(_: Self, bytes: *const libc::c_void, length: NSUInteger) -> id {
-        msg_send![class("NSData"), dataWithBytes:bytes length:length]
+    unsafe fn synchronize(self) -> BOOL {
+        msg_send![self, synchronize]
     }
 
-    unsafe fn dataWithBytesNoCopy_length_(_: Self, bytes: *const libc::c_void, length: NSUInteger) -> id {
-        msg_send![class("NSData"), dataWithBytesNoCopy:bytes length:length]
+    unsafe fn get_value<T: FromCocoa>(self, key: &str) -> Option<T> {
+        T::from_cocoa(self.objectForKey_(*key.to_cocoa()))
     }
 
-    unsafe fn dataWithBytesNoCopy_length_freeWhenDone_(_: Self, bytes: *const libc::c_void,
-                                                      length: NSUInteger, freeWhenDone: BOOL) -> id {
-        msg_send![class("NSData"), dataWithBytesNoCopy:bytes length:length freeWhenDone:freeWhenDone]
+    unsafe fn set_value<T: ToCocoa + ?Sized>(self, key: &str, value: &T) {
+        self.setObject_forKey_(*value.to_cocoa(), *key.to_cocoa())
     }
 
-    unsafe fn dataWithContentsOfFile_(_: Self, path: id) -> id {
-        msg_send![class("NSData"), dataWithContentsOfFile:path]
+    unsafe fn remove_value(self, key: &str) {
+        self.removeObjectForKey_(*key.to_cocoa())
     }
 
-    unsafe fn dataWithContentsOfFile_options_error_(_: Self, path: id, mask: NSDataReadingOptions,
-                                                    errorPtr: *mut id) -> id {
-        msg_send![class("NSData"), dataWithContentsOfFile:path options:mask error:errorPtr]
+    unsafe fn get_data(self, key: &str) -> Option<Vec<u8>> {
+        non_nil(self.dataForKey_(*key.to_cocoa())).map(|data| data.to_vec())
     }
 
-    unsafe fn dataWithContentsOfURL_(_: Self, aURL: id) -> id {
-        msg_send![class("NSData"), dataWithContentsOfURL:aURL]
+    unsafe fn set_data(self, key: &str, bytes: &[u8]) {
+        let data = NSData::dataWithBytes_length_(nil, bytes.as_ptr() as *const libc::c_void,
+                                                 bytes.len() as NSUInteger);
+        self.setObject_forKey_(data, *key.to_cocoa())
     }
 
-    unsafe fn dataWithContentsOfURL_options_error_(_: Self, aURL: id, mask: NSDataReadingOptions,
-                                                   errorPtr: *mut id) -> id {
-        msg_send![class("NSData"), dataWithContentsOfURL:aURL options:mask error:errorPtr]
+    unsafe fn register_defaults<T: ToCocoa + ?Sized>(self, defaults: &T) {
+        self.registerDefaults_(*defaults.to_cocoa())
     }
 
-    unsafe fn dataWithData_(_: Self, aData: id) -> id {
-        msg_send![class("NSData"), dataWithData:aData]
+    unsafe fn observe_changes<F: Fn() + 'static>(self, f: F) -> NotificationObserver {
+        NSNotificationCenter::defaultCenter(nil).observe(NSUserDefaultsDidChangeNotification, self, move |_| f())
     }
 
-    unsafe fn initWithBase64EncodedData_options_(self, base64Data: id, options: NSDataBase64DecodingOptions)
-                                                 -> id;
-    unsafe fn initWithBase64EncodedString_options_(self, base64String: id, options: NSDataBase64DecodingOptions)
-                                                   -> id;
-    unsafe fn initWithBytes_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id;
-    unsafe fn initWithBytesNoCopy_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id;
-    unsafe fn initWithBytesNoCopy_length_deallocator_(self, bytes: *const libc::c_void, length: NSUInteger,
-                                                      deallocator: *mut Block<(*const libc::c_void, NSUInteger), ()>)
-                                                      -> id;
-    unsafe fn initWithBytesNoCopy_length_freeWhenDone_(self, bytes: *const libc::c_void,
-                                                       length: NSUInteger, freeWhenDone: BOOL) -> id;
-    unsafe fn initWithContentsOfFile_(self, path: id) -> id;
-    unsafe fn initWithContentsOfFile_options_error(self, path: id, mask: NSDataReadingOptions, errorPtr: *mut id)
-                                                   -> id;
-    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id;
-    unsafe fn initWithContentsOfURL_options_error_(self, aURL: id, mask: NSDataReadingOptions, errorPtr: *mut id)
-                                                   -> id;
-    unsafe fn initWithData_(self, data: id) -> id;
+    #[cfg(feature = "serde")]
+    unsafe fn get_struct<T: DeserializeOwned>(self, key: &str) -> Result<Option<T>, plist::Error> {
+        let object = self.objectForKey_(*key.to_cocoa());
+        if object.is_null() {
+            return Ok(None);
+        }
+        from_object(object).map(Some)
+    }
 
-    unsafe fn bytes(self) -> *const libc::c_void;
-    unsafe fn description(self) -> id;
-    unsafe fn enumerateByteRangesUsingBlock_(self, block: *mut Block<(*const libc::c_void, NSRange, *mut BOOL), ()>);
-    unsafe fn getBytes_length_(self, buffer: *mut libc::c_void, length: NSUInteger);
-    unsafe fn getBytes_range_(self, buffer: *mut libc::c_void, range: NSRange);
-    unsafe fn subdataWithRange_(self, range: NSRange) -> id;
-    unsafe fn rangeOfData_options_range_(self, dataToFind: id, options: NSDataSearchOptions, searchRange: NSRange)
-                                         -> NSRange;
+    #[cfg(feature = "serde")]
+    unsafe fn set_struct<T: Serialize + ?Sized>(self, key: &str, value: &T) -> Result<(), plist::Error> {
+        let value = plist::serde::to_value(value)?;
+        self.set_value(key, &value);
+        Ok(())
+    }
+}
 
-    unsafe fn base64EncodedDataWithOptions_(self, options: NSDataBase64EncodingOptions) -> id;
-    unsafe fn base64EncodedStringWithOptions_(self, options: NSDataBase64EncodingOptions) -> id;
+pub trait NSBundle: Sized {
+    unsafe fn mainBundle(_: Self) -> id {
+        msg_send![class("NSBundle"), mainBundle]
+    }
+
+    unsafe fn bundleWithPath_(_: Self, path: id) -> id {
+        msg_send![class("NSBundle"), bundleWithPath:path]
+    }
+
+    unsafe fn bundleWithURL_(_: Self, url: id) -> id {
+        msg_send![class("NSBundle"), bundleWithURL:url]
+    }
+
+    unsafe fn bundleWithIdentifier_(_: Self, identifier: id) -> id {
+        msg_send![class("NSBundle"), bundleWithIdentifier:identifier]
+    }
+
+    /// Returns `None` if there is no bundle directory at `path`.
+    unsafe fn with_path(_: Self, path: &Path) -> Option<id> {
+        non_nil(NSBundle::bundleWithPath_(nil, NSFileManager::defaultManager(nil).path_string(path)))
+    }
+
+    unsafe fn with_identifier(_: Self, identifier: &str) -> Option<id> {
+        non_nil(NSBundle::bundleWithIdentifier_(nil, *identifier.to_cocoa()))
+    }
+
+    unsafe fn bundleIdentifier(self) -> id;
+    unsafe fn bundlePath(self) -> id;
+    unsafe fn bundleURL(self) -> id;
+    unsafe fn resourcePath(self) -> id;
+    unsafe fn resourceURL(self) -> id;
+    unsafe fn executablePath(self) -> id;
+    unsafe fn privateFrameworksPath(self) -> id;
+    unsafe fn builtInPlugInsPath(self) -> id;
+    unsafe fn infoDictionary(self) -> id;
+    unsafe fn localizedInfoDictionary(self) -> id;
+    unsafe fn objectForInfoDictionaryKey_(self, key: id) -> id;
+    unsafe fn pathForResource_ofType_(self, name: id, ext: id) -> id;
+    unsafe fn pathForResource_ofType_inDirectory_(self, name: id, ext: id, subpath: id) -> id;
+    unsafe fn pathsForResourcesOfType_inDirectory_(self, ext: id, subpath: id) -> id;
+    unsafe fn URLForResource_withExtension_(self, name: id, ext: id) -> id;
+    unsafe fn URLForResource_withExtension_subdirectory_(self, name: id, ext: id, subpath: id) -> id;
+    unsafe fn localizedStringForKey_value_table_(self, key: id, value: id, tableName: id) -> id;
+    unsafe fn localizations(self) -> id;
+    unsafe fn preferredLocalizations(self) -> id;
+    unsafe fn developmentLocalization(self) -> id;
+    unsafe fn load(self) -> BOOL;
+    unsafe fn isLoaded(self) -> BOOL;
+    unsafe fn unload(self) -> BOOL;
+
+    unsafe fn identifier(self) -> Option<String>;
+    unsafe fn bundle_path(self) -> Option<PathBuf>;
+    /// Whether this is an application bundle, which for the main bundle means the process was
+    /// launched from a `.app` rather than as a bare executable.
+    unsafe fn packaged_as_app(self) -> bool;
+    /// Looks up an Info.plist key, localized if the bundle localizes it.
+    unsafe fn info_value<T: FromCocoa>(self, key: &str) -> Option<T>;
+    /// `CFBundleShortVersionString`, the user-visible version.
+    unsafe fn short_version(self) -> Option<String>;
+    /// `CFBundleVersion`, the build number.
+    unsafe fn build_version(self) -> Option<String>;
+    unsafe fn resource_path(self, name: &str, ext: Option<&str>) -> Option<PathBuf>;
+    unsafe fn resource_url(self, name: &str, ext: Option<&str>) -> Option<id>;
+    /// Looks `key` up in `table`, or in Localizable.strings if `table` is `None`.
+    unsafe fn localized_string(self, key: &str, table: Option<&str>) -> Option<String>;
+}
 
-    unsafe fn isEqualToData_(self, otherData: id) -> id;
-    unsafe fn length(self) -> NSUInteger;
+/// Keeps an optional string argument alive while its `id`, or nil, is in use.
+fn optional_string(string: Option<&str>) -> Option<Id> {
+    string.map(|string| unsafe { string.to_cocoa() })
+}
 
-    unsafe fn writeToFile_atomically_(self, path: id, atomically: BOOL) -> BOOL;
-    unsafe fn writeToFile_options_error_(self, path: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL;
-    unsafe fn writeToURL_atomically_(self, aURL: id, atomically: BOOL) -> BOOL;
-    unsafe fn writeToURL_options_error_(self, aURL: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL;
+fn id_or_nil(object: &Option<Id>) -> id {
+    object.as_ref().map_or(nil, |object| **object)
 }
 
-impl NSData for id {
-    unsafe fn initWithBase64EncodedData_options_(self, base64Data: id, options: NSDataBase64DecodingOptions)
-                                                 -> id {
-        msg_send![self, initWithBase64EncodedData:base64Data options:options]
+impl NSBundle for id {
+    unsafe fn bundleIdentifier(self) -> id {
+        msg_send![self, bundleIdentifier]
     }
 
-    unsafe fn initWithBase64EncodedString_options_(self, base64String: id, options: NSDataBase64DecodingOptions)
-                                                   -> id {
-        msg_send![self, initWithBase64EncodedString:base64String options:options]
+    unsafe fn bundlePath(self) -> id {
+        msg_send![self, bundlePath]
     }
 
-    unsafe fn initWithBytes_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id {
-        msg_send![self,initWithBytes:bytes length:length]
+    unsafe fn bundleURL(self) -> id {
+        msg_send![self, bundleURL]
     }
 
-    unsafe fn initWithBytesNoCopy_length_(self, bytes: *const libc::c_void, length: NSUInteger) -> id {
-        msg_send![self, initWithBytesNoCopy:bytes length:length]
+    unsafe fn resourcePath(self) -> id {
+        msg_send![self, resourcePath]
     }
 
-    unsafe fn initWithBytesNoCopy_length_deallocator_(self, bytes: *const libc::c_void, length: NSUInteger,
-                                                      deallocator: *mut Block<(*const libc::c_void, NSUInteger), ()>)
-                                                      -> id {
-        msg_send![self, initWithBytesNoCopy:bytes length:length deallocator:deallocator]
+    unsafe fn resourceURL(self) -> id {
+        msg_send![self, resourceURL]
     }
 
-    unsafe fn initWithBytesNoCopy_length_freeWhenDone_(self, bytes: *const libc::c_void,
-                                                       length: NSUInteger, freeWhenDone: BOOL) -> id {
-        msg_send![self, initWithBytesNoCopy:bytes length:length freeWhenDone:freeWhenDone]
+    unsafe fn executablePath(self) -> id {
+        msg_send![self, executablePath]
     }
 
-    unsafe fn initWithContentsOfFile_(self, path: id) -> id {
-        msg_send![self, initWithContentsOfFile:path]
+    unsafe fn privateFrameworksPath(self) -> id {
+        msg_send![self, privateFrameworksPath]
     }
 
-    unsafe fn initWithContentsOfFile_options_error(self, path: id, mask: NSDataReadingOptions, errorPtr: *mut id)
-                                                   -> id {
-        msg_send![self, initWithContentsOfFile:path options:mask error:errorPtr]
+    unsafe fn builtInPlugInsPath(self) -> id {
+        msg_send![self, builtInPlugInsPath]
     }
 
-    unsafe fn initWithContentsOfURL_(self, aURL: id) -> id {
-        msg_send![self, initWithContentsOfURL:aURL]
+    unsafe fn infoDictionary(self) -> id {
+        msg_send![self, infoDictionary]
     }
 
-    unsafe fn initWithContentsOfURL_options_error_(self, aURL: id, mask: NSDataReadingOptions, errorPtr: *mut id)
-                                                   -> id {
-        msg_send![self, initWithContentsOfURL:aURL options:mask error:errorPtr]
+    unsafe fn localizedInfoDictionary(self) -> id {
+        msg_send![self, localizedInfoDictionary]
     }
 
-    unsafe fn initWithData_(self, data: id) -> id {
-        msg_send![self, initWithData:data]
+    unsafe fn objectForInfoDictionaryKey_(self, key: id) -> id {
+        msg_send![self, objectForInfoDictionaryKey:key]
     }
 
-    unsafe fn bytes(self) -> *const libc::c_void {
-        msg_send![self, bytes]
+    unsafe fn pathForResource_ofType_(self, name: id, ext: id) -> id {
+        msg_send![self, pathForResource:name ofType:ext]
     }
 
-    unsafe fn description(self) -> id {
-        msg_send![self, description]
+    unsafe fn pathForResource_ofType_inDirectory_(self, name: id, ext: id, subpath: id) -> id {
+        msg_send![self, pathForResource:name ofType:ext inDirectory:subpath]
     }
 
-    unsafe fn enumerateByteRangesUsingBlock_(self, block: *mut Block<(*const libc::c_void, NSRange, *mut BOOL), ()>) {
-        msg_send![self, enumerateByteRangesUsingBlock:block]
+    unsafe fn pathsForResourcesOfType_inDirectory_(self, ext: id, subpath: id) -> id {
+        msg_send![self, pathsForResourcesOfType:ext inDirectory:subpath]
     }
 
-    unsafe fn getBytes_length_(self, buffer: *mut libc::c_void, length: NSUInteger) {
-        msg_send![self, getBytes:buffer length:length]
+    unsafe fn URLForResource_withExtension_(self, name: id, ext: id) -> id {
+        msg_send![self, URLForResource:name withExtension:ext]
     }
 
-    unsafe fn getBytes_range_(self, buffer: *mut libc::c_void, range: NSRange) {
-        msg_send![self, getBytes:buffer range:range]
+    unsafe fn URLForResource_withExtension_subdirectory_(self, name: id, ext: id, subpath: id) -> id {
+        msg_send![self, URLForResource:name withExtension:ext subdirectory:subpath]
     }
 
-    unsafe fn subdataWithRange_(self, range: NSRange) -> id {
-        msg_send![self, subdataWithRange:range]
+    unsafe fn localizedStringForKey_value_table_(self, key: id, value: id, tableName: id) -> id {
+        msg_send![self, localizedStringForKey:key value:value table:tableName]
     }
 
-    unsafe fn rangeOfData_options_range_(self, dataToFind: id, options: NSDataSearchOptions, searchRange: NSRange)
-                                         -> NSRange {
-        msg_send![self, rangeOfData:dataToFind options:options range:searchRange]
+    unsafe fn localizations(self) -> id {
+        msg_send![self, localizations]
     }
 
-    unsafe fn base64EncodedDataWithOptions_(self, options: NSDataBase64EncodingOptions) -> id {
-        msg_send![self, base64EncodedDataWithOptions:options]
+    unsafe fn preferredLocalizations(self) -> id {
+        msg_send![self, preferredLocalizations]
     }
 
-    unsafe fn base64EncodedStringWithOptions_(self, options: NSDataBase64EncodingOptions) -> id {
-        msg_send![self, base64EncodedStringWithOptions:options]
+    unsafe fn developmentLocalization(self) -> id {
+        msg_send![self, developmentLocalization]
     }
 
-    unsafe fn isEqualToData_(self, otherData: id) -> id {
-        msg_send![self, isEqualToData:otherData]
+    unsafe fn load(self) -> BOOL {
+        msg_send![self, load]
     }
 
-    unsafe fn length(self) -> NSUInteger {
-        msg_send![self, length]
+    unsafe fn isLoaded(self) -> BOOL {
+        msg_send![self, isLoaded]
     }
 
-    unsafe fn writeToFile_atomically_(self, path: id, atomically: BOOL) -> BOOL {
-        msg_send![self, writeToFile:path atomically:atomically]
+    unsafe fn unload(self) -> BOOL {
+        msg_send![self, unload]
     }
 
-    unsafe fn writeToFile_options_error_(self, path: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL {
-        msg_send![self, writeToFile:path options:mask error:errorPtr]
+    unsafe fn identifier(self) -> Option<String> {
+        String::from_cocoa(self.bundleIdentifier())
     }
 
-    unsafe fn writeToURL_atomically_(self, aURL: id, atomically: BOOL) -> BOOL {
-        msg_send![self, writeToURL:aURL atomically:atomically]
+    unsafe fn bundle_path(self) -> Option<PathBuf> {
+        path_from_string(self.bundlePath())
     }
 
-    unsafe fn writeToURL_options_error_(self, aURL: id, mask: NSDataWritingOptions, errorPtr: *mut id) -> BOOL {
-        msg_send![self, writeToURL:aURL options:mask error:errorPtr]
+    unsafe fn packaged_as_app(self) -> bool {
+        self.info_value::<String>("CFBundlePackageType").as_deref() == Some("APPL") ||
+            self.bundle_path().map_or(false, |path| path.extension() == Some(OsStr::new("app")))
     }
-}
 
-bitflags! {
-    pub struct NSDataReadingOptions: libc::c_ulonglong {
-       const NSDataReadingMappedIfSafe = 1 << 0;
-       const NSDataReadingUncached = 1 << 1;
-       const NSDataReadingMappedAlways = 1 << 3;
+    unsafe fn info_value<T: FromCocoa>(self, key: &str) -> Option<T> {
+        T::from_cocoa(self.objectForInfoDictionaryKey_(*key.to_cocoa()))
     }
-}
 
-bitflags! {
-    pub struct NSDataBase64EncodingOptions: libc::c_ulonglong {
-        const NSDataBase64Encoding64CharacterLineLength = 1 << 0;
-        const NSDataBase64Encoding76CharacterLineLength = 1 << 1;
-        const NSDataBase64EncodingEndLineWithCarriageReturn = 1 << 4;
-        const NSDataBase64EncodingEndLineWithLineFeed = 1 << 5;
+    unsafe fn short_version(self) -> Option<String> {
+        self.info_value("CFBundleShortVersionString")
     }
-}
 
-bitflags! {
-    pub struct NSDataBase64DecodingOptions: libc::c_ulonglong {
-       const NSDataBase64DecodingIgnoreUnknownCharacters = 1 << 0;
+    unsafe fn build_version(self) -> Option<String> {
+        self.info_value("CFBundleVersion")
     }
-}
 
-bitflags! {
-    pub struct NSDataWritingOptions: libc::c_ulonglong {
-        const NSDataWritingAtomic = 1 << 0;
-        const NSDataWritingWithoutOverwriting = 1 << 1;
+    unsafe fn resource_path(self, name: &str, ext: Option<&str>) -> Option<PathBuf> {
+        let ext = optional_string(ext);
+        let path = self.pathForResource_ofType_(*name.to_cocoa(), id_or_nil(&ext));
+        non_nil(path).and_then(|path| path_from_string(path))
     }
-}
 
-bitflags! {
-    pub struct NSDataSearchOptions: libc::c_ulonglong {
-        const NSDataSearchBackwards = 1 << 0;
-        const NSDataSearchAnchored = 1 << 1;
+    unsafe fn resource_url(self, name: &str, ext: Option<&str>) -> Option<id> {
+        let ext = optional_string(ext);
+        non_nil(self.URLForResource_withExtension_(*name.to_cocoa(), id_or_nil(&ext)))
+    }
+
+    unsafe fn localized_string(self, key: &str, table: Option<&str>) -> Option<String> {
+        // A missing key comes back as the default value, so use one no table would contain.
+        const MISSING: &str = "\u{fffe}cocoa-rs: missing localized string";
+        let table = optional_string(table);
+        let string = self.localizedStringForKey_value_table_(*key.to_cocoa(), *MISSING.to_cocoa(),
+                                                             id_or_nil(&table));
+        String::from_cocoa(string).filter(|string| string != MISSING)
     }
 }
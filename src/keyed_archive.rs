@@ -0,0 +1,419 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The object graph format written by `NSKeyedArchiver` and read by `NSKeyedUnarchiver`.
+//!
+//! An archive is a binary property list with four keys: `$archiver`, `$version`, `$top` and
+//! `$objects`. Every archived object lives in the `$objects` array and refers to others by UID,
+//! which is an index into that array; UID 0 is always the `$null` placeholder for nil.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use plist::{self, Value};
+
+const ARCHIVER: &str = "NSKeyedArchiver";
+const VERSION: i64 = 100000;
+const ROOT_KEY: &str = "root";
+const NULL: &str = "$null";
+
+/// A decoded archive object.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Object {
+    Null,
+    /// `NSString` and `NSMutableString`.
+    String(String),
+    /// An `NSNumber` holding an integer.
+    Integer(i64),
+    /// An `NSNumber` holding a float or double.
+    Real(f64),
+    /// An `NSNumber` holding a `BOOL`.
+    Boolean(bool),
+    /// `NSData` and `NSMutableData`.
+    Data(Vec<u8>),
+    /// An `NSDate`, in seconds since 2001-01-01 00:00:00 UTC.
+    Date(f64),
+    /// `NSArray` and `NSMutableArray`.
+    Array(Vec<Object>),
+    /// `NSDictionary` and `NSMutableDictionary`, in archive order. Keys need not be strings.
+    Dictionary(Vec<(Object, Object)>),
+    Color(Color),
+}
+
+/// An `NSColor` in one of the component-based color spaces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    CalibratedRgb { red: f64, green: f64, blue: f64, alpha: f64 },
+    DeviceRgb { red: f64, green: f64, blue: f64, alpha: f64 },
+    CalibratedWhite { white: f64, alpha: f64 },
+    DeviceWhite { white: f64, alpha: f64 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Plist(plist::Error),
+    /// The archive is a valid property list but not a valid keyed archive.
+    Invalid(String),
+    /// The archive contains an instance of a class this module cannot decode.
+    UnsupportedClass(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Plist(ref err) => err.fmt(f),
+            Error::Invalid(ref what) => write!(f, "invalid keyed archive: {}", what),
+            Error::UnsupportedClass(ref name) => write!(f, "cannot decode instances of {}", name),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<plist::Error> for Error {
+    fn from(err: plist::Error) -> Error {
+        Error::Plist(err)
+    }
+}
+
+fn invalid<T>(what: &str) -> Result<T, Error> {
+    Err(Error::Invalid(what.to_owned()))
+}
+
+/// Decodes the root object of a binary keyed archive.
+pub fn from_slice(bytes: &[u8]) -> Result<Object, Error> {
+    from_plist(&plist::binary::from_slice(bytes)?)
+}
+
+/// Decodes the root object of a keyed archive that has already been parsed as a property list.
+pub fn from_plist(archive: &Value) -> Result<Object, Error> {
+    let archive = match archive.as_dictionary() {
+        Some(archive) => archive,
+        None => return invalid("top level is not a dictionary"),
+    };
+    if archive.get("$archiver").and_then(Value::as_str) != Some(ARCHIVER) {
+        return invalid("$archiver is not NSKeyedArchiver");
+    }
+    let objects = match archive.get("$objects").and_then(Value::as_array) {
+        Some(objects) => objects,
+        None => return invalid("missing $objects"),
+    };
+    let root = match archive.get("$top").and_then(Value::as_dictionary).and_then(|t| t.get(ROOT_KEY)) {
+        Some(&Value::Uid(root)) => root,
+        _ => return invalid("missing $top root reference"),
+    };
+
+    let mut decoder = Decoder {
+        objects,
+        in_progress: vec![false; objects.len()],
+    };
+    decoder.decode(root)
+}
+
+/// Encodes `object` as the root of a binary keyed archive.
+pub fn to_vec(object: &Object) -> Vec<u8> {
+    plist::binary::to_vec(&to_plist(object))
+}
+
+/// Encodes `object` as the root of a keyed archive property list.
+pub fn to_plist(object: &Object) -> Value {
+    let mut encoder = Encoder {
+        objects: vec![Value::String(NULL.to_owned())],
+        classes: BTreeMap::new(),
+    };
+    let root = encoder.encode(object);
+
+    let mut top = BTreeMap::new();
+    top.insert(ROOT_KEY.to_owned(), root);
+    let mut archive = BTreeMap::new();
+    archive.insert("$archiver".to_owned(), Value::String(ARCHIVER.to_owned()));
+    archive.insert("$version".to_owned(), Value::Integer(VERSION));
+    archive.insert("$top".to_owned(), Value::Dictionary(top));
+    archive.insert("$objects".to_owned(), Value::Array(encoder.objects));
+    Value::Dictionary(archive)
+}
+
+struct Decoder<'a> {
+    objects: &'a [Value],
+    /// The tree-shaped `Object` cannot represent an object graph with cycles.
+    in_progress: Vec<bool>,
+}
+
+impl<'a> Decoder<'a> {
+    fn decode(&mut self, uid: u64) -> Result<Object, Error> {
+        let index = uid as usize;
+        let value = match self.objects.get(index) {
+            Some(value) => value,
+            None => return Err(Error::Invalid(format!("UID {} out of range", uid))),
+        };
+        if self.in_progress[index] {
+            return Err(Error::Invalid(format!("object {} refers to itself", uid)));
+        }
+        self.in_progress[index] = true;
+        let object = self.decode_value(index, value);
+        self.in_progress[index] = false;
+        object
+    }
+
+    fn decode_value(&mut self, index: usize, value: &Value) -> Result<Object, Error> {
+        match *value {
+            Value::String(ref s) if index == 0 && s == NULL => Ok(Object::Null),
+            Value::String(ref s) => Ok(Object::String(s.clone())),
+            Value::Integer(i) => Ok(Object::Integer(i)),
+            Value::Real(r) => Ok(Object::Real(r)),
+            Value::Boolean(b) => Ok(Object::Boolean(b)),
+            Value::Data(ref data) => Ok(Object::Data(data.clone())),
+            Value::Dictionary(ref fields) => self.decode_instance(fields),
+            _ => Err(Error::Invalid(format!("object {} is not an archived object", index))),
+        }
+    }
+
+    fn decode_instance(&mut self, fields: &BTreeMap<String, Value>) -> Result<Object, Error> {
+        let class = match fields.get("$class").and_then(Value::as_uid) {
+            Some(class) => class,
+            None => return invalid("object dictionary without $class"),
+        };
+        let class = match self.objects.get(class as usize).and_then(Value::as_dictionary) {
+            Some(class) => class,
+            None => return invalid("$class does not refer to a class dictionary"),
+        };
+        let name = match class.get("$classname").and_then(Value::as_str) {
+            Some(name) => name,
+            None => return invalid("class dictionary without $classname"),
+        };
+
+        // Walk up the class chain so that private subclasses such as __NSArrayM still decode.
+        let mut chain = vec![name];
+        if let Some(classes) = class.get("$classes").and_then(Value::as_array) {
+            chain.extend(classes.iter().filter_map(Value::as_str));
+        }
+        for class_name in chain {
+            match class_name {
+                "NSString" | "NSMutableString" => {
+                    return match fields.get("NS.string") {
+                        Some(Value::String(s)) => Ok(Object::String(s.clone())),
+                        // Strings with embedded NULs are archived as UTF-8 bytes.
+                        _ => match fields.get("NS.bytes") {
+                            Some(Value::Data(bytes)) => {
+                                String::from_utf8(bytes.clone())
+                                    .map(Object::String)
+                                    .map_err(|_| Error::Invalid("NS.bytes is not UTF-8".to_owned()))
+                            }
+                            _ => invalid("NSString without NS.string"),
+                        },
+                    };
+                }
+                "NSArray" | "NSMutableArray" => {
+                    let refs = self.uid_array(fields, "NS.objects")?;
+                    let mut array = Vec::with_capacity(refs.len());
+                    for uid in refs {
+                        array.push(self.decode(uid)?);
+                    }
+                    return Ok(Object::Array(array));
+                }
+                "NSDictionary" | "NSMutableDictionary" => {
+                    let keys = self.uid_array(fields, "NS.keys")?;
+                    let values = self.uid_array(fields, "NS.objects")?;
+                    if keys.len() != values.len() {
+                        return invalid("NS.keys and NS.objects differ in length");
+                    }
+                    let mut dict = Vec::with_capacity(keys.len());
+                    for (k, v) in keys.into_iter().zip(values) {
+                        dict.push((self.decode(k)?, self.decode(v)?));
+                    }
+                    return Ok(Object::Dictionary(dict));
+                }
+                "NSData" | "NSMutableData" => {
+                    return match fields.get("NS.data").or_else(|| fields.get("NS.bytes")) {
+                        Some(Value::Data(data)) => Ok(Object::Data(data.clone())),
+                        _ => invalid("NSData without NS.data"),
+                    };
+                }
+                "NSDate" => {
+                    return match fields.get("NS.time") {
+                        Some(&Value::Real(time)) => Ok(Object::Date(time)),
+                        Some(&Value::Integer(time)) => Ok(Object::Date(time as f64)),
+                        _ => invalid("NSDate without NS.time"),
+                    };
+                }
+                "NSColor" => return decode_color(fields).map(Object::Color),
+                _ => {}
+            }
+        }
+        Err(Error::UnsupportedClass(name.to_owned()))
+    }
+
+    fn uid_array(&self, fields: &BTreeMap<String, Value>, key: &str) -> Result<Vec<u64>, Error> {
+        let array = match fields.get(key).and_then(Value::as_array) {
+            Some(array) => array,
+            None => return Err(Error::Invalid(format!("missing {}", key))),
+        };
+        array.iter()
+             .map(|v| v.as_uid().ok_or_else(|| Error::Invalid(format!("{} holds a non-UID", key))))
+             .collect()
+    }
+}
+
+/// `NSColorSpace` values, as archived by `-[NSColor encodeWithCoder:]`.
+const CALIBRATED_RGB: i64 = 1;
+const DEVICE_RGB: i64 = 2;
+const CALIBRATED_WHITE: i64 = 3;
+const DEVICE_WHITE: i64 = 4;
+
+fn decode_color(fields: &BTreeMap<String, Value>) -> Result<Color, Error> {
+    let space = match fields.get("NSColorSpace") {
+        Some(&Value::Integer(space)) => space,
+        _ => return invalid("NSColor without NSColorSpace"),
+    };
+    let key = match space {
+        CALIBRATED_RGB | DEVICE_RGB => "NSRGB",
+        CALIBRATED_WHITE | DEVICE_WHITE => "NSWhite",
+        _ => return Err(Error::UnsupportedClass(format!("NSColor in color space {}", space))),
+    };
+    let components = match fields.get(key) {
+        Some(Value::Data(data)) => parse_components(data)?,
+        _ => return Err(Error::Invalid(format!("NSColor without {}", key))),
+    };
+    // Alpha is omitted when it is 1.
+    let component = |i: usize| components.get(i).cloned();
+    match (space, components.len()) {
+        (CALIBRATED_RGB, 3) | (CALIBRATED_RGB, 4) | (DEVICE_RGB, 3) | (DEVICE_RGB, 4) => {
+            let (red, green, blue) = (components[0], components[1], components[2]);
+            let alpha = component(3).unwrap_or(1.0);
+            Ok(if space == CALIBRATED_RGB {
+                Color::CalibratedRgb { red, green, blue, alpha }
+            } else {
+                Color::DeviceRgb { red, green, blue, alpha }
+            })
+        }
+        (CALIBRATED_WHITE, 1) | (CALIBRATED_WHITE, 2) | (DEVICE_WHITE, 1) | (DEVICE_WHITE, 2) => {
+            let (white, alpha) = (components[0], component(1).unwrap_or(1.0));
+            Ok(if space == CALIBRATED_WHITE {
+                Color::CalibratedWhite { white, alpha }
+            } else {
+                Color::DeviceWhite { white, alpha }
+            })
+        }
+        _ => Err(Error::Invalid(format!("{} has {} components", key, components.len()))),
+    }
+}
+
+/// Components are archived as ASCII decimals separated by spaces, usually NUL-terminated.
+fn parse_components(data: &[u8]) -> Result<Vec<f64>, Error> {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let text = match ::std::str::from_utf8(&data[..end]) {
+        Ok(text) => text,
+        Err(_) => return invalid("color components are not ASCII"),
+    };
+    text.split_whitespace()
+        .map(|c| c.parse().map_err(|_| Error::Invalid(format!("bad color component {:?}", c))))
+        .collect()
+}
+
+fn format_components(components: &[f64]) -> Value {
+    let text = components.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" ");
+    let mut data = text.into_bytes();
+    data.push(0);
+    Value::Data(data)
+}
+
+struct Encoder {
+    objects: Vec<Value>,
+    classes: BTreeMap<&'static str, u64>,
+}
+
+impl Encoder {
+    fn encode(&mut self, object: &Object) -> Value {
+        let value = match *object {
+            Object::Null => return Value::Uid(0),
+            Object::String(ref s) => Value::String(s.clone()),
+            Object::Integer(i) => Value::Integer(i),
+            Object::Real(r) => Value::Real(r),
+            Object::Boolean(b) => Value::Boolean(b),
+            Object::Data(ref data) => Value::Data(data.clone()),
+            Object::Date(time) => {
+                let mut fields = BTreeMap::new();
+                fields.insert("NS.time".to_owned(), Value::Real(time));
+                self.instance(fields, "NSDate")
+            }
+            Object::Array(ref array) => {
+                let uid = self.reserve();
+                let mut fields = BTreeMap::new();
+                let refs = array.iter().map(|o| self.encode(o)).collect();
+                fields.insert("NS.objects".to_owned(), Value::Array(refs));
+                let value = self.instance(fields, "NSArray");
+                return self.fill(uid, value);
+            }
+            Object::Dictionary(ref dict) => {
+                let uid = self.reserve();
+                let mut fields = BTreeMap::new();
+                let keys = dict.iter().map(|(k, _)| self.encode(k)).collect();
+                let values = dict.iter().map(|(_, v)| self.encode(v)).collect();
+                fields.insert("NS.keys".to_owned(), Value::Array(keys));
+                fields.insert("NS.objects".to_owned(), Value::Array(values));
+                let value = self.instance(fields, "NSDictionary");
+                return self.fill(uid, value);
+            }
+            Object::Color(color) => {
+                let (space, key, components) = match color {
+                    Color::CalibratedRgb { red, green, blue, alpha } => {
+                        (CALIBRATED_RGB, "NSRGB", vec![red, green, blue, alpha])
+                    }
+                    Color::DeviceRgb { red, green, blue, alpha } => {
+                        (DEVICE_RGB, "NSRGB", vec![red, green, blue, alpha])
+                    }
+                    Color::CalibratedWhite { white, alpha } => {
+                        (CALIBRATED_WHITE, "NSWhite", vec![white, alpha])
+                    }
+                    Color::DeviceWhite { white, alpha } => (DEVICE_WHITE, "NSWhite", vec![white, alpha]),
+                };
+                let mut components = components;
+                if components.last() == Some(&1.0) {
+                    components.pop();
+                }
+                let mut fields = BTreeMap::new();
+                fields.insert("NSColorSpace".to_owned(), Value::Integer(space));
+                fields.insert(key.to_owned(), format_components(&components));
+                self.instance(fields, "NSColor")
+            }
+        };
+        let uid = self.reserve();
+        self.fill(uid, value)
+    }
+
+    /// Containers take their UID before their children, as `NSKeyedArchiver` does.
+    fn reserve(&mut self) -> u64 {
+        self.objects.push(Value::Uid(0));
+        (self.objects.len() - 1) as u64
+    }
+
+    fn fill(&mut self, uid: u64, value: Value) -> Value {
+        self.objects[uid as usize] = value;
+        Value::Uid(uid)
+    }
+
+    fn instance(&mut self, mut fields: BTreeMap<String, Value>, class: &'static str) -> Value {
+        let class = match self.classes.get(class) {
+            Some(&uid) => uid,
+            None => {
+                let mut description = BTreeMap::new();
+                description.insert("$classname".to_owned(), Value::String(class.to_owned()));
+                description.insert("$classes".to_owned(),
+                                   Value::Array(vec![Value::String(class.to_owned()),
+                                                     Value::String("NSObject".to_owned())]));
+                let uid = self.reserve();
+                self.fill(uid, Value::Dictionary(description));
+                self.classes.insert(class, uid);
+                uid
+            }
+        };
+        fields.insert("$class".to_owned(), Value::Uid(class));
+        Value::Dictionary(fields)
+    }
+}
@@ -12,14 +12,40 @@
 
 #![allow(non_snake_case)]
 
+#[cfg(target_os = "macos")]
 extern crate block;
 #[macro_use]
 extern crate bitflags;
 extern crate libc;
+#[cfg(target_os = "macos")]
 extern crate core_graphics;
+#[cfg(target_os = "macos")]
 #[macro_use]
 extern crate objc;
+#[cfg(all(target_os = "macos", feature = "serde_json"))]
+extern crate serde_json;
+#[cfg(all(target_os = "macos", feature = "url"))]
+extern crate url;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
+#[cfg(target_os = "macos")]
 pub mod appkit;
-pub mod base;
+#[cfg(target_os = "macos")]
 pub mod foundation;
+
+// These modules are plain Rust and build on every platform.
+pub mod base;
+pub mod base64;
+pub mod data;
+pub mod date;
+pub mod executor;
+pub mod fast_enumeration;
+pub mod keyed_archive;
+pub mod plist;
+pub mod range;
+pub mod styled_text;
@@ -0,0 +1,516 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Old-style OpenStep property lists, as produced by `-[NSDictionary description]`, and the
+//! `.strings` files produced by `-[NSDictionary descriptionInStringsFileFormat]`.
+//!
+//! These formats only have strings, arrays, dictionaries and data. Serializing any other
+//! `Value` fails with `Error::Unsupported`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use super::{Error, Value};
+
+/// Parses an OpenStep property list.
+pub fn from_slice(bytes: &[u8]) -> Result<Value, Error> {
+    from_str(&decode(bytes)?)
+}
+
+pub fn from_str(text: &str) -> Result<Value, Error> {
+    let mut parser = Parser::new(text);
+    let value = parser.parse_value()?;
+    parser.skip_whitespace()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected data after the property list"));
+    }
+    Ok(value)
+}
+
+/// Parses a `.strings` file: a sequence of `"key" = "value";` entries without the enclosing
+/// braces. As in Foundation, an entry written as `"key";` maps the key to itself.
+pub fn strings_from_slice(bytes: &[u8]) -> Result<BTreeMap<String, String>, Error> {
+    strings_from_str(&decode(bytes)?)
+}
+
+pub fn strings_from_str(text: &str) -> Result<BTreeMap<String, String>, Error> {
+    let mut parser = Parser::new(text);
+    let mut strings = BTreeMap::new();
+    loop {
+        parser.skip_whitespace()?;
+        if parser.peek().is_none() {
+            return Ok(strings);
+        }
+        let key = parser.parse_string()?;
+        parser.skip_whitespace()?;
+        let value = if parser.eat('=') {
+            parser.skip_whitespace()?;
+            parser.parse_string()?
+        } else {
+            key.clone()
+        };
+        parser.skip_whitespace()?;
+        parser.expect(';')?;
+        strings.insert(key, value);
+    }
+}
+
+/// Serializes `value` in the format of `-[NSDictionary description]`.
+pub fn to_string(value: &Value) -> Result<String, Error> {
+    let mut out = String::new();
+    write_value(&mut out, value, 0)?;
+    Ok(out)
+}
+
+/// Serializes `strings` in the format of `-[NSDictionary descriptionInStringsFileFormat]`.
+pub fn strings_to_string(strings: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    for (key, value) in strings {
+        write_quoted(&mut out, key);
+        out.push_str(" = ");
+        write_quoted(&mut out, value);
+        out.push_str(";\n");
+    }
+    out
+}
+
+/// Decodes UTF-16 input with either byte order mark, or UTF-8 input with or without one.
+fn decode(bytes: &[u8]) -> Result<String, Error> {
+    let utf16 = |bytes: &[u8], big_endian: bool| {
+        if bytes.len() % 2 != 0 {
+            return Err(Error::Invalid("odd number of bytes in UTF-16 input".to_owned()));
+        }
+        let units: Vec<u16> = bytes.chunks(2).map(|c| {
+            let (hi, lo) = if big_endian { (c[0], c[1]) } else { (c[1], c[0]) };
+            (hi as u16) << 8 | lo as u16
+        }).collect();
+        String::from_utf16(&units).map_err(|_| Error::Invalid("unpaired UTF-16 surrogate".to_owned()))
+    };
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        utf16(&bytes[2..], true)
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        utf16(&bytes[2..], false)
+    } else {
+        let bytes = if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) { &bytes[3..] } else { bytes };
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::Invalid("input is not UTF-8".to_owned()))
+    }
+}
+
+/// Characters that may appear in a string without quotes.
+fn is_unquoted(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_$+/:.-".contains(c)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Parser {
+        Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn error(&self, what: &str) -> Error {
+        Error::Invalid(format!("line {}: {}", self.line, what))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    /// Skips whitespace and `//` and `/* */` comments.
+    fn skip_whitespace(&mut self) -> Result<(), Error> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') if self.chars.get(self.pos + 1) == Some(&'/') => {
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                Some('/') if self.chars.get(self.pos + 1) == Some(&'*') => {
+                    let start = self.line;
+                    self.pos += 2;
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.eat('/') => break,
+                            Some(_) => {}
+                            None => {
+                                return Err(Error::Invalid(format!("line {}: unterminated comment",
+                                                                  start)));
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        self.skip_whitespace()?;
+        match self.peek() {
+            Some('{') => self.parse_dictionary(),
+            Some('(') => self.parse_array(),
+            Some('<') => self.parse_data(),
+            Some(_) => self.parse_string().map(Value::String),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_dictionary(&mut self) -> Result<Value, Error> {
+        self.expect('{')?;
+        let mut dict = BTreeMap::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.eat('}') {
+                return Ok(Value::Dictionary(dict));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace()?;
+            self.expect('=')?;
+            let value = self.parse_value()?;
+            self.skip_whitespace()?;
+            self.expect(';')?;
+            dict.insert(key, value);
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, Error> {
+        self.expect('(')?;
+        let mut array = vec![];
+        loop {
+            self.skip_whitespace()?;
+            if self.eat(')') {
+                return Ok(Value::Array(array));
+            }
+            array.push(self.parse_value()?);
+            self.skip_whitespace()?;
+            // A trailing comma before the closing parenthesis is allowed.
+            if !self.eat(',') {
+                self.skip_whitespace()?;
+                self.expect(')')?;
+                return Ok(Value::Array(array));
+            }
+        }
+    }
+
+    fn parse_data(&mut self) -> Result<Value, Error> {
+        self.expect('<')?;
+        let mut data = vec![];
+        let mut high = None;
+        loop {
+            match self.bump() {
+                Some('>') if high.is_none() => return Ok(Value::Data(data)),
+                Some(c) if c.is_whitespace() => {}
+                Some(c) => {
+                    let nibble = match c.to_digit(16) {
+                        Some(nibble) => nibble as u8,
+                        None => return Err(self.error(&format!("invalid character {:?} in data", c))),
+                    };
+                    match high.take() {
+                        Some(high) => data.push(high << 4 | nibble),
+                        None => high = Some(nibble),
+                    }
+                }
+                None => return Err(self.error("unterminated data")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                self.bump();
+                self.parse_quoted(quote)
+            }
+            Some(c) if is_unquoted(c) => {
+                let mut s = String::new();
+                while let Some(c) = self.peek() {
+                    if !is_unquoted(c) {
+                        break;
+                    }
+                    s.push(c);
+                    self.bump();
+                }
+                Ok(s)
+            }
+            Some(c) => Err(self.error(&format!("unexpected character {:?}", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_quoted(&mut self, quote: char) -> Result<String, Error> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') => s.push(self.parse_escape()?),
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Err(self.error("unterminated escape sequence")),
+        };
+        Ok(match c {
+            'a' => '\u{7}',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\u{b}',
+            'U' | 'u' => {
+                let mut code = 0;
+                for _ in 0..4 {
+                    match self.peek().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => {
+                            code = code << 4 | digit;
+                            self.bump();
+                        }
+                        None => return Err(self.error("\\U must be followed by four hex digits")),
+                    }
+                }
+                // Characters outside the BMP are written as a surrogate pair of escapes.
+                if (0xD800..0xDC00).contains(&code) && self.chars[self.pos..].starts_with(&['\\', 'U']) {
+                    let saved = (self.pos, self.line);
+                    self.pos += 2;
+                    let low = self.chars.get(self.pos..self.pos + 4)
+                                        .map(|digits| digits.iter().collect::<String>())
+                                        .and_then(|digits| u32::from_str_radix(&digits, 16).ok());
+                    match low {
+                        Some(low) if (0xDC00..0xE000).contains(&low) => {
+                            self.pos += 4;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        _ => {
+                            self.pos = saved.0;
+                            self.line = saved.1;
+                        }
+                    }
+                }
+                match ::std::char::from_u32(code) {
+                    Some(c) => c,
+                    None => return Err(self.error(&format!("\\U{:04X} is not a character", code))),
+                }
+            }
+            '0'..='7' => {
+                // Up to three octal digits. Values above 0x7F are NEXTSTEP-encoded in Foundation;
+                // this treats them as Latin-1, which agrees for the common accented letters only.
+                let mut code = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code << 3 | digit;
+                            self.bump();
+                        }
+                        None => break,
+                    }
+                }
+                ::std::char::from_u32(code).unwrap()
+            }
+            // `\"`, `\'`, `\\` and any other escaped character stand for themselves.
+            c => c,
+        })
+    }
+}
+
+fn write_indent(out: &mut String, level: usize) {
+    for _ in 0..level {
+        out.push_str("    ");
+    }
+}
+
+fn write_value(out: &mut String, value: &Value, level: usize) -> Result<(), Error> {
+    match *value {
+        Value::String(ref s) => write_string(out, s),
+        Value::Data(ref data) => {
+            out.push('<');
+            for (i, byte) in data.iter().enumerate() {
+                if i > 0 && i % 4 == 0 {
+                    out.push(' ');
+                }
+                write!(out, "{:02x}", byte).unwrap();
+            }
+            out.push('>');
+        }
+        Value::Array(ref array) => {
+            out.push_str("(\n");
+            for (i, item) in array.iter().enumerate() {
+                write_indent(out, level + 1);
+                write_value(out, item, level + 1)?;
+                out.push_str(if i + 1 < array.len() { ",\n" } else { "\n" });
+            }
+            write_indent(out, level);
+            out.push(')');
+        }
+        Value::Dictionary(ref dict) => {
+            out.push_str("{\n");
+            for (key, item) in dict {
+                write_indent(out, level + 1);
+                write_string(out, key);
+                out.push_str(" = ");
+                write_value(out, item, level + 1)?;
+                out.push_str(";\n");
+            }
+            write_indent(out, level);
+            out.push('}');
+        }
+        Value::Integer(_) | Value::Unsigned(_) => return Err(Error::Unsupported("integer")),
+        Value::Real(_) => return Err(Error::Unsupported("real")),
+        Value::Boolean(_) => return Err(Error::Unsupported("boolean")),
+        Value::Date(_) => return Err(Error::Unsupported("date")),
+        Value::Uid(_) => return Err(Error::Unsupported("UID")),
+    }
+    Ok(())
+}
+
+fn write_string(out: &mut String, s: &str) {
+    if !s.is_empty() && s.chars().all(is_unquoted) {
+        out.push_str(s);
+    } else {
+        write_quoted(out, s);
+    }
+}
+
+fn write_quoted(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\U{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use plist::{Error, Value};
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+            // A line comment.
+            {
+                name = "Hello, \"world\"\n";
+                /* A block
+                   comment. */
+                path = /usr/local/bin;
+                list = (one, "two", 'three',);
+                bytes = <0001 02ff>;
+                empty = {};
+                escapes = "\U00e9\101\t\\";
+                emoji = "\UD83D\UDE00";
+            }
+        "#;
+        let mut dict = BTreeMap::new();
+        dict.insert("name".to_owned(), string("Hello, \"world\"\n"));
+        dict.insert("path".to_owned(), string("/usr/local/bin"));
+        dict.insert("list".to_owned(), Value::Array(vec![string("one"), string("two"), string("three")]));
+        dict.insert("bytes".to_owned(), Value::Data(vec![0, 1, 2, 255]));
+        dict.insert("empty".to_owned(), Value::Dictionary(BTreeMap::new()));
+        dict.insert("escapes".to_owned(), string("éA\t\\"));
+        dict.insert("emoji".to_owned(), string("😀"));
+        assert_eq!(from_str(text), Ok(Value::Dictionary(dict)));
+    }
+
+    #[test]
+    fn test_errors_report_line() {
+        assert_eq!(from_str("{\n a = b;\n c = d\n}"), Err(Error::Invalid("line 4: expected ';'".to_owned())));
+        assert!(from_str("(a, b").is_err());
+        assert!(from_str("<0g>").is_err());
+        assert!(from_str("\"a\" b").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut inner = BTreeMap::new();
+        inner.insert("needs quotes".to_owned(), string("tab\there; \"quoted\""));
+        inner.insert("data".to_owned(), Value::Data((0..9).collect()));
+        let value = Value::Array(vec![string("plain"), string(""), Value::Dictionary(inner),
+                                      Value::Array(vec![])]);
+        let text = to_string(&value).unwrap();
+        assert_eq!(text, "(\n    plain,\n    \"\",\n    {\n        data = <00010203 04050607 08>;\n        \
+                          \"needs quotes\" = \"tab\\there; \\\"quoted\\\"\";\n    },\n    (\n    )\n)");
+        assert_eq!(from_str(&text), Ok(value));
+        assert_eq!(to_string(&Value::Integer(1)), Err(Error::Unsupported("integer")));
+    }
+
+    #[test]
+    fn test_strings_utf16() {
+        let text = "/* Menu title */\n\"Quit\" = \"Quitter\";\n\"OK\";\nCancel = \"Annuler\";\n";
+        let mut le = vec![0xFF, 0xFE];
+        let mut be = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16() {
+            le.extend_from_slice(&[unit as u8, (unit >> 8) as u8]);
+            be.extend_from_slice(&[(unit >> 8) as u8, unit as u8]);
+        }
+        let mut expected = BTreeMap::new();
+        expected.insert("Quit".to_owned(), "Quitter".to_owned());
+        expected.insert("OK".to_owned(), "OK".to_owned());
+        expected.insert("Cancel".to_owned(), "Annuler".to_owned());
+        assert_eq!(strings_from_slice(&le), Ok(expected.clone()));
+        assert_eq!(strings_from_slice(&be), Ok(expected.clone()));
+        assert_eq!(strings_from_str(&strings_to_string(&expected)), Ok(expected));
+    }
+}
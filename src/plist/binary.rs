@@ -0,0 +1,516 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The `bplist00` format written by `NSPropertyListBinaryFormat_v1_0`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use super::{Error, Value};
+
+const MAGIC: &[u8] = b"bplist00";
+const TRAILER_LEN: usize = 32;
+
+pub fn from_slice(bytes: &[u8]) -> Result<Value, Error> {
+    if bytes.len() < MAGIC.len() + TRAILER_LEN {
+        return Err(Error::UnexpectedEof);
+    }
+    if &bytes[..MAGIC.len()] != MAGIC {
+        return Err(Error::Invalid("missing bplist00 header".to_owned()));
+    }
+
+    let trailer = &bytes[bytes.len() - TRAILER_LEN..];
+    let offset_size = trailer[6] as usize;
+    let ref_size = trailer[7] as usize;
+    let num_objects = read_uint(&trailer[8..16]) as usize;
+    let top_object = read_uint(&trailer[16..24]) as usize;
+    let table_offset = read_uint(&trailer[24..32]) as usize;
+
+    if offset_size == 0 || offset_size > 8 || ref_size == 0 || ref_size > 8 {
+        return Err(Error::Invalid("bad trailer integer sizes".to_owned()));
+    }
+    let table_len = num_objects.checked_mul(offset_size)
+                               .ok_or_else(|| Error::Invalid("object count overflows".to_owned()))?;
+    let table_end = table_offset.checked_add(table_len)
+                                .ok_or_else(|| Error::Invalid("offset table overflows".to_owned()))?;
+    if table_end > bytes.len() - TRAILER_LEN {
+        return Err(Error::UnexpectedEof);
+    }
+
+    let offsets = bytes[table_offset..table_end].chunks(offset_size)
+                                                .map(|c| read_uint(c) as usize)
+                                                .collect();
+    let mut reader = Reader {
+        bytes,
+        offsets,
+        ref_size,
+        in_progress: vec![false; num_objects],
+        read: vec![None; num_objects],
+        // Writers share only scalars, so a property list holds at most one value per byte.
+        budget: bytes.len(),
+    };
+    reader.read_object(top_object)
+}
+
+pub fn to_vec(value: &Value) -> Vec<u8> {
+    let mut writer = Writer {
+        objects: vec![],
+        scalars: HashMap::new(),
+    };
+    writer.flatten(value);
+
+    let ref_size = int_size(writer.objects.len() as u64);
+    let mut out = MAGIC.to_vec();
+    let mut offsets = Vec::with_capacity(writer.objects.len());
+    for object in &writer.objects {
+        offsets.push(out.len() as u64);
+        match *object {
+            Entry::Scalar(ref encoded) => out.extend_from_slice(encoded),
+            Entry::Array(ref refs) => {
+                write_marker(&mut out, 0xA0, refs.len());
+                for &r in refs {
+                    write_uint(&mut out, r as u64, ref_size);
+                }
+            }
+            Entry::Dictionary(ref keys, ref values) => {
+                write_marker(&mut out, 0xD0, keys.len());
+                for &r in keys.iter().chain(values) {
+                    write_uint(&mut out, r as u64, ref_size);
+                }
+            }
+        }
+    }
+
+    let table_offset = out.len() as u64;
+    let offset_size = int_size(table_offset);
+    for offset in offsets {
+        write_uint(&mut out, offset, offset_size);
+    }
+    out.extend_from_slice(&[0; 6]);
+    out.push(offset_size as u8);
+    out.push(ref_size as u8);
+    write_uint(&mut out, writer.objects.len() as u64, 8);
+    write_uint(&mut out, 0, 8);
+    write_uint(&mut out, table_offset, 8);
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+    in_progress: Vec<bool>,
+    /// Objects already read, with how many values each one holds, since an object may be
+    /// referenced from many places.
+    read: Vec<Option<(Value, usize)>>,
+    /// How many more values may be read. Containers referenced from several places could
+    /// otherwise expand exponentially.
+    budget: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_object(&mut self, index: usize) -> Result<Value, Error> {
+        let offset = match self.offsets.get(index) {
+            Some(&offset) => offset,
+            None => return Err(Error::Invalid(format!("object reference {} out of range", index))),
+        };
+        if let Some((ref value, count)) = self.read[index] {
+            let value = value.clone();
+            self.spend(count)?;
+            return Ok(value);
+        }
+        if self.in_progress[index] {
+            return Err(Error::Invalid(format!("object {} contains itself", index)));
+        }
+        self.in_progress[index] = true;
+        let budget = self.budget;
+        let value = self.read_object_at(offset).and_then(|value| self.spend(1).map(|()| value));
+        self.in_progress[index] = false;
+        let value = value?;
+        self.read[index] = Some((value.clone(), budget - self.budget));
+        Ok(value)
+    }
+
+    fn spend(&mut self, count: usize) -> Result<(), Error> {
+        match self.budget.checked_sub(count) {
+            Some(budget) => {
+                self.budget = budget;
+                Ok(())
+            }
+            None => Err(Error::Invalid("shared objects expand to more values than the file holds".to_owned())),
+        }
+    }
+
+    fn read_object_at(&mut self, offset: usize) -> Result<Value, Error> {
+        let marker = *self.bytes.get(offset).ok_or(Error::UnexpectedEof)?;
+        let low = (marker & 0x0F) as usize;
+        match marker >> 4 {
+            0x0 => match marker {
+                0x08 => Ok(Value::Boolean(false)),
+                0x09 => Ok(Value::Boolean(true)),
+                _ => Err(Error::Unsupported("null or fill object")),
+            },
+            0x1 => {
+                let (value, _) = self.read_int(offset)?;
+                Ok(match i64::try_from(value) {
+                    Ok(value) => Value::Integer(value),
+                    Err(_) => Value::Unsigned(value as u64),
+                })
+            }
+            0x2 => {
+                let bytes = self.slice(offset + 1, 1 << low)?;
+                match low {
+                    2 => Ok(Value::Real(f32::from_bits(read_uint(bytes) as u32) as f64)),
+                    3 => Ok(Value::Real(f64::from_bits(read_uint(bytes)))),
+                    _ => Err(Error::Invalid(format!("{}-byte real", 1 << low))),
+                }
+            }
+            0x3 if marker == 0x33 => {
+                let bytes = self.slice(offset + 1, 8)?;
+                Ok(Value::Date(f64::from_bits(read_uint(bytes))))
+            }
+            0x4 => {
+                let (start, len) = self.read_len(offset, low)?;
+                Ok(Value::Data(self.slice(start, len)?.to_vec()))
+            }
+            0x5 => {
+                let (start, len) = self.read_len(offset, low)?;
+                let bytes = self.slice(start, len)?;
+                if !bytes.is_ascii() {
+                    return Err(Error::Invalid("non-ASCII byte in ASCII string".to_owned()));
+                }
+                Ok(Value::String(bytes.iter().map(|&b| b as char).collect()))
+            }
+            0x6 => {
+                let (start, len) = self.read_len(offset, low)?;
+                let units = len.checked_mul(2).ok_or(Error::UnexpectedEof)?;
+                let bytes = self.slice(start, units)?;
+                let units: Vec<u16> = bytes.chunks(2).map(|c| (c[0] as u16) << 8 | c[1] as u16).collect();
+                String::from_utf16(&units).map(Value::String)
+                                          .map_err(|_| Error::Invalid("unpaired UTF-16 surrogate".to_owned()))
+            }
+            0x8 => Ok(Value::Uid(read_uint(self.slice(offset + 1, low + 1)?))),
+            0xA | 0xC => {
+                let (start, len) = self.read_len(offset, low)?;
+                let refs = self.read_refs(start, len)?;
+                let mut array = Vec::with_capacity(refs.len());
+                for r in refs {
+                    array.push(self.read_object(r)?);
+                }
+                Ok(Value::Array(array))
+            }
+            0xD => {
+                let (start, len) = self.read_len(offset, low)?;
+                let refs = self.read_refs(start, len.checked_mul(2).ok_or(Error::UnexpectedEof)?)?;
+                let (keys, values) = refs.split_at(len);
+                let mut dict = BTreeMap::new();
+                for (&k, &v) in keys.iter().zip(values) {
+                    let key = match self.read_object(k)? {
+                        Value::String(key) => key,
+                        _ => return Err(Error::Unsupported("non-string dictionary key")),
+                    };
+                    dict.insert(key, self.read_object(v)?);
+                }
+                Ok(Value::Dictionary(dict))
+            }
+            _ => Err(Error::Invalid(format!("unknown object marker {:#04x}", marker))),
+        }
+    }
+
+    /// Reads an integer object, returning its value and the offset just past it.
+    fn read_int(&self, offset: usize) -> Result<(i128, usize), Error> {
+        let marker = *self.bytes.get(offset).ok_or(Error::UnexpectedEof)?;
+        if marker >> 4 != 0x1 {
+            return Err(Error::Invalid(format!("expected integer, found marker {:#04x}", marker)));
+        }
+        let size = 1 << (marker & 0x0F);
+        let bytes = self.slice(offset + 1, size)?;
+        let value = match size {
+            // Smaller integers are signed.
+            1 | 2 | 4 | 8 => read_uint(bytes) as i64 as i128,
+            // 128-bit integers are written for values above i64::MAX; accept those below 2^64.
+            16 if bytes[..8].iter().all(|&b| b == 0) => read_uint(&bytes[8..]) as i128,
+            _ => return Err(Error::Unsupported("integer wider than 64 bits")),
+        };
+        Ok((value, offset + 1 + size))
+    }
+
+    /// Reads the element count that follows a marker, which is either the marker's low nibble
+    /// or, if that is 0xF, a separate integer object.
+    fn read_len(&self, offset: usize, low: usize) -> Result<(usize, usize), Error> {
+        if low != 0x0F {
+            return Ok((offset + 1, low));
+        }
+        let (len, end) = self.read_int(offset + 1)?;
+        let len = usize::try_from(len).map_err(|_| Error::Invalid(format!("length {} out of range", len)))?;
+        Ok((end, len))
+    }
+
+    fn read_refs(&self, start: usize, count: usize) -> Result<Vec<usize>, Error> {
+        let len = count.checked_mul(self.ref_size).ok_or(Error::UnexpectedEof)?;
+        let bytes = self.slice(start, len)?;
+        Ok(bytes.chunks(self.ref_size).map(|c| read_uint(c) as usize).collect())
+    }
+
+    fn slice(&self, start: usize, len: usize) -> Result<&'a [u8], Error> {
+        let end = start.checked_add(len).ok_or(Error::UnexpectedEof)?;
+        self.bytes.get(start..end).ok_or(Error::UnexpectedEof)
+    }
+}
+
+enum Entry {
+    Scalar(Vec<u8>),
+    Array(Vec<usize>),
+    Dictionary(Vec<usize>, Vec<usize>),
+}
+
+struct Writer {
+    objects: Vec<Entry>,
+    /// Identical scalars are written once and shared, as `NSPropertyListSerialization` does.
+    scalars: HashMap<Vec<u8>, usize>,
+}
+
+impl Writer {
+    fn flatten(&mut self, value: &Value) -> usize {
+        match *value {
+            Value::Array(ref array) => {
+                let index = self.reserve();
+                let refs = array.iter().map(|v| self.flatten(v)).collect();
+                self.objects[index] = Entry::Array(refs);
+                index
+            }
+            Value::Dictionary(ref dict) => {
+                let index = self.reserve();
+                let keys = dict.keys().map(|k| self.flatten_string(k)).collect();
+                let values = dict.values().map(|v| self.flatten(v)).collect();
+                self.objects[index] = Entry::Dictionary(keys, values);
+                index
+            }
+            Value::String(ref s) => self.flatten_string(s),
+            _ => {
+                let mut encoded = vec![];
+                encode_scalar(&mut encoded, value);
+                self.intern(encoded)
+            }
+        }
+    }
+
+    fn flatten_string(&mut self, s: &str) -> usize {
+        let mut encoded = vec![];
+        encode_string(&mut encoded, s);
+        self.intern(encoded)
+    }
+
+    fn reserve(&mut self) -> usize {
+        self.objects.push(Entry::Array(vec![]));
+        self.objects.len() - 1
+    }
+
+    fn intern(&mut self, encoded: Vec<u8>) -> usize {
+        if let Some(&index) = self.scalars.get(&encoded) {
+            return index;
+        }
+        let index = self.objects.len();
+        self.scalars.insert(encoded.clone(), index);
+        self.objects.push(Entry::Scalar(encoded));
+        index
+    }
+}
+
+fn encode_scalar(out: &mut Vec<u8>, value: &Value) {
+    match *value {
+        Value::Boolean(false) => out.push(0x08),
+        Value::Boolean(true) => out.push(0x09),
+        Value::Integer(i) => write_int(out, i),
+        Value::Unsigned(u) => {
+            out.push(0x14);
+            write_uint(out, 0, 8);
+            write_uint(out, u, 8);
+        }
+        Value::Real(r) => {
+            out.push(0x23);
+            write_uint(out, r.to_bits(), 8);
+        }
+        Value::Date(d) => {
+            out.push(0x33);
+            write_uint(out, d.to_bits(), 8);
+        }
+        Value::Data(ref data) => {
+            write_marker(out, 0x40, data.len());
+            out.extend_from_slice(data);
+        }
+        Value::Uid(uid) => {
+            let size = int_size(uid);
+            out.push(0x80 | (size - 1) as u8);
+            write_uint(out, uid, size);
+        }
+        Value::String(ref s) => encode_string(out, s),
+        Value::Array(_) | Value::Dictionary(_) => unreachable!("containers are flattened"),
+    }
+}
+
+fn encode_string(out: &mut Vec<u8>, s: &str) {
+    if s.is_ascii() {
+        write_marker(out, 0x50, s.len());
+        out.extend_from_slice(s.as_bytes());
+    } else {
+        let units: Vec<u16> = s.encode_utf16().collect();
+        write_marker(out, 0x60, units.len());
+        for unit in units {
+            write_uint(out, unit as u64, 2);
+        }
+    }
+}
+
+fn write_marker(out: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 0x0F {
+        out.push(kind | len as u8);
+    } else {
+        out.push(kind | 0x0F);
+        write_int(out, len as i64);
+    }
+}
+
+/// Writes an integer object. Only the 8-byte form is signed, so negative values always use it.
+fn write_int(out: &mut Vec<u8>, value: i64) {
+    let size = if value < 0 { 8 } else { int_size(value as u64) };
+    out.push(0x10 | size.trailing_zeros() as u8);
+    write_uint(out, value as u64, size);
+}
+
+/// The smallest of 1, 2, 4 or 8 bytes that can hold `value`.
+fn int_size(value: u64) -> usize {
+    if value <= 0xFF {
+        1
+    } else if value <= 0xFFFF {
+        2
+    } else if value <= 0xFFFF_FFFF {
+        4
+    } else {
+        8
+    }
+}
+
+fn write_uint(out: &mut Vec<u8>, value: u64, size: usize) {
+    for i in (0..size).rev() {
+        out.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn read_uint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &b| acc << 8 | b as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use plist::Value;
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut dict = BTreeMap::new();
+        dict.insert("int".to_owned(), Value::Integer(-42));
+        dict.insert("big".to_owned(), Value::Integer(1 << 40));
+        dict.insert("max".to_owned(), Value::Integer(i64::MAX));
+        dict.insert("unsigned".to_owned(), Value::Unsigned(u64::MAX));
+        dict.insert("real".to_owned(), Value::Real(0.5));
+        dict.insert("yes".to_owned(), Value::Boolean(true));
+        dict.insert("date".to_owned(), Value::Date(-3600.25));
+        dict.insert("data".to_owned(), Value::Data((0..40).collect()));
+        dict.insert("unicode".to_owned(), Value::String("Iñtërnâtiônàlizætiøn".to_owned()));
+        dict.insert("uid".to_owned(), Value::Uid(300));
+        dict.insert("array".to_owned(),
+                    Value::Array(vec![Value::String("int".to_owned()), Value::Array(vec![])]));
+        let value = Value::Dictionary(dict);
+
+        assert_eq!(from_slice(&to_vec(&value)), Ok(value));
+    }
+
+    #[test]
+    fn test_shares_identical_scalars() {
+        let value = Value::Array(vec![Value::String("same".to_owned()); 20]);
+        let bytes = to_vec(&value);
+        // One array and one string.
+        assert_eq!(read_uint(&bytes[bytes.len() - 24..bytes.len() - 16]), 2);
+        assert_eq!(from_slice(&bytes), Ok(value));
+    }
+
+    #[test]
+    fn test_rejects_cycles() {
+        // An array whose only element is itself.
+        let mut bytes = b"bplist00\xa1\x00".to_vec();
+        bytes.push(8);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 10]);
+        assert!(from_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_sixteen_byte_integers() {
+        // A lone 16-byte integer object, 2^63.
+        let mut bytes = b"bplist00\x14".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.push(8);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 25]);
+        assert_eq!(from_slice(&bytes), Ok(Value::Unsigned(1 << 63)));
+
+        // Above 2^64.
+        bytes[16] = 1;
+        assert_eq!(from_slice(&bytes), Err(Error::Unsupported("integer wider than 64 bits")));
+
+        // Below i64::MAX.
+        bytes[16] = 0;
+        bytes[17] = 0x7F;
+        assert_eq!(from_slice(&bytes), Ok(Value::Integer(0x7F << 56)));
+    }
+
+    /// A binary property list of `objects`, with the last one at the top.
+    fn bplist(objects: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let mut offsets = vec![];
+        for object in objects {
+            offsets.push(bytes.len() as u64);
+            bytes.extend_from_slice(object);
+        }
+        let table_offset = bytes.len() as u64;
+        for offset in offsets {
+            write_uint(&mut bytes, offset, 2);
+        }
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 2, 1]);
+        write_uint(&mut bytes, objects.len() as u64, 8);
+        write_uint(&mut bytes, objects.len() as u64 - 1, 8);
+        write_uint(&mut bytes, table_offset, 8);
+        bytes
+    }
+
+    #[test]
+    fn test_shared_containers() {
+        let objects = vec![vec![0x10, 7], vec![0xA2, 0, 0], vec![0xA2, 1, 1]];
+        let pair = Value::Array(vec![Value::Integer(7); 2]);
+        assert_eq!(from_slice(&bplist(&objects)), Ok(Value::Array(vec![pair.clone(), pair])));
+
+        // Each array holds the one before it twice, so the last expands to 2^60 values.
+        let mut objects = vec![vec![0xA0]];
+        for i in 0..60 {
+            objects.push(vec![0xA2, i, i]);
+        }
+        let error = Error::Invalid("shared objects expand to more values than the file holds".to_owned());
+        assert_eq!(from_slice(&bplist(&objects)), Err(error));
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(from_slice(b"bplist00"), Err(Error::UnexpectedEof));
+    }
+}
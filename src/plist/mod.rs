@@ -0,0 +1,144 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Property lists, as read and written by `NSPropertyListSerialization`.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+pub mod ascii;
+pub mod binary;
+#[cfg(feature = "serde")]
+pub mod serde;
+
+/// A property list object.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    /// An integer above `i64::MAX`, which binary property lists store in 16 bytes. Smaller
+    /// integers are always `Integer`.
+    Unsigned(u64),
+    Real(f64),
+    Boolean(bool),
+    /// Seconds since the Cocoa reference date, 2001-01-01 00:00:00 UTC.
+    Date(f64),
+    Data(Vec<u8>),
+    Array(Vec<Value>),
+    Dictionary(BTreeMap<String, Value>),
+    /// An object reference. Only keyed archives use these.
+    Uid(u64),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_dictionary(&self) -> Option<&BTreeMap<String, Value>> {
+        match *self {
+            Value::Dictionary(ref d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_uid(&self) -> Option<u64> {
+        match *self {
+            Value::Uid(uid) => Some(uid),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The input ended before a complete property list was read.
+    UnexpectedEof,
+    /// The input is malformed; the string describes where.
+    Invalid(String),
+    /// The value has no representation in the requested format.
+    Unsupported(&'static str),
+    /// An error inside a value, along with the path to it from the outermost value.
+    At(Vec<PathSegment>, Box<Error>),
+}
+
+/// A step from a value to one inside it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl Error {
+    #[cfg(any(feature = "serde", target_os = "macos"))]
+    pub(crate) fn at(self, segment: PathSegment) -> Error {
+        match self {
+            Error::At(mut path, error) => {
+                path.insert(0, segment);
+                Error::At(path, error)
+            }
+            error => Error::At(vec![segment], Box::new(error)),
+        }
+    }
+
+    pub fn path(&self) -> &[PathSegment] {
+        match *self {
+            Error::At(ref path, _) => path,
+            _ => &[],
+        }
+    }
+
+    /// The path as written in Rust, such as `shapes[2].width`. Empty at the top level.
+    pub fn key_path(&self) -> String {
+        let mut key_path = String::new();
+        for segment in self.path() {
+            match *segment {
+                PathSegment::Key(ref key) if key_path.is_empty() => key_path.push_str(key),
+                PathSegment::Key(ref key) => {
+                    key_path.push('.');
+                    key_path.push_str(key);
+                }
+                PathSegment::Index(index) => key_path.push_str(&format!("[{}]", index)),
+            }
+        }
+        key_path
+    }
+
+    /// The error without its path.
+    pub fn inner(&self) -> &Error {
+        match *self {
+            Error::At(_, ref error) => error,
+            ref error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnexpectedEof => f.write_str("unexpected end of property list"),
+            Error::Invalid(ref what) => write!(f, "invalid property list: {}", what),
+            Error::Unsupported(what) => write!(f, "unsupported property list value: {}", what),
+            Error::At(_, ref error) => write!(f, "{}: {}", self.key_path(), error),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,806 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serde support, mapping Rust data onto property list values.
+//!
+//! Structs and maps become dictionaries, sequences and tuples arrays, and byte buffers data.
+//! Property lists have no null, so `None` and `()` fields are left out of their dictionary, and
+//! anywhere else are an error. Unit variants are strings; other variants are dictionaries with
+//! the variant name as their only key. Wrap times in `Date` to get dates.
+//!
+//! Errors inside a value carry the key path to it, as `Error::At`.
+
+use std::collections::{btree_map, BTreeMap};
+use std::fmt::{self, Display};
+use std::time::SystemTime;
+use std::vec;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use date;
+use super::{Error, PathSegment, Value};
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Error {
+        Error::Invalid(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Error {
+        Error::Invalid(msg.to_string())
+    }
+}
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(Serializer)?.ok_or(Error::Unsupported("None outside a dictionary"))
+}
+
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+    T::deserialize(value)
+}
+
+const DATE_NEWTYPE: &str = "$cocoa-rs::Date";
+
+/// A time that serializes to a date. Other serializers see the seconds since the Cocoa
+/// reference date.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Date(pub SystemTime);
+
+impl Serialize for Date {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(DATE_NEWTYPE, &date::reference_interval_from_system_time(self.0))
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Date {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Date, D::Error> {
+        struct DateVisitor;
+
+        impl<'de> Visitor<'de> for DateVisitor {
+            type Value = Date;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a date")
+            }
+
+            fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Date, D::Error> {
+                let interval = <f64 as de::Deserialize>::deserialize(deserializer)?;
+                date::system_time_from_reference_interval(interval)
+                    .map(Date)
+                    .ok_or_else(|| de::Error::custom(format!("date {} out of range", interval)))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(DATE_NEWTYPE, DateVisitor)
+    }
+}
+
+/// Serializes to `Some(value)`, or to `None` for values a dictionary should leave out.
+struct Serializer;
+
+fn element<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(Serializer)?.ok_or(Error::Unsupported("None in an array"))
+}
+
+fn variant(name: &'static str, value: Value) -> Value {
+    let mut dictionary = BTreeMap::new();
+    dictionary.insert(name.to_owned(), value);
+    Value::Dictionary(dictionary)
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeDictionary;
+    type SerializeStruct = SerializeDictionary;
+    type SerializeStructVariant = SerializeDictionary;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<Value>, Error> {
+        Ok(Some(Value::Boolean(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Option<Value>, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Option<Value>, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Option<Value>, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Option<Value>, Error> {
+        Ok(Some(Value::Integer(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Option<Value>, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Option<Value>, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Option<Value>, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Option<Value>, Error> {
+        if v > i64::MAX as u64 {
+            return Ok(Some(Value::Unsigned(v)));
+        }
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Option<Value>, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Option<Value>, Error> {
+        Ok(Some(Value::Real(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Option<Value>, Error> {
+        Ok(Some(Value::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Option<Value>, Error> {
+        Ok(Some(Value::String(v.to_owned())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<Value>, Error> {
+        Ok(Some(Value::Data(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Option<Value>, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str)
+                              -> Result<Option<Value>, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T)
+                                                       -> Result<Option<Value>, Error> {
+        match value.serialize(self)? {
+            Some(Value::Real(interval)) if name == DATE_NEWTYPE => Ok(Some(Value::Date(interval))),
+            value => Ok(value),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, name: &'static str,
+                                                        value: &T) -> Result<Option<Value>, Error> {
+        let value = element(value).map_err(|e| e.at(PathSegment::Key(name.to_owned())))?;
+        Ok(Some(variant(name, value)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray { items: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, name: &'static str, len: usize)
+                               -> Result<SerializeArray, Error> {
+        Ok(SerializeArray { items: Vec::with_capacity(len), variant: Some(name) })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<SerializeDictionary, Error> {
+        Ok(SerializeDictionary { entries: BTreeMap::new(), key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<SerializeDictionary, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, name: &'static str, _: usize)
+                                -> Result<SerializeDictionary, Error> {
+        Ok(SerializeDictionary { entries: BTreeMap::new(), key: None, variant: Some(name) })
+    }
+}
+
+struct SerializeArray {
+    items: Vec<Value>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeArray {
+    fn finish(self) -> Result<Option<Value>, Error> {
+        let array = Value::Array(self.items);
+        Ok(Some(match self.variant {
+            Some(name) => variant(name, array),
+            None => array,
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let mut item = element(value).map_err(|e| e.at(PathSegment::Index(self.items.len())));
+        if let Some(name) = self.variant {
+            item = item.map_err(|e| e.at(PathSegment::Key(name.to_owned())));
+        }
+        self.items.push(item?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Value>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Value>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Value>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Value>, Error> {
+        self.finish()
+    }
+}
+
+struct SerializeDictionary {
+    entries: BTreeMap<String, Value>,
+    key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeDictionary {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        let mut value = value.serialize(Serializer).map_err(|e| e.at(PathSegment::Key(key.clone())));
+        if let Some(name) = self.variant {
+            value = value.map_err(|e| e.at(PathSegment::Key(name.to_owned())));
+        }
+        if let Some(value) = value? {
+            self.entries.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, Error> {
+        let dictionary = Value::Dictionary(self.entries);
+        Ok(Some(match self.variant {
+            Some(name) => variant(name, dictionary),
+            None => dictionary,
+        }))
+    }
+}
+
+impl ser::SerializeMap for SerializeDictionary {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match element(key)? {
+            Value::String(key) => self.key = Some(key),
+            Value::Integer(i) => self.key = Some(i.to_string()),
+            Value::Unsigned(u) => self.key = Some(u.to_string()),
+            _ => return Err(Error::Unsupported("non-string dictionary key")),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Option<Value>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeDictionary {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Option<Value>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeDictionary {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Option<Value>, Error> {
+        self.finish()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::String(s) => visitor.visit_string(s),
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::Unsigned(u) => visitor.visit_u64(u),
+            Value::Real(f) | Value::Date(f) => visitor.visit_f64(f),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Data(bytes) => visitor.visit_byte_buf(bytes),
+            Value::Array(items) => {
+                let mut items = Items { items: items.into_iter(), index: 0 };
+                let value = visitor.visit_seq(&mut items)?;
+                match items.items.len() {
+                    0 => Ok(value),
+                    rest => Err(de::Error::invalid_length(items.index + rest, &"fewer elements in array")),
+                }
+            }
+            Value::Dictionary(entries) => visitor.visit_map(Entries { entries: entries.into_iter(), value: None }),
+            Value::Uid(uid) => visitor.visit_u64(uid),
+        }
+    }
+
+    /// Values that are present are never `None`; missing fields are handled by serde.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    /// `()` is serialized by leaving it out, so any value may stand in for it.
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V)
+                                                   -> Result<V::Value, Error> {
+        if name != DATE_NEWTYPE {
+            return visitor.visit_newtype_struct(self);
+        }
+        match self {
+            Value::Date(interval) => visitor.visit_newtype_struct(interval.into_deserializer()),
+            _ => Err(Error::Invalid("expected a date".to_owned())),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V)
+                                         -> Result<V::Value, Error> {
+        match self {
+            Value::String(name) => visitor.visit_enum(Variant { name, value: None }),
+            Value::Dictionary(entries) => {
+                let mut entries = entries.into_iter();
+                match (entries.next(), entries.next()) {
+                    (Some((name, value)), None) => visitor.visit_enum(Variant { name, value: Some(value) }),
+                    _ => Err(Error::Invalid("an enum dictionary must have exactly one key".to_owned())),
+                }
+            }
+            _ => Err(Error::Invalid("expected a string or dictionary for an enum".to_owned())),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct Items {
+    items: vec::IntoIter<Value>,
+    index: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Items {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        match self.items.next() {
+            Some(value) => {
+                let index = self.index;
+                self.index += 1;
+                seed.deserialize(value).map(Some).map_err(|e| e.at(PathSegment::Index(index)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct Entries {
+    entries: btree_map::IntoIter<String, Value>,
+    value: Option<(String, Value)>,
+}
+
+impl<'de> de::MapAccess<'de> for Entries {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                let result = seed.deserialize(Key(key.clone())).map_err(|e| e.at(PathSegment::Key(key.clone())));
+                self.value = Some((key, value));
+                result.map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (key, value) = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(value).map_err(|e| e.at(PathSegment::Key(key)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+/// A dictionary key, which integers were written to as strings.
+struct Key(String);
+
+impl<'de> IntoDeserializer<'de, Error> for Key {
+    type Deserializer = Key;
+
+    fn into_deserializer(self) -> Key {
+        self
+    }
+}
+
+macro_rules! deserialize_integer_key {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.parse() {
+                    Ok(i) => visitor.$visit(i),
+                    // Let the visitor report the mismatch.
+                    Err(_) => visitor.visit_string(self.0),
+                }
+            }
+        )*
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Key {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_integer_key! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V)
+                                                   -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V)
+                                         -> Result<V::Value, Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char str string bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct Variant {
+    name: String,
+    value: Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for Variant {
+    type Error = Error;
+    type Variant = VariantValue;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, VariantValue), Error> {
+        let name = seed.deserialize(self.name.as_str().into_deserializer())?;
+        Ok((name, VariantValue { name: self.name, value: self.value }))
+    }
+}
+
+struct VariantValue {
+    name: String,
+    value: Option<Value>,
+}
+
+impl VariantValue {
+    fn value(self) -> Result<(Value, PathSegment), Error> {
+        match self.value {
+            Some(value) => Ok((value, PathSegment::Key(self.name))),
+            None => Err(Error::Invalid("expected a dictionary for a variant with data".to_owned())),
+        }
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantValue {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let (value, segment) = self.value()?;
+        seed.deserialize(value).map_err(|e| e.at(segment))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        let (value, segment) = self.value()?;
+        de::Deserializer::deserialize_seq(value, visitor).map_err(|e| e.at(segment))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        let (value, segment) = self.value()?;
+        de::Deserializer::deserialize_map(value, visitor).map_err(|e| e.at(segment))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Point(i32, i32),
+        Rect { width: u32, height: u32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        name: String,
+        volume: u8,
+        ratio: f32,
+        enabled: bool,
+        nickname: Option<String>,
+        tags: Vec<String>,
+        shapes: Vec<Shape>,
+        counts: BTreeMap<String, i64>,
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            name: "main".to_owned(),
+            volume: 7,
+            ratio: 0.5,
+            enabled: true,
+            nickname: None,
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            shapes: vec![Shape::Empty, Shape::Circle(1.5), Shape::Point(1, -2), Shape::Rect { width: 3, height: 4 }],
+            counts: vec![("x".to_owned(), 1)].into_iter().collect(),
+        }
+    }
+
+    fn dictionary(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dictionary(entries.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+    }
+
+    #[test]
+    fn test_to_value() {
+        let value = to_value(&settings()).unwrap();
+        let expected = dictionary(vec![
+            ("name", Value::String("main".to_owned())),
+            ("volume", Value::Integer(7)),
+            ("ratio", Value::Real(0.5)),
+            ("enabled", Value::Boolean(true)),
+            ("tags", Value::Array(vec![Value::String("a".to_owned()), Value::String("b".to_owned())])),
+            ("shapes", Value::Array(vec![
+                Value::String("Empty".to_owned()),
+                dictionary(vec![("Circle", Value::Real(1.5))]),
+                dictionary(vec![("Point", Value::Array(vec![Value::Integer(1), Value::Integer(-2)]))]),
+                dictionary(vec![("Rect", dictionary(vec![("width", Value::Integer(3)),
+                                                         ("height", Value::Integer(4))]))]),
+            ])),
+            ("counts", dictionary(vec![("x", Value::Integer(1))])),
+        ]);
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = to_value(&settings()).unwrap();
+        assert_eq!(from_value::<Settings>(value).unwrap(), settings());
+
+        let mut with_nickname = settings();
+        with_nickname.nickname = Some("n".to_owned());
+        assert_eq!(from_value::<Settings>(to_value(&with_nickname).unwrap()).unwrap(), with_nickname);
+    }
+
+    #[test]
+    fn test_integer_keys() {
+        let mut map = BTreeMap::new();
+        map.insert(3u32, -1i64);
+        map.insert(10, 2);
+        let value = to_value(&map).unwrap();
+        assert_eq!(value, dictionary(vec![("10", Value::Integer(2)), ("3", Value::Integer(-1))]));
+        assert_eq!(from_value::<BTreeMap<u32, i64>>(value).unwrap(), map);
+        assert!(from_value::<BTreeMap<u32, i64>>(dictionary(vec![("x", Value::Integer(1))])).is_err());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(to_value(&None::<i32>), Err(Error::Unsupported("None outside a dictionary")));
+        let error = to_value(&vec![Some(1), None]).unwrap_err();
+        assert_eq!(error, Error::At(vec![PathSegment::Index(1)], Box::new(Error::Unsupported("None in an array"))));
+        let mut map = BTreeMap::new();
+        map.insert(vec![1], 1);
+        assert_eq!(to_value(&map), Err(Error::Unsupported("non-string dictionary key")));
+    }
+
+    #[test]
+    fn test_errors() {
+        let missing = dictionary(vec![("name", Value::String("main".to_owned()))]);
+        let error = from_value::<Settings>(missing).unwrap_err();
+        assert_eq!(error, Error::Invalid("missing field `volume`".to_owned()));
+
+        let error = from_value::<u8>(Value::Integer(300)).unwrap_err();
+        assert!(error.to_string().contains("300"), "{}", error);
+        assert!(from_value::<Shape>(dictionary(vec![])).is_err());
+
+        let error = from_value::<(i64,)>(Value::Array(vec![Value::Integer(1), Value::Integer(2)])).unwrap_err();
+        assert!(error.to_string().contains("invalid length 2"), "{}", error);
+    }
+
+    #[test]
+    fn test_key_paths() {
+        let mut nested = BTreeMap::new();
+        nested.insert("list", vec![Some(1), None]);
+        let error = to_value(&vec![nested]).unwrap_err();
+        assert_eq!(error.key_path(), "[0].list[1]");
+        assert_eq!(error.path(), &[PathSegment::Index(0), PathSegment::Key("list".to_owned()), PathSegment::Index(1)]);
+        assert_eq!(error.to_string(), "[0].list[1]: unsupported property list value: None in an array");
+
+        let mut value = to_value(&settings()).unwrap();
+        if let Value::Dictionary(ref mut entries) = value {
+            let rect = dictionary(vec![("width", Value::String("wide".to_owned())), ("height", Value::Integer(4))]);
+            entries.insert("shapes".to_owned(), Value::Array(vec![Value::String("Empty".to_owned()),
+                                                                  dictionary(vec![("Rect", rect)])]));
+        }
+        let error = from_value::<Settings>(value).unwrap_err();
+        assert_eq!(error.key_path(), "shapes[1].Rect.width");
+        assert!(error.inner().to_string().contains("invalid type: string \"wide\""), "{}", error);
+
+        let counts = dictionary(vec![("x", Value::Array(vec![Value::Integer(300)]))]);
+        assert_eq!(from_value::<BTreeMap<String, Vec<u8>>>(counts).unwrap_err().key_path(), "x[0]");
+        let keys = dictionary(vec![("x", Value::Integer(1))]);
+        assert_eq!(from_value::<BTreeMap<u32, i64>>(keys).unwrap_err().key_path(), "x");
+    }
+
+    #[test]
+    fn test_dates() {
+        let date = Date(UNIX_EPOCH + Duration::from_secs(978307200 + 60));
+        assert_eq!(to_value(&date).unwrap(), Value::Date(60.0));
+        assert_eq!(from_value::<Date>(Value::Date(60.0)).unwrap(), date);
+        let date = from_value::<Date>(Value::Date(-1.5)).unwrap();
+        assert_eq!(date.0, UNIX_EPOCH + Duration::from_millis(978_307_200_000 - 1500));
+        assert_eq!(from_value::<Date>(Value::Real(1.0)), Err(Error::Invalid("expected a date".to_owned())));
+        assert!(from_value::<Date>(Value::Date(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_other_values() {
+        assert_eq!(from_value::<bytes::Bytes>(Value::Data(vec![1, 2])).unwrap().0, vec![1, 2]);
+        assert_eq!(from_value::<f64>(Value::Date(1.0)).unwrap(), 1.0);
+        assert_eq!(from_value::<u64>(Value::Uid(3)).unwrap(), 3);
+        assert_eq!(to_value(&u64::MAX), Ok(Value::Unsigned(u64::MAX)));
+        assert_eq!(from_value::<u64>(Value::Unsigned(u64::MAX)).unwrap(), u64::MAX);
+        assert!(from_value::<i64>(Value::Unsigned(u64::MAX)).is_err());
+        assert_eq!(from_value::<f64>(Value::Integer(2)).unwrap(), 2.0);
+    }
+
+    mod bytes {
+        use std::fmt;
+        use serde::de::{Deserialize, Deserializer, Visitor};
+
+        pub struct Bytes(pub Vec<u8>);
+
+        impl<'de> Deserialize<'de> for Bytes {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+                struct BytesVisitor;
+
+                impl<'de> Visitor<'de> for BytesVisitor {
+                    type Value = Bytes;
+
+                    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        f.write_str("bytes")
+                    }
+
+                    fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Bytes, E> {
+                        Ok(Bytes(bytes))
+                    }
+                }
+
+                deserializer.deserialize_byte_buf(BytesVisitor)
+            }
+        }
+    }
+}
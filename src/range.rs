@@ -0,0 +1,34 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `NSRange`, Foundation's location and length pair, which needs no Objective-C runtime.
+
+#![allow(non_upper_case_globals)]
+
+use base::{NSInteger, NSUInteger};
+
+/// The location Foundation reports when a search finds nothing.
+pub const NSNotFound: NSInteger = NSInteger::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NSRange {
+    pub location: NSUInteger,
+    pub length: NSUInteger,
+}
+
+impl NSRange {
+    #[inline]
+    pub fn new(location: NSUInteger, length: NSUInteger) -> NSRange {
+        NSRange {
+            location,
+            length
+        }
+    }
+}
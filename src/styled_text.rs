@@ -0,0 +1,312 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A plain Rust model of attributed strings, with ranges in UTF-8 bytes.
+//!
+//! `appkit` converts `StyledText` to and from `NSAttributedString`.
+
+use std::ops::Range;
+use base::NSUInteger;
+use range::NSRange;
+
+/// Converts between byte offsets into a string and the UTF-16 offsets Foundation uses.
+#[derive(Clone, Debug)]
+pub struct TextIndex {
+    // The offsets of every character boundary, including the end of the text.
+    utf8: Vec<usize>,
+    utf16: Vec<usize>,
+}
+
+impl TextIndex {
+    pub fn new(text: &str) -> TextIndex {
+        let mut utf8 = Vec::with_capacity(text.len() + 1);
+        let mut utf16 = Vec::with_capacity(text.len() + 1);
+        let mut units = 0;
+        for (offset, c) in text.char_indices() {
+            utf8.push(offset);
+            utf16.push(units);
+            units += c.len_utf16();
+        }
+        utf8.push(text.len());
+        utf16.push(units);
+        TextIndex { utf8, utf16 }
+    }
+
+    pub fn utf16_len(&self) -> usize {
+        self.utf16[self.utf16.len() - 1]
+    }
+
+    /// Panics unless both ends of `range` are character boundaries.
+    pub fn to_ns_range(&self, range: Range<usize>) -> NSRange {
+        let boundary = |offset| match self.utf8.binary_search(&offset) {
+            Ok(i) => self.utf16[i],
+            Err(_) => panic!("byte offset {} is not a character boundary", offset),
+        };
+        let (start, end) = (boundary(range.start), boundary(range.end));
+        assert!(start <= end, "range {:?} ends before it starts", range);
+        NSRange::new(start as NSUInteger, (end - start) as NSUInteger)
+    }
+
+    /// Ends that split a surrogate pair move outwards to take in the whole character, and ends
+    /// past the text move back to its end.
+    pub fn to_range(&self, range: NSRange) -> Range<usize> {
+        let start = range.location as usize;
+        let end = start.saturating_add(range.length as usize);
+        let start = match self.utf16.binary_search(&start) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let end = match self.utf16.binary_search(&end) {
+            Ok(i) => i,
+            Err(i) => i.min(self.utf16.len() - 1),
+        };
+        self.utf8[start.min(end)]..self.utf8[end]
+    }
+}
+
+/// An sRGB color, with components from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+impl Color {
+    pub fn rgb(red: f64, green: f64, blue: f64) -> Color {
+        Color { red, green, blue, alpha: 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Font {
+    /// The PostScript name, such as `Helvetica-Bold`.
+    pub name: String,
+    pub size: f64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Underline {
+    Single,
+    Thick,
+    Double,
+}
+
+impl Underline {
+    /// The `NSUnderlineStyle` value.
+    pub fn raw(self) -> isize {
+        match self {
+            Underline::Single => 0x01,
+            Underline::Thick => 0x02,
+            Underline::Double => 0x09,
+        }
+    }
+
+    /// Ignores the pattern and by-word bits. Returns `None` for no underline.
+    pub fn from_raw(raw: isize) -> Option<Underline> {
+        match raw & 0xff {
+            0x00 => None,
+            0x02 => Some(Underline::Thick),
+            0x09 => Some(Underline::Double),
+            _ => Some(Underline::Single),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextAlignment {
+    Left,
+    Right,
+    Center,
+    Justified,
+    /// Left or right, following the writing direction.
+    Natural,
+}
+
+/// Spacing is in points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParagraphStyle {
+    pub alignment: TextAlignment,
+    pub line_spacing: f64,
+    pub paragraph_spacing: f64,
+    pub first_line_head_indent: f64,
+    pub head_indent: f64,
+    /// Negative values are measured back from the trailing margin.
+    pub tail_indent: f64,
+}
+
+impl Default for ParagraphStyle {
+    fn default() -> ParagraphStyle {
+        ParagraphStyle {
+            alignment: TextAlignment::Natural,
+            line_spacing: 0.0,
+            paragraph_spacing: 0.0,
+            first_line_head_indent: 0.0,
+            head_indent: 0.0,
+            tail_indent: 0.0,
+        }
+    }
+}
+
+/// The attributes of a span. `None` leaves the text view's default in place.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attributes {
+    pub font: Option<Font>,
+    pub foreground_color: Option<Color>,
+    pub background_color: Option<Color>,
+    pub underline: Option<Underline>,
+    pub link: Option<String>,
+    pub paragraph_style: Option<ParagraphStyle>,
+}
+
+impl Attributes {
+    pub fn is_empty(&self) -> bool {
+        *self == Attributes::default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    /// Byte offsets into the text, on character boundaries.
+    pub range: Range<usize>,
+    pub attributes: Attributes,
+}
+
+/// Text with attributes on some of its ranges. Where spans overlap, the attributes each sets
+/// apply in order, so later spans win.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StyledText {
+    pub text: String,
+    pub spans: Vec<Span>,
+}
+
+impl StyledText {
+    pub fn new() -> StyledText {
+        StyledText::default()
+    }
+
+    pub fn plain(text: &str) -> StyledText {
+        StyledText { text: text.to_owned(), spans: vec![] }
+    }
+
+    /// Appends `text`, extending the last span if its attributes are the same.
+    pub fn push(&mut self, text: &str, attributes: Attributes) {
+        let range = self.text.len()..self.text.len() + text.len();
+        self.text.push_str(text);
+        if text.is_empty() || attributes.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some(ref mut last) if last.range.end == range.start && last.attributes == attributes => {
+                last.range.end = range.end;
+                return;
+            }
+            _ => {}
+        }
+        self.spans.push(Span { range, attributes });
+    }
+
+    /// Sorts the spans, drops those that are empty or set nothing, and merges neighbours with the
+    /// same attributes. Overlapping spans are left alone.
+    pub fn normalize(&mut self) {
+        self.spans.retain(|span| !span.range.is_empty() && !span.attributes.is_empty());
+        self.spans.sort_by_key(|span| span.range.start);
+        let mut spans: Vec<Span> = Vec::with_capacity(self.spans.len());
+        for span in self.spans.drain(..) {
+            match spans.last_mut() {
+                Some(ref mut last) if last.range.end == span.range.start && last.attributes == span.attributes => {
+                    last.range.end = span.range.end;
+                    continue;
+                }
+                _ => {}
+            }
+            spans.push(span);
+        }
+        self.spans = spans;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use range::NSRange;
+    use super::*;
+
+    #[test]
+    fn test_text_index() {
+        // "é" is two bytes and one unit, "🎨" four bytes and two units.
+        let index = TextIndex::new("aé🎨b");
+        assert_eq!(index.utf16_len(), 5);
+        assert_eq!(index.to_ns_range(0..7), NSRange::new(0, 4));
+        assert_eq!(index.to_ns_range(3..8), NSRange::new(2, 3));
+        assert_eq!(index.to_range(NSRange::new(2, 3)), 3..8);
+        assert_eq!(index.to_range(NSRange::new(0, 5)), 0..8);
+
+        // Halves of a surrogate pair take in the whole character.
+        assert_eq!(index.to_range(NSRange::new(3, 2)), 3..8);
+        assert_eq!(index.to_range(NSRange::new(2, 1)), 3..7);
+        assert_eq!(index.to_range(NSRange::new(4, 10)), 7..8);
+
+        let empty = TextIndex::new("");
+        assert_eq!(empty.utf16_len(), 0);
+        assert_eq!(empty.to_ns_range(0..0), NSRange::new(0, 0));
+        assert_eq!(empty.to_range(NSRange::new(0, 0)), 0..0);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a character boundary")]
+    fn test_text_index_boundary() {
+        TextIndex::new("é").to_ns_range(1..2);
+    }
+
+    #[test]
+    fn test_underline() {
+        for &underline in &[Underline::Single, Underline::Thick, Underline::Double] {
+            assert_eq!(Underline::from_raw(underline.raw()), Some(underline));
+        }
+        assert_eq!(Underline::from_raw(0), None);
+        // NSUnderlineStyleDouble | NSUnderlinePatternDot | NSUnderlineByWord
+        assert_eq!(Underline::from_raw(0x09 | 0x100 | 0x8000), Some(Underline::Double));
+    }
+
+    #[test]
+    fn test_push() {
+        let bold = Attributes {
+            font: Some(Font { name: "Helvetica-Bold".to_owned(), size: 12.0 }),
+            ..Attributes::default()
+        };
+        let mut text = StyledText::new();
+        text.push("Hi ", bold.clone());
+        text.push("there", bold.clone());
+        text.push(", ", Attributes::default());
+        text.push("", bold.clone());
+        text.push("you", bold.clone());
+        assert_eq!(text.text, "Hi there, you");
+        assert_eq!(text.spans, vec![Span { range: 0..8, attributes: bold.clone() },
+                                    Span { range: 10..13, attributes: bold.clone() }]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let red = Attributes { foreground_color: Some(Color::rgb(1.0, 0.0, 0.0)), ..Attributes::default() };
+        let link = Attributes { link: Some("https://example.com".to_owned()), ..Attributes::default() };
+        let mut text = StyledText {
+            text: "abcdef".to_owned(),
+            spans: vec![
+                Span { range: 2..4, attributes: red.clone() },
+                Span { range: 0..2, attributes: red.clone() },
+                Span { range: 4..4, attributes: link.clone() },
+                Span { range: 4..5, attributes: Attributes::default() },
+                Span { range: 5..6, attributes: link.clone() },
+            ],
+        };
+        text.normalize();
+        assert_eq!(text.spans, vec![Span { range: 0..4, attributes: red }, Span { range: 5..6, attributes: link }]);
+    }
+}
@@ -1,7 +1,17 @@
+#![cfg(target_os = "macos")]
+
 #[macro_use]
 extern crate objc;
 extern crate block;
 extern crate cocoa;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "serde_json")]
+#[macro_use]
+extern crate serde_json;
+
+mod support;
 
 #[cfg(test)]
 mod foundation {
@@ -63,10 +73,1548 @@ mod foundation {
         }
     }
 
+    mod nsnumber {
+        use std::convert::TryFrom;
+        use cocoa::foundation::{NSNumber, NSNumberConversionError, NSNumberValue};
+        use cocoa::base::{nil, YES};
+
+        #[test]
+        fn test_value() {
+            unsafe {
+                assert_eq!(NSNumber::numberWithBool_(nil, YES).value(), NSNumberValue::Bool(true));
+                assert_eq!(NSNumber::numberWithShort_(nil, -3).value(), NSNumberValue::I16(-3));
+                assert_eq!(NSNumber::numberWithInt_(nil, 1 << 20).value(), NSNumberValue::I32(1 << 20));
+                assert_eq!(NSNumber::numberWithLongLong_(nil, -1).value(), NSNumberValue::I64(-1));
+                assert_eq!(NSNumber::numberWithFloat_(nil, 0.5).value(), NSNumberValue::F32(0.5));
+                assert_eq!(NSNumber::numberWithDouble_(nil, 0.1).value(), NSNumberValue::F64(0.1));
+                assert_eq!(NSNumberValue::from(42u8).into_number().intValue(), 42);
+            }
+        }
+
+        #[test]
+        fn test_try_from() {
+            assert_eq!(u8::try_from(NSNumberValue::I32(255)), Ok(255));
+            assert_eq!(u8::try_from(NSNumberValue::I32(256)), Err(NSNumberConversionError::Overflow));
+            assert_eq!(u32::try_from(NSNumberValue::I8(-1)), Err(NSNumberConversionError::Overflow));
+            assert_eq!(i64::try_from(NSNumberValue::F64(-2.0)), Ok(-2));
+            assert_eq!(i64::try_from(NSNumberValue::F64(2.5)), Err(NSNumberConversionError::Inexact));
+            assert_eq!(i64::try_from(NSNumberValue::F64(f64::NEG_INFINITY)),
+                       Err(NSNumberConversionError::Overflow));
+            assert_eq!(u8::try_from(NSNumberValue::F32(f32::INFINITY)), Err(NSNumberConversionError::Overflow));
+            assert_eq!(i64::try_from(NSNumberValue::F64(f64::NAN)), Err(NSNumberConversionError::Inexact));
+            assert_eq!(f64::try_from(NSNumberValue::U64(u64::MAX)),
+                       Err(NSNumberConversionError::Inexact));
+            assert_eq!(f32::try_from(NSNumberValue::F64(0.1)), Err(NSNumberConversionError::Inexact));
+            assert_eq!(f32::try_from(NSNumberValue::F64(0.5)), Ok(0.5));
+            assert_eq!(bool::try_from(NSNumberValue::I32(1)), Err(NSNumberConversionError::NotANumber));
+            assert_eq!(i32::try_from(NSNumberValue::Bool(true)), Err(NSNumberConversionError::NotANumber));
+        }
+    }
+
+    mod nsdate {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+        use cocoa::foundation::{NSComparisonResult, NSDate};
+        use cocoa::base::nil;
+
+        #[test]
+        fn test_intervals() {
+            unsafe {
+                let epoch = NSDate::dateWithTimeIntervalSince1970_(nil, 0.0);
+                assert_eq!(epoch.timeIntervalSinceReferenceDate(), -978307200.0);
+                assert_eq!(epoch.systemTime(), Some(UNIX_EPOCH));
+
+                let later = epoch.dateByAddingTimeInterval_(1.5);
+                assert_eq!(later.timeIntervalSinceDate_(epoch), 1.5);
+                assert_eq!(epoch.compare_(later), NSComparisonResult::NSOrderedAscending);
+                assert_eq!(epoch.laterDate_(later), later);
+            }
+        }
+
+        #[test]
+        fn test_system_time() {
+            let time = UNIX_EPOCH + Duration::new(1_500_000_000, 250_000_000);
+            unsafe {
+                let date = NSDate::dateWithSystemTime(nil, time);
+                assert_eq!(date.timeIntervalSince1970(), 1_500_000_000.25);
+                assert_eq!(date.systemTime(), Some(time));
+                assert!(NSDate::date(nil).systemTime().unwrap() <= SystemTime::now());
+            }
+        }
+    }
+
+    mod nsdata {
+        use cocoa::base64;
+        use cocoa::foundation::{NSData, NSMutableData, NSDataBase64EncodingOptions,
+                                NSDataBase64DecodingOptions, NSDataSearchOptions, NSNotFound, NSRange,
+                                NSUInteger};
+        use cocoa::base::{class, id, nil};
+
+        #[test]
+        fn test_slices() {
+            unsafe {
+                let data = NSData::from_slice(nil, b"hello");
+                assert_eq!(data.as_slice(), b"hello");
+                assert_eq!(data.to_vec(), b"hello".to_vec());
+                assert_eq!(NSData::data(nil).as_slice(), b"");
+            }
+        }
+
+        #[test]
+        fn test_from_vec() {
+            let mut bytes = Vec::with_capacity(64);
+            bytes.extend_from_slice(b"no copy");
+            let ptr = bytes.as_ptr();
+            unsafe {
+                let data = NSData::from_vec(nil, bytes);
+                assert_eq!(data.bytes() as *const u8, ptr);
+                assert_eq!(data.as_slice(), b"no copy");
+            }
+        }
+
+        #[test]
+        fn test_base64_matches_foundation() {
+            let bytes: Vec<u8> = (0..200).collect();
+            unsafe {
+                let data = NSData::from_slice(nil, &bytes);
+                // Every combination of the line length and line ending options.
+                for bits in 0..16 {
+                    let options = NSDataBase64EncodingOptions::from_bits_truncate(bits & 0b11 | (bits & 0b1100) << 2);
+                    let encoded = data.base64EncodedDataWithOptions_(options);
+                    assert_eq!(encoded.as_slice(), base64::encode(&bytes, options).as_bytes());
+
+                    let decoded: id = msg_send![class("NSData"), alloc];
+                    let decoded = decoded.initWithBase64EncodedData_options_(encoded,
+                                                                             NSDataBase64DecodingOptions::all());
+                    assert_eq!(decoded.to_vec(), bytes);
+                }
+            }
+        }
+
+        #[test]
+        fn test_search_matches_foundation() {
+            let haystack = b"abcabcab";
+            let needles: &[&[u8]] = &[b"ab", b"cab", b"bca", b"abd", b""];
+            unsafe {
+                let data = NSData::from_slice(nil, haystack);
+                for &needle in needles {
+                    let needle_data = NSData::from_slice(nil, needle);
+                    for bits in 0..4 {
+                        let options = NSDataSearchOptions::from_bits_truncate(bits);
+                        for start in 0..haystack.len() {
+                            for end in start..haystack.len() + 1 {
+                                let range = NSRange::new(start as NSUInteger, (end - start) as NSUInteger);
+                                let expected = data.rangeOfData_options_range_(needle_data, options, range);
+                                let expected = if expected.location == NSNotFound as NSUInteger {
+                                    None
+                                } else {
+                                    Some(expected)
+                                };
+                                assert_eq!(data.range_of_bytes(needle, options, range), expected);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_mutable() {
+            unsafe {
+                let data = NSMutableData::mutable_from_slice(nil, b"hello");
+                data.append_slice(b", world");
+                assert_eq!(data.as_slice(), b"hello, world");
+                data.replace_range(0..5, b"goodbye");
+                assert_eq!(data.as_slice(), b"goodbye, world");
+                data.as_mut_slice()[0] = b'G';
+                data.setLength_(7);
+                assert_eq!(data.as_slice(), b"Goodbye");
+            }
+        }
+    }
+
+    mod nsarray {
+        use std::env;
+        use std::process::Command;
+        use cocoa::foundation::{ArrayId, FromCocoa, NSArray, NSFastEnumeration, NSMutableArray,
+                                NSString, ToCocoa};
+        use cocoa::base::{class, id, nil, Id, BOOL, YES};
+
+        unsafe fn strings(values: &[&str]) -> Vec<Id> {
+            values.iter().map(|s| Id::from_retained(NSString::alloc(nil).init_str(s))).collect()
+        }
+
+        #[test]
+        fn test_access() {
+            unsafe {
+                let objects = strings(&["a", "b", "c"]);
+                let array: ArrayId = objects.iter().cloned().collect();
+                assert_eq!(array.len(), 3);
+                assert!(array.get(1).unwrap().isEqualToString("b"));
+                assert_eq!(array.get(3), None);
+                assert!(array.first().unwrap().isEqualToString("a"));
+                assert!(array.last().unwrap().isEqualToString("c"));
+                assert!(array.contains(*objects[2]));
+                assert_eq!(array.index_of(*objects[1]), Some(1));
+
+                let other = NSString::alloc(nil).init_str("z");
+                assert!(!array.contains(other));
+                assert_eq!(array.index_of(other), None);
+
+                let empty = NSArray::array(nil);
+                assert_eq!(empty.first(), None);
+                assert_eq!(empty.last(), None);
+                assert_eq!(empty.get(0), None);
+            }
+        }
+
+        #[test]
+        fn test_mutation() {
+            unsafe {
+                let array: id = msg_send![class("NSMutableArray"), new];
+                let array = Id::from_retained(array);
+                let objects = strings(&["a", "b", "c"]);
+                assert!(array.insert(0, *objects[1]));
+                assert!(array.insert(0, *objects[0]));
+                assert!(array.insert(2, *objects[2]));
+                assert!(!array.insert(4, *objects[2]));
+                assert_eq!(NSArray::count(*array), 3);
+
+                let removed = array.remove(1).unwrap();
+                assert!(removed.isEqualToString("b"));
+                assert!(array.remove(2).is_none());
+
+                let replaced = array.replace_at(0, *objects[1]).unwrap();
+                assert!(replaced.isEqualToString("a"));
+                assert!(array.replace_at(2, *objects[1]).is_none());
+                assert!(array.get(0).unwrap().isEqualToString("b"));
+            }
+        }
+
+        #[test]
+        fn test_sort_by() {
+            unsafe {
+                let array = NSMutableArray::arrayWithCapacity_(nil, 4);
+                for object in strings(&["pear", "fig", "banana", "apple"]) {
+                    array.addObject_(*object);
+                }
+                array.sort_by(|a, b| a.len().cmp(&b.len()));
+                assert!(array.first().unwrap().isEqualToString("fig"));
+                assert!(array.last().unwrap().isEqualToString("banana"));
+            }
+        }
+
+        #[test]
+        fn test_into_iter() {
+            unsafe {
+                let array: ArrayId = strings(&["x", "y", "z"]).into_iter().collect();
+                assert_eq!((&array).into_iter().count(), 3);
+
+                let reversed: Vec<Id> = array.clone().into_iter().rev().collect();
+                assert!(reversed[0].isEqualToString("z"));
+                assert!(reversed[2].isEqualToString("x"));
+
+                // Collected arrays are mutable, and clones must not share their contents.
+                let copy = array.clone();
+                NSMutableArray::addObject_(array.as_ptr(), *"w".to_cocoa());
+                assert_eq!((array.len(), copy.len()), (4, 3));
+                NSMutableArray::addObject_(copy.as_ptr(), *"v".to_cocoa());
+                assert_eq!((array.len(), copy.len()), (4, 4));
+                NSMutableArray::removeLastObject(array.as_ptr());
+
+                let mut iter = array.into_iter();
+                assert_eq!(iter.len(), 3);
+                iter.next();
+                assert_eq!(iter.len(), 2);
+            }
+        }
+
+        #[test]
+        fn test_from_vec() {
+            unsafe {
+                let objects: Vec<Id> = (0..100i64).map(|i| i.to_cocoa()).collect();
+                let copied = NSArray::arrayWithObjects(nil, &objects.iter().map(|o| **o).collect::<Vec<_>>());
+                let array = ArrayId::from(objects);
+                assert_eq!(array.len(), 100);
+                assert_eq!(i64::from_cocoa(array.objectAtIndex_(42)), Some(42));
+                let equal: BOOL = msg_send![**array, isEqualToArray:copied];
+                assert_eq!(equal, YES);
+
+                // Fast enumeration reads the vector directly, in one batch.
+                let items = array.iter_as::<i64>().map(Option::unwrap).collect::<Vec<_>>();
+                assert_eq!(items, (0..100).collect::<Vec<_>>());
+                let mutable: id = msg_send![**array, mutableCopy];
+                assert_eq!(NSArray::count(mutable), 100);
+
+                let empty = ArrayId::from(vec![]);
+                assert_eq!(empty.iter().count(), 0);
+                assert_eq!(empty.first(), None);
+            }
+        }
+
+        #[test]
+        fn test_from_vec_releases() {
+            unsafe {
+                let object = Id::from_retained(msg_send![class("NSObject"), new]);
+                let retain_count = || -> usize { msg_send![*object, retainCount] };
+                let array = ArrayId::from(vec![object.clone(), object.clone()]);
+                assert_eq!(retain_count(), 3);
+                drop(array);
+                assert_eq!(retain_count(), 1);
+            }
+        }
+
+        // Indexing past the end aborts the process, so the check runs this test again in a child.
+        #[test]
+        fn test_from_vec_out_of_range() {
+            if env::var_os("COCOA_RS_INDEX_OUT_OF_RANGE").is_some() {
+                unsafe {
+                    let array = ArrayId::from(vec![1i64.to_cocoa()]);
+                    array.objectAtIndex_(1);
+                }
+                return;
+            }
+            let output = Command::new(env::current_exe().unwrap())
+                .args(&["--exact", "foundation::nsarray::test_from_vec_out_of_range", "--nocapture"])
+                .env("COCOA_RS_INDEX_OUT_OF_RANGE", "1")
+                .output()
+                .unwrap();
+            assert!(!output.status.success());
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("index 1 beyond bounds of an array of 1"), "{}", stderr);
+        }
+    }
+
+    mod nsset {
+        use std::collections::{BTreeSet, HashSet};
+        use cocoa::foundation::{FromCocoa, NSCountedSet, NSFastEnumeration, NSMutableSet,
+                                NSOrderedSet, NSSet, NSString, ToCocoa};
+        use cocoa::base::{class, id, nil, Id};
+
+        unsafe fn set_of(values: &[&str]) -> Id {
+            values.iter().cloned().collect::<HashSet<_>>().to_cocoa()
+        }
+
+        unsafe fn strings(set: id) -> BTreeSet<String> {
+            BTreeSet::from_cocoa(set).unwrap()
+        }
+
+        #[test]
+        fn test_membership() {
+            unsafe {
+                let set = set_of(&["a", "b"]);
+                assert_eq!(set.member_count(), 2);
+                assert!(set.has_member(*"a".to_cocoa()));
+                assert!(!set.has_member(*"c".to_cocoa()));
+                assert!(set.member_(*"b".to_cocoa()).isEqualToString("b"));
+                assert!(set.has_member(set.anyObject()));
+                assert_eq!(NSSet::set(nil).anyObject(), nil);
+                assert_eq!(set.iter().count(), 2);
+                let members = set.members();
+                assert_eq!(members.len(), 2);
+                assert!(members.iter().all(|&member| set.has_member(member)));
+
+                let mutable: id = msg_send![class("NSMutableSet"), set];
+                mutable.add_member(*"a".to_cocoa());
+                mutable.add_member(*"a".to_cocoa());
+                mutable.add_member(*"b".to_cocoa());
+                assert_eq!(mutable.member_count(), 2);
+                mutable.remove_member(*"a".to_cocoa());
+                assert_eq!(strings(mutable), ["b"].iter().map(|s| s.to_string()).collect());
+                mutable.remove_all_members();
+                assert!(mutable.members().is_empty());
+            }
+        }
+
+        #[test]
+        fn test_algebra() {
+            unsafe {
+                let set: id = msg_send![class("NSMutableSet"), set];
+                set.unionSet_(*set_of(&["a", "b", "c"]));
+                set.minusSet_(*set_of(&["a"]));
+                assert_eq!(strings(set), ["b", "c"].iter().map(|s| s.to_string()).collect());
+                set.intersectSet_(*set_of(&["c", "d"]));
+                assert_eq!(strings(set), ["c"].iter().map(|s| s.to_string()).collect());
+                assert!(set.isSubsetOfSet_(*set_of(&["c", "d"])) != 0);
+
+                let union = set.setByAddingObjectsFromSet_(*set_of(&["e"]));
+                assert_eq!(union.member_count(), 2);
+                assert_eq!(HashSet::<i32>::from_cocoa(union), None);
+            }
+        }
+
+        #[test]
+        fn test_counted_set() {
+            unsafe {
+                let words = vec!["to", "be", "or", "not", "to", "be"].to_cocoa();
+                let counted = NSCountedSet::alloc(nil).initWithArray_(*words);
+                assert_eq!(counted.member_count(), 4);
+                assert_eq!(counted.countForObject_(*"be".to_cocoa()), 2);
+                assert_eq!(counted.countForObject_(*"or".to_cocoa()), 1);
+                assert_eq!(counted.countForObject_(*"question".to_cocoa()), 0);
+            }
+        }
+
+        #[test]
+        fn test_ordered_set() {
+            unsafe {
+                let array = vec!["c", "a", "c", "b"].to_cocoa();
+                let ordered = NSOrderedSet::orderedSetWithArray_(nil, *array);
+                assert_eq!(ordered.ordered_count(), 3);
+                assert!(ordered.ordered_get(0).unwrap().isEqualToString("c"));
+                assert!(ordered.ordered_get(2).unwrap().isEqualToString("b"));
+                assert_eq!(ordered.ordered_get(3), None);
+                assert_eq!(ordered.ordered_index_of(*"a".to_cocoa()), Some(1));
+                assert_eq!(ordered.ordered_index_of(*"d".to_cocoa()), None);
+                assert!(ordered.ordered_contains(*"b".to_cocoa()));
+                let members = ordered.ordered_members();
+                assert_eq!(members.len(), 3);
+                assert!(members[1].isEqualToString("a"));
+                assert_eq!(Vec::<String>::from_cocoa(ordered).unwrap(), vec!["c", "a", "b"]);
+                assert_eq!(strings(ordered.set()).len(), 3);
+            }
+        }
+    }
+
+    mod nsurl {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::{Path, PathBuf};
+        use cocoa::foundation::{FromCocoa, NSURL, NSURLConversionError};
+        use cocoa::base::{id, nil};
+
+        unsafe fn string(object: id) -> Option<String> {
+            String::from_cocoa(object)
+        }
+
+        #[test]
+        fn test_components() {
+            unsafe {
+                let url = NSURL::from_url_str(nil, "https://user@example.com:8080/a/b.txt?q=1#top").unwrap();
+                assert_eq!(string(url.scheme()).unwrap(), "https");
+                assert_eq!(string(url.host()).unwrap(), "example.com");
+                assert_eq!(string(url.user()).unwrap(), "user");
+                assert_eq!(string(url.path()).unwrap(), "/a/b.txt");
+                assert_eq!(string(url.query()).unwrap(), "q=1");
+                assert_eq!(string(url.fragment()).unwrap(), "top");
+                assert_eq!(string(url.pathExtension()).unwrap(), "txt");
+                assert_eq!(i32::from_cocoa(url.port()), Some(8080));
+                assert_eq!(url.to_url_string(), "https://user@example.com:8080/a/b.txt?q=1#top");
+                assert_eq!(url.to_path_buf(),
+                           Err(NSURLConversionError::NotAFileURL(url.to_url_string())));
+            }
+        }
+
+        #[test]
+        fn test_paths() {
+            unsafe {
+                let path = Path::new("/tmp/some dir/file.txt");
+                let url = NSURL::from_path(nil, path).unwrap();
+                assert_eq!(url.to_url_string(), "file:///tmp/some%20dir/file.txt");
+                assert_eq!(url.to_path_buf().unwrap(), path);
+
+                let dir = NSURL::from_path(nil, Path::new("/tmp/new/")).unwrap();
+                assert!(dir.hasDirectoryPath() != 0);
+
+                // Bytes that are not UTF-8 survive the round trip.
+                let odd = PathBuf::from(OsStr::from_bytes(b"/tmp/\xff\xfe"));
+                assert_eq!(NSURL::from_path(nil, &odd).unwrap().to_path_buf().unwrap(), odd);
+
+                let nul = PathBuf::from("/tmp/a\0b");
+                assert_eq!(NSURL::from_path(nil, &nul), Err(NSURLConversionError::InvalidPath(nul.clone())));
+            }
+        }
+
+        #[cfg(feature = "url")]
+        #[test]
+        fn test_url_crate() {
+            extern crate url;
+            unsafe {
+                let parsed = url::Url::parse("http://example.com/x?y=z").unwrap();
+                let url = NSURL::from_url(nil, &parsed).unwrap();
+                assert_eq!(url.to_url().unwrap(), parsed);
+            }
+        }
+    }
+
+    mod nserror {
+        use std::env;
+        use std::error::Error;
+        use std::fs;
+        use std::io;
+        use std::os::raw::c_void;
+        use std::path::PathBuf;
+        use std::process;
+        use objc::declare::ClassDecl;
+        use objc::runtime::{Class, Object, Sel};
+        use cocoa::foundation::{NSData, NSDataReadingOptions, NSDataWritingAtomic, NSError, NSString,
+                                NSFileReadNoSuchFileError, MISSING_ERROR_DOMAIN};
+        use cocoa::base::{class, id, nil, BOOL, NO};
+
+        // A path of its own for each test, so that concurrent runs do not share files.
+        fn temp_path(test: &str) -> PathBuf {
+            env::temp_dir().join(format!("cocoa-rs-nserror-{}-{}", test, process::id()))
+        }
+
+        #[test]
+        fn test_missing_file() {
+            unsafe {
+                let path = NSString::alloc(nil).init_str("/nonexistent/cocoa-rs/file");
+                let error = NSData::dataWithContentsOfFile_options_(nil, path, NSDataReadingOptions::empty())
+                    .unwrap_err();
+                assert_eq!(error.domain(), "NSCocoaErrorDomain");
+                assert_eq!(error.code(), NSFileReadNoSuchFileError);
+                assert_eq!(error.io_error_kind(), io::ErrorKind::NotFound);
+                assert!(!error.to_string().is_empty());
+
+                let underlying = error.underlying_error().unwrap();
+                assert_eq!(underlying.domain(), "NSPOSIXErrorDomain");
+                assert_eq!(underlying.code(), 2);
+                assert!(error.source().is_some());
+                assert_eq!(error.underlying_errors().len(), 1);
+
+                let io_error: io::Error = error.into();
+                assert_eq!(io_error.kind(), io::ErrorKind::NotFound);
+            }
+        }
+
+        #[test]
+        fn test_posix_domain() {
+            unsafe {
+                let error = NSError::new("NSPOSIXErrorDomain", 13, nil);
+                assert_eq!(error.io_error_kind(), io::ErrorKind::PermissionDenied);
+                assert!(error.underlying_error().is_none());
+
+                let other = NSError::new("com.example", 1, nil);
+                assert_eq!(other.io_error_kind(), io::ErrorKind::Other);
+            }
+        }
+
+        #[test]
+        fn test_write_and_read() {
+            unsafe {
+                let bytes = b"hello";
+                let data = NSData::dataWithBytes_length_(nil, bytes.as_ptr() as *const _, bytes.len() as u64);
+                let file = temp_path("write-and-read");
+                let path = NSString::alloc(nil).init_str(file.to_str().unwrap());
+                data.writeToFile_options_(path, NSDataWritingAtomic).unwrap();
+                let read: id = msg_send![class("NSData"), alloc];
+                let read = read.initWithContentsOfFile_options_(path, NSDataReadingOptions::empty()).unwrap();
+                assert_eq!(read.length(), 5);
+                fs::remove_file(file).unwrap();
+            }
+        }
+
+        #[test]
+        fn test_missing_error() {
+            // Fails without setting the error, as some methods do.
+            extern "C" fn write(_: &Object, _: Sel, _: id, _: u64, _: *mut c_void) -> BOOL {
+                NO
+            }
+
+            unsafe {
+                let mut decl = ClassDecl::new("CocoaRsSilentWriter", Class::get("NSObject").unwrap()).unwrap();
+                decl.add_method(sel!(writeToFile:options:error:),
+                                write as extern "C" fn(&Object, Sel, id, u64, *mut c_void) -> BOOL);
+                let writer: id = msg_send![decl.register(), new];
+
+                let path = NSString::alloc(nil).init_str(temp_path("missing-error").to_str().unwrap());
+                let error = writer.writeToFile_options_(path, NSDataWritingAtomic).unwrap_err();
+                assert_eq!(error.domain(), MISSING_ERROR_DOMAIN);
+                assert_eq!(error.domain(), "CocoaRsMissingErrorDomain");
+                assert_eq!(error.code(), 0);
+                assert!(error.underlying_error().is_none());
+                assert!(!error.to_string().is_empty());
+            }
+        }
+    }
+
+    mod nsfilemanager {
+        use std::env;
+        use std::fs;
+        use std::io;
+        use std::path::PathBuf;
+        use cocoa::foundation::{FileType, NSFileManager, NSDirectoryEnumerationOptions,
+                                NSDirectoryEnumerationSkipsSubdirectoryDescendants};
+        use cocoa::base::nil;
+
+        fn scratch(name: &str) -> PathBuf {
+            let dir = env::temp_dir().join(format!("cocoa-rs-{}", name));
+            let _ = fs::remove_dir_all(&dir);
+            dir
+        }
+
+        #[test]
+        fn test_operations() {
+            unsafe {
+                let manager = NSFileManager::defaultManager(nil);
+                let dir = scratch("file-manager");
+                manager.create_directory(&dir.join("a/b"), true).unwrap();
+                assert!(manager.create_directory(&dir.join("x/y"), false).is_err());
+                fs::write(dir.join("a/file.txt"), b"hello").unwrap();
+
+                let attributes = manager.attributes_of_item(&dir.join("a/file.txt")).unwrap();
+                assert_eq!(attributes.file_type, FileType::Regular);
+                assert_eq!(attributes.size, 5);
+                assert!(attributes.modification_date.is_some());
+                assert!(manager.attributes_of_item(&dir.join("a")).unwrap().is_dir());
+
+                let mut contents = manager.contents_of_directory(&dir.join("a")).unwrap();
+                contents.sort();
+                assert_eq!(contents, vec![dir.join("a/b"), dir.join("a/file.txt")]);
+
+                manager.copy_item(&dir.join("a/file.txt"), &dir.join("copy.txt")).unwrap();
+                manager.move_item(&dir.join("copy.txt"), &dir.join("a/b/moved.txt")).unwrap();
+                assert!(!manager.file_exists(&dir.join("copy.txt")));
+                assert_eq!(fs::read(dir.join("a/b/moved.txt")).unwrap(), b"hello");
+
+                let error = manager.copy_item(&dir.join("a/file.txt"), &dir.join("a/b/moved.txt")).unwrap_err();
+                assert_eq!(error.io_error_kind(), io::ErrorKind::AlreadyExists);
+
+                manager.remove_item(&dir).unwrap();
+                let error = manager.remove_item(&dir).unwrap_err();
+                assert_eq!(error.io_error_kind(), io::ErrorKind::NotFound);
+            }
+        }
+
+        #[test]
+        fn test_walk_directory() {
+            unsafe {
+                let manager = NSFileManager::defaultManager(nil);
+                let dir = scratch("walk");
+                fs::create_dir_all(dir.join("a/b")).unwrap();
+                fs::write(dir.join("a/b/c"), b"").unwrap();
+                // Resolve /tmp's symlink so that paths compare equal.
+                let dir = dir.canonicalize().unwrap();
+
+                let mut paths: Vec<_> = manager.walk_directory(&dir, NSDirectoryEnumerationOptions::empty()).unwrap()
+                    .map(Result::unwrap)
+                    .collect();
+                paths.sort();
+                assert_eq!(paths, vec![dir.join("a"), dir.join("a/b"), dir.join("a/b/c")]);
+
+                let shallow: Vec<_> = manager.walk_directory(&dir, NSDirectoryEnumerationSkipsSubdirectoryDescendants)
+                    .unwrap()
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+                assert_eq!(shallow, vec![dir.join("a")]);
+                fs::remove_dir_all(&dir).unwrap();
+            }
+        }
+
+        #[test]
+        fn test_standard_directories() {
+            unsafe {
+                let manager = NSFileManager::defaultManager(nil);
+                let support = manager.application_support_directory().unwrap();
+                assert!(support.ends_with("Library/Application Support"));
+                assert!(manager.caches_directory().unwrap().ends_with("Library/Caches"));
+            }
+        }
+    }
+
+    mod nsuserdefaults {
+        use std::cell::Cell;
+        use std::collections::HashMap;
+        use std::rc::Rc;
+        use cocoa::base::{nil, Id};
+        use cocoa::foundation::{FromCocoa, NSUserDefaults, ToCocoa};
+        use cocoa::plist::Value;
+
+        /// Tests run in parallel, so each gets a suite of its own.
+        unsafe fn suite(test: &str) -> (Id, Id) {
+            let name = format!("org.servo.cocoa-rs.tests.{}", test);
+            let defaults = NSUserDefaults::with_suite_name(nil, &name).unwrap();
+            let name = name.to_cocoa();
+            defaults.removePersistentDomainForName_(*name);
+            (defaults, name)
+        }
+
+        #[test]
+        fn test_typed_values() {
+            unsafe {
+                let (defaults, name) = suite("typed-values");
+                assert_eq!(defaults.get_value::<String>("name"), None);
+                defaults.set_value("name", "cocoa");
+                defaults.set_value("count", &3i64);
+                defaults.set_value("enabled", &true);
+                defaults.set_value("tags", &vec!["a", "b"]);
+                defaults.set_data("blob", &[1, 2, 3]);
+
+                assert_eq!(defaults.get_value::<String>("name").unwrap(), "cocoa");
+                assert_eq!(defaults.get_value::<i64>("count"), Some(3));
+                assert_eq!(defaults.get_value::<bool>("enabled"), Some(true));
+                assert_eq!(defaults.get_value::<Vec<String>>("tags").unwrap(), vec!["a", "b"]);
+                assert_eq!(defaults.get_data("blob").unwrap(), vec![1, 2, 3]);
+                assert_eq!(defaults.get_value::<i64>("name"), None);
+                assert_eq!(defaults.get_value::<Value>("count"), Some(Value::Integer(3)));
+                assert_eq!(Value::from_cocoa(*u64::MAX.to_cocoa()), Some(Value::Unsigned(u64::MAX)));
+                assert_eq!(Value::from_cocoa(*Value::Unsigned(u64::MAX).to_cocoa()), Some(Value::Unsigned(u64::MAX)));
+
+                defaults.remove_value("name");
+                assert_eq!(defaults.get_value::<String>("name"), None);
+                defaults.removePersistentDomainForName_(*name);
+            }
+        }
+
+        #[test]
+        fn test_register_defaults() {
+            unsafe {
+                let (defaults, name) = suite("register-defaults");
+                let mut registered = HashMap::new();
+                registered.insert("volume", 11i64);
+                defaults.register_defaults(&registered);
+                assert_eq!(defaults.get_value::<i64>("volume"), Some(11));
+                defaults.set_value("volume", &2i64);
+                assert_eq!(defaults.get_value::<i64>("volume"), Some(2));
+                defaults.remove_value("volume");
+                assert_eq!(defaults.get_value::<i64>("volume"), Some(11));
+                defaults.removePersistentDomainForName_(*name);
+            }
+        }
+
+        #[test]
+        fn test_observe_changes() {
+            unsafe {
+                let (defaults, name) = suite("observe-changes");
+                let changes = Rc::new(Cell::new(0));
+                let observed = changes.clone();
+                let observer = defaults.observe_changes(move || observed.set(observed.get() + 1));
+                defaults.set_value("observed", &1i64);
+                assert!(changes.get() > 0);
+
+                drop(observer);
+                let seen = changes.get();
+                defaults.set_value("observed", &2i64);
+                assert_eq!(changes.get(), seen);
+                defaults.removePersistentDomainForName_(*name);
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn test_structs() {
+            #[derive(Debug, PartialEq, Serialize, Deserialize)]
+            struct Window {
+                title: String,
+                frame: (f64, f64, f64, f64),
+                hidden: bool,
+                tabs: Vec<String>,
+                zoom: Option<u32>,
+            }
+
+            unsafe {
+                let (defaults, name) = suite("structs");
+                let window = Window {
+                    title: "Main".to_owned(),
+                    frame: (0.0, 0.0, 640.5, 480.0),
+                    hidden: false,
+                    tabs: vec!["one".to_owned()],
+                    zoom: None,
+                };
+                assert_eq!(defaults.get_struct::<Window>("window"), Ok(None));
+                defaults.set_struct("window", &window).unwrap();
+                assert_eq!(defaults.get_struct::<Window>("window"), Ok(Some(window)));
+                assert!(defaults.get_value::<HashMap<String, Value>>("window").unwrap().contains_key("title"));
+                defaults.removePersistentDomainForName_(*name);
+            }
+        }
+    }
+
+    mod nsbundle {
+        use std::env;
+        use std::fs;
+        use cocoa::base::nil;
+        use cocoa::foundation::NSBundle;
+
+        const INFO_PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleIdentifier</key>
+    <string>org.servo.cocoa-rs.test-bundle</string>
+    <key>CFBundlePackageType</key>
+    <string>BNDL</string>
+    <key>CFBundleShortVersionString</key>
+    <string>1.2.3</string>
+    <key>CFBundleVersion</key>
+    <string>45</string>
+    <key>CFBundleDevelopmentRegion</key>
+    <string>en</string>
+    <key>TestCount</key>
+    <integer>7</integer>
+</dict>
+</plist>
+"#;
+
+        #[test]
+        fn test_bundle() {
+            unsafe {
+                let root = env::temp_dir().canonicalize().unwrap().join("cocoa-rs-test.bundle");
+                let _ = fs::remove_dir_all(&root);
+                let resources = root.join("Contents/Resources");
+                fs::create_dir_all(resources.join("en.lproj")).unwrap();
+                fs::write(root.join("Contents/Info.plist"), INFO_PLIST).unwrap();
+                fs::write(resources.join("data.txt"), b"data").unwrap();
+                fs::write(resources.join("en.lproj/Localizable.strings"), "\"greeting\" = \"Hello\";\n").unwrap();
+
+                let bundle = NSBundle::with_path(nil, &root).unwrap();
+                assert_eq!(bundle.identifier().unwrap(), "org.servo.cocoa-rs.test-bundle");
+                assert_eq!(bundle.bundle_path().unwrap(), root);
+                assert!(!bundle.packaged_as_app());
+                assert_eq!(bundle.short_version().unwrap(), "1.2.3");
+                assert_eq!(bundle.build_version().unwrap(), "45");
+                assert_eq!(bundle.info_value::<i64>("TestCount"), Some(7));
+                assert_eq!(bundle.info_value::<String>("Missing"), None);
+
+                assert_eq!(bundle.resource_path("data", Some("txt")).unwrap(), resources.join("data.txt"));
+                assert_eq!(bundle.resource_path("data.txt", None).unwrap(), resources.join("data.txt"));
+                assert!(bundle.resource_path("missing", Some("txt")).is_none());
+                assert!(bundle.resource_url("data", Some("txt")).is_some());
+
+                assert_eq!(bundle.localized_string("greeting", None).unwrap(), "Hello");
+                assert_eq!(bundle.localized_string("farewell", None), None);
+                assert_eq!(bundle.localized_string("greeting", Some("Missing")), None);
+
+                assert!(NSBundle::with_path(nil, &root.join("missing")).is_none());
+                fs::remove_dir_all(&root).unwrap();
+            }
+        }
+
+        #[test]
+        fn test_main_bundle() {
+            unsafe {
+                // Test executables are not inside an application bundle.
+                let main = NSBundle::mainBundle(nil);
+                assert!(!main.packaged_as_app());
+                assert!(main.bundle_path().unwrap().is_dir());
+                assert!(NSBundle::with_identifier(nil, "com.apple.Foundation").is_some());
+            }
+        }
+    }
+
+    mod nsprocessinfo {
+        use std::env;
+        use std::process;
+        use cocoa::base::{nil, NO, YES};
+        use cocoa::foundation::{NSActivityUserInitiated, NSOperatingSystemVersion, NSProcessInfo};
+
+        #[test]
+        fn test_process() {
+            unsafe {
+                let info = NSProcessInfo::processInfo(nil);
+                assert_eq!(info.args(), env::args().collect::<Vec<_>>());
+                assert_eq!(info.env_vars().get("PATH"), env::var("PATH").ok().as_ref());
+                assert_eq!(info.processIdentifier() as u32, process::id());
+                assert!(info.physicalMemory() > 0);
+                assert!(info.activeProcessorCount() >= 1);
+                assert!(info.processorCount() >= info.activeProcessorCount());
+                assert!(info.systemUptime() > 0.0);
+                assert!(info.thermalState().is_some());
+            }
+        }
+
+        #[test]
+        fn test_operating_system_version() {
+            unsafe {
+                let info = NSProcessInfo::processInfo(nil);
+                let version = info.operatingSystemVersion();
+                assert!(version >= NSOperatingSystemVersion::new(10, 10, 0));
+                assert_eq!(info.isOperatingSystemAtLeastVersion_(version), YES);
+                let next = NSOperatingSystemVersion::new(version.majorVersion + 1, 0, 0);
+                assert!(next > version);
+                assert_eq!(info.isOperatingSystemAtLeastVersion_(next), NO);
+                assert_eq!(NSOperatingSystemVersion::new(10, 15, 7).to_string(), "10.15.7");
+
+                let monterey = info.isOperatingSystemAtLeastVersion_(NSOperatingSystemVersion::new(12, 0, 0));
+                assert_eq!(info.low_power_mode().is_some(), monterey == YES);
+            }
+        }
+
+        #[test]
+        fn test_activity() {
+            unsafe {
+                let info = NSProcessInfo::processInfo(nil);
+                let activity = info.begin_activity(NSActivityUserInitiated, "Running tests");
+                assert!(!activity.as_id().is_null());
+                drop(activity);
+            }
+        }
+    }
+
+    mod nsrunloop {
+        use std::cell::{Cell, RefCell};
+        use std::rc::Rc;
+        use std::sync::{Arc, Mutex};
+        use std::thread::{self, ThreadId};
+        use std::time::Duration;
+        use cocoa::base::{id, nil, BOOL, YES};
+        use cocoa::foundation::{kCFRunLoopAfterWaiting, kCFRunLoopBeforeWaiting, kCFRunLoopEntry, kCFRunLoopExit,
+                                NSDate, NSDefaultRunLoopMode, NSRunLoop, NSRunLoopCommonModes,
+                                RunLoopObserver, RunLoopSource};
+
+        fn before(secs: f64) -> id {
+            unsafe { NSDate::dateWithTimeIntervalSinceNow_(nil, secs) }
+        }
+
+        #[test]
+        fn test_run_loops() {
+            unsafe {
+                let current: id = NSRunLoop::currentRunLoop();
+                let main: id = NSRunLoop::mainRunLoop();
+                assert!(!current.is_null() && !main.is_null());
+                assert!(!current.getCFRunLoop().is_null());
+                assert!(current.currentMode().is_null());
+
+                // With nothing to wait on the loop returns straight away.
+                current.runUntilDate_(before(10.0));
+            }
+        }
+
+        #[test]
+        fn test_source() {
+            let performed = Rc::new(Cell::new(0));
+            let source = {
+                let performed = performed.clone();
+                RunLoopSource::new(0, move || {
+                    performed.set(performed.get() + 1);
+                    unsafe {
+                        let mode = <id as NSRunLoop>::currentRunLoop().currentMode();
+                        let equal: BOOL = msg_send![mode, isEqualToString:NSDefaultRunLoopMode];
+                        assert_eq!(equal, YES);
+                    }
+                })
+            };
+            unsafe {
+                let run_loop = NSRunLoop::currentRunLoop();
+                source.add(run_loop, NSRunLoopCommonModes);
+                assert_eq!(run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, before(0.01)), YES);
+                assert_eq!(performed.get(), 0);
+
+                source.signal();
+                assert_eq!(run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, before(1.0)), YES);
+                assert_eq!(performed.get(), 1);
+
+                // Signalled from another thread, the run loop wakes up well before its limit.
+                let signaller = source.signaller(run_loop);
+                let thread = thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    signaller.signal();
+                });
+                run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, before(10.0));
+                assert_eq!(performed.get(), 2);
+                thread.join().unwrap();
+
+                source.remove(run_loop, NSRunLoopCommonModes);
+            }
+        }
+
+        // Records the thread it is dropped on.
+        struct DropsOn(Arc<Mutex<Option<ThreadId>>>);
+
+        impl Drop for DropsOn {
+            fn drop(&mut self) {
+                *self.0.lock().unwrap() = Some(thread::current().id());
+            }
+        }
+
+        #[test]
+        fn test_late_signaller() {
+            let dropped_on = Arc::new(Mutex::new(None));
+            let performed = Rc::new(Cell::new(false));
+            let source = {
+                let guard = DropsOn(dropped_on.clone());
+                let performed = performed.clone();
+                RunLoopSource::new(0, move || {
+                    let _ = &guard;
+                    performed.set(true);
+                })
+            };
+            let woken = Rc::new(Cell::new(false));
+            let waker = {
+                let woken = woken.clone();
+                RunLoopSource::new(1, move || woken.set(true))
+            };
+            unsafe {
+                let run_loop = NSRunLoop::currentRunLoop();
+                source.add(run_loop, NSDefaultRunLoopMode);
+                waker.add(run_loop, NSDefaultRunLoopMode);
+                let late = source.signaller(run_loop);
+                let wake = waker.signaller(run_loop);
+                drop(source);
+
+                // The run loop is waiting when the signaller outliving its source is signalled and
+                // dropped, and the source's closure is not dropped on the signalling thread.
+                let thread = thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    late.signal();
+                    drop(late);
+                    wake.signal();
+                });
+                run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, before(10.0));
+                thread.join().unwrap();
+                assert!(woken.get());
+                assert!(!performed.get());
+                assert_eq!(*dropped_on.lock().unwrap(), Some(thread::current().id()));
+
+                waker.remove(run_loop, NSDefaultRunLoopMode);
+            }
+        }
+
+        #[test]
+        fn test_observer() {
+            let activities = Rc::new(RefCell::new(vec![]));
+            let observer = {
+                let activities = activities.clone();
+                RunLoopObserver::new(kCFRunLoopEntry | kCFRunLoopBeforeWaiting | kCFRunLoopAfterWaiting |
+                                     kCFRunLoopExit, true, 0, move |activity| activities.borrow_mut().push(activity))
+            };
+            let source = RunLoopSource::new(0, || {});
+            unsafe {
+                let run_loop = NSRunLoop::currentRunLoop();
+                source.add(run_loop, NSDefaultRunLoopMode);
+                observer.add(run_loop, NSDefaultRunLoopMode);
+                run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, before(0.01));
+                assert_eq!(*activities.borrow(),
+                           [kCFRunLoopEntry, kCFRunLoopBeforeWaiting, kCFRunLoopAfterWaiting, kCFRunLoopExit]);
+
+                observer.remove(run_loop, NSDefaultRunLoopMode);
+                run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, before(0.01));
+                assert_eq!(activities.borrow().len(), 4);
+            }
+        }
+    }
+
+    mod nsthread {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::mpsc;
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+        use cocoa::base::{id, nil, NO, YES};
+        use cocoa::foundation::{NSAutoreleasePool, NSDate, NSQualityOfService, NSRunLoop, NSString, NSThread};
+
+        #[test]
+        fn test_current_thread() {
+            unsafe {
+                // The test harness runs tests off the main thread.
+                assert_eq!(NSThread::isMainThread(nil), NO);
+                let current = NSThread::currentThread(nil);
+                assert!(current != NSThread::mainThread(nil));
+                assert!(current.stackSize() > 0);
+            }
+        }
+
+        #[test]
+        fn test_detach() {
+            let (sender, receiver) = mpsc::channel();
+            unsafe {
+                let thread = NSThread::with_closure(nil, move || {
+                    let current = NSThread::currentThread(nil);
+                    let name = current.name();
+                    let name = NSString::UTF8String(name);
+                    let name = std::ffi::CStr::from_ptr(name).to_str().unwrap().to_owned();
+                    // Autoreleased objects go into the thread's own pool.
+                    let _ = NSString::alloc(nil).init_str("autoreleased").autorelease();
+                    sender.send((name, current.stackSize(), current.qualityOfService())).unwrap();
+                });
+                thread.setName_(NSString::alloc(nil).init_str("cocoa-rs worker").autorelease());
+                thread.setStackSize_(1 << 20);
+                thread.setQualityOfService_(NSQualityOfService::NSQualityOfServiceUtility);
+                assert_eq!(thread.isExecuting(), NO);
+                thread.start();
+            }
+            let (name, stack_size, quality) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(name, "cocoa-rs worker");
+            assert_eq!(stack_size, 1 << 20);
+            assert_eq!(quality, NSQualityOfService::NSQualityOfServiceUtility);
+
+            let (sender, receiver) = mpsc::channel();
+            unsafe { NSThread::detach(nil, move || sender.send(NSThread::isMainThread(nil)).unwrap()) };
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(NO));
+        }
+
+        struct SendId(id);
+
+        unsafe impl Send for SendId {}
+
+        #[test]
+        fn test_perform() {
+            let done = Arc::new(AtomicBool::new(false));
+            let (sender, receiver) = mpsc::channel();
+            {
+                let done = done.clone();
+                unsafe {
+                    NSThread::detach(nil, move || {
+                        sender.send(SendId(NSThread::currentThread(nil))).unwrap();
+                        let run_loop: id = NSRunLoop::currentRunLoop();
+                        while !done.load(Ordering::SeqCst) {
+                            run_loop.runUntilDate_(NSDate::dateWithTimeIntervalSinceNow_(nil, 0.01));
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    });
+                }
+            }
+            let target = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+            let (sender, receiver) = mpsc::channel();
+            unsafe {
+                target.0.perform(move || {
+                    sender.send(NSThread::currentThread(nil) as usize).unwrap();
+                }, true);
+            }
+            // Waiting means the closure has already run.
+            assert_eq!(receiver.try_recv(), Ok(target.0 as usize));
+
+            // A panic resumes in the waiting caller, and the target thread carries on.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                target.0.perform(|| panic!("performed"), true)
+            }));
+            assert_eq!(result.unwrap_err().downcast_ref::<&str>(), Some(&"performed"));
+            unsafe { target.0.perform(|| panic!("performed without waiting"), false) };
+
+            let (sender, receiver) = mpsc::channel();
+            unsafe { target.0.perform(move || sender.send(YES).unwrap(), false) };
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(YES));
+            done.store(true, Ordering::SeqCst);
+        }
+    }
+
+    mod nstimer {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use std::time::Duration;
+        use cocoa::base::{id, nil};
+        use cocoa::foundation::{NSDate, NSDefaultRunLoopMode, NSRunLoop, NSTimer, Timer};
+
+        fn run_for(secs: f64) {
+            unsafe {
+                let date = NSDate::dateWithTimeIntervalSinceNow_(nil, secs);
+                let run_loop: id = NSRunLoop::currentRunLoop();
+                run_loop.runUntilDate_(date);
+            }
+        }
+
+        #[test]
+        fn test_once() {
+            let count = Rc::new(Cell::new(0));
+            let timer = {
+                let count = count.clone();
+                Timer::once(Duration::from_millis(10), move || count.set(count.get() + 1))
+            };
+            timer.schedule_on_current_run_loop();
+            assert!(timer.is_valid());
+            run_for(0.1);
+            assert_eq!(count.get(), 1);
+            assert!(!timer.is_valid());
+        }
+
+        #[test]
+        fn test_repeating() {
+            let count = Rc::new(Cell::new(0));
+            let timer = {
+                let count = count.clone();
+                Timer::new(Duration::from_millis(10), true, move |timer: id| {
+                    count.set(count.get() + 1);
+                    if count.get() == 3 {
+                        unsafe { timer.invalidate() }
+                    }
+                }).with_tolerance(Duration::from_millis(1))
+            };
+            unsafe {
+                assert_eq!(timer.as_id().tolerance(), 0.001);
+                timer.schedule(NSRunLoop::currentRunLoop(), &[NSDefaultRunLoopMode]);
+            }
+            run_for(0.2);
+            assert_eq!(count.get(), 3);
+            assert!(!timer.is_valid());
+        }
+
+        #[test]
+        fn test_fire_and_drop() {
+            let count = Rc::new(Cell::new(0));
+            let timer = {
+                let count = count.clone();
+                Timer::repeating(Duration::from_millis(10), move || count.set(count.get() + 1))
+            };
+            timer.fire();
+            assert_eq!(count.get(), 1);
+            timer.schedule_on_current_run_loop();
+            drop(timer);
+            run_for(0.05);
+            assert_eq!(count.get(), 1);
+        }
+    }
+
+    mod runloopexecutor {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use block::Block;
+        use cocoa::base::{id, nil, Id, YES};
+        use support::and_then;
+        use cocoa::foundation::{completion_handler, completion_handler_with_error, key_value_change,
+                                next_notification, sleep, NSDate, NSDefaultRunLoopMode, NSError,
+                                NSKeyValueChangeNewKey, NSKeyValueObservingOptionNew, NSNotificationCenter,
+                                NSRunLoop, NSString, RunLoopExecutor, ToCocoa};
+
+        #[test]
+        fn test_sleep() {
+            let executor = RunLoopExecutor::current();
+            let woken = Rc::new(Cell::new(0));
+            for &millis in &[30, 10, 20] {
+                let woken = woken.clone();
+                executor.spawn(and_then(sleep(Duration::from_millis(millis)), move |()| {
+                    woken.set(woken.get() * 10 + millis / 10);
+                    sleep(Duration::from_millis(0))
+                }));
+            }
+            let start = Instant::now();
+            executor.block_on(sleep(Duration::from_millis(50)));
+            assert!(start.elapsed() >= Duration::from_millis(50));
+            assert_eq!(woken.get(), 123);
+            assert!(executor.is_empty());
+        }
+
+        #[test]
+        fn test_nested_run_loop() {
+            let executor = RunLoopExecutor::current();
+            let slept = Rc::new(Cell::new(false));
+            {
+                let slept = slept.clone();
+                executor.spawn(and_then(sleep(Duration::from_millis(20)), move |()| {
+                    slept.set(true);
+                    sleep(Duration::from_millis(0))
+                }));
+            }
+            let inner = slept.clone();
+            // Woken by its timer, this task runs from the executor's run loop source, and the
+            // other task wakes up and runs inside its nested run loop.
+            executor.block_on(and_then(sleep(Duration::from_millis(5)), move |()| unsafe {
+                let run_loop: id = NSRunLoop::currentRunLoop();
+                let limit = NSDate::dateWithTimeIntervalSinceNow_(nil, 5.0);
+                while !inner.get() && run_loop.runMode_beforeDate_(NSDefaultRunLoopMode, limit) == YES {}
+                assert!(inner.get());
+                sleep(Duration::from_millis(0))
+            }));
+            assert!(slept.get());
+            assert!(executor.is_empty());
+        }
+
+        #[test]
+        fn test_notification() {
+            let executor = RunLoopExecutor::current();
+            unsafe {
+                let center = NSNotificationCenter::defaultCenter(nil);
+                let name = "CocoaRsExecutorTestNotification".to_cocoa();
+                let notified = executor.spawn(next_notification(center, *name, nil));
+                executor.spawn(and_then(sleep(Duration::from_millis(10)), move |()| {
+                    center.postNotificationName_object_(*name, nil);
+                    sleep(Duration::from_millis(0))
+                }));
+                let notification = executor.block_on(notified);
+                let name: id = msg_send![*notification, name];
+                assert!(name.isEqualToString("CocoaRsExecutorTestNotification"));
+            }
+        }
+
+        #[test]
+        fn test_key_value_change() {
+            let executor = RunLoopExecutor::current();
+            unsafe {
+                let progress: id = msg_send![class!(NSProgress), progressWithTotalUnitCount:10i64];
+                let change = executor.spawn(key_value_change(progress, "completedUnitCount",
+                                                             NSKeyValueObservingOptionNew));
+                let progress = Id::from_unretained(progress);
+                executor.spawn(and_then(sleep(Duration::from_millis(10)), move |()| {
+                    let () = msg_send![*progress, setCompletedUnitCount:4i64];
+                    sleep(Duration::from_millis(0))
+                }));
+                let change = executor.block_on(change);
+                let new: id = msg_send![*change, objectForKey:NSKeyValueChangeNewKey];
+                let count: i64 = msg_send![new, longLongValue];
+                assert_eq!(count, 4);
+            }
+        }
+
+        struct SendBlock(*mut Block<(id, id), ()>);
+
+        unsafe impl Send for SendBlock {}
+
+        #[test]
+        fn test_completion_handlers() {
+            let executor = RunLoopExecutor::current();
+            let (block, completion) = completion_handler_with_error();
+            let pointer = SendBlock(&*block as *const _ as *mut _);
+            let thread = thread::spawn(move || unsafe {
+                let pointer = pointer;
+                thread::sleep(Duration::from_millis(10));
+                (*pointer.0).call(("done".to_cocoa().as_ptr(), nil));
+            });
+            let result = executor.block_on(completion).unwrap();
+            thread.join().unwrap();
+            assert!(unsafe { result.unwrap().isEqualToString("done") });
+
+            let (block, completion) = completion_handler_with_error();
+            unsafe {
+                let error = NSError::new("CocoaRsTests", 7, nil);
+                block.call((nil, error.as_id()));
+            }
+            assert_eq!(executor.block_on(completion).unwrap().unwrap_err().code(), 7);
+
+            // Released without being called.
+            let (block, completion) = completion_handler();
+            drop(block);
+            assert!(executor.block_on(completion).is_none());
+        }
+    }
+
+    mod nsjsonserialization {
+        use cocoa::base::{id, nil, Id, NO, YES};
+        use cocoa::foundation::{NSArray, NSData, NSDate, NSDictionary, NSJSONReadingFragmentsAllowed,
+                                NSJSONReadingOptions, NSJSONSerialization, NSJSONWritingFragmentsAllowed,
+                                NSJSONWritingOptions, NSJSONWritingSortedKeys, NSNull, NSNumber,
+                                NSPropertyListReadCorruptError, NSPropertyListWriteInvalidError, FromCocoa,
+                                ToCocoa};
+
+        unsafe fn data(json: &str) -> Id {
+            Id::from_unretained(NSData::dataWithBytes_length_(nil, json.as_ptr() as *const _, json.len() as u64))
+        }
+
+        #[test]
+        fn test_round_trip() {
+            unsafe {
+                let object = NSJSONSerialization::JSONObjectWithData_options_(
+                    nil, *data(r#"{"b": [1, 2.5, true, null], "a": "x"}"#), NSJSONReadingOptions::empty()).unwrap();
+                assert_eq!(NSJSONSerialization::isValidJSONObject_(nil, *object), YES);
+                let list: id = NSDictionary::objectForKey_(*object, *"b".to_cocoa());
+                assert_eq!(NSArray::count(list), 4);
+                assert_eq!(NSArray::objectAtIndex_(list, 3), NSNull::null(nil));
+
+                let json = NSJSONSerialization::dataWithJSONObject_options_(nil, *object, NSJSONWritingSortedKeys)
+                    .unwrap();
+                assert_eq!(json.to_vec(), br#"{"a":"x","b":[1,2.5,true,null]}"#.to_vec());
+            }
+        }
+
+        #[test]
+        fn test_errors() {
+            unsafe {
+                let error = NSJSONSerialization::JSONObjectWithData_options_(nil, *data("{"),
+                                                                             NSJSONReadingOptions::empty())
+                    .unwrap_err();
+                assert_eq!(error.code(), NSPropertyListReadCorruptError);
+                assert!(NSJSONSerialization::JSONObjectWithData_options_(nil, *data("3"),
+                                                                         NSJSONReadingOptions::empty()).is_err());
+                let three = NSJSONSerialization::JSONObjectWithData_options_(nil, *data("3"),
+                                                                             NSJSONReadingFragmentsAllowed)
+                    .unwrap();
+                assert_eq!(i64::from_cocoa(*three), Some(3));
+
+                // Invalid objects are reported rather than raising.
+                let date = NSArray::arrayWithObject(nil, NSDate::date(nil));
+                assert_eq!(NSJSONSerialization::isValidJSONObject_(nil, date), NO);
+                let error = NSJSONSerialization::dataWithJSONObject_options_(nil, date, NSJSONWritingOptions::empty())
+                    .unwrap_err();
+                assert_eq!(error.code(), NSPropertyListWriteInvalidError);
+                let nan = NSNumber::numberWithDouble_(nil, f64::NAN);
+                assert!(NSJSONSerialization::dataWithJSONObject_options_(nil, nan, NSJSONWritingFragmentsAllowed)
+                    .is_err());
+                let json = NSJSONSerialization::dataWithJSONObject_options_(nil, *"hi".to_cocoa(),
+                                                                            NSJSONWritingFragmentsAllowed)
+                    .unwrap();
+                assert_eq!(json.to_vec(), b"\"hi\"".to_vec());
+            }
+        }
+
+        #[cfg(feature = "serde_json")]
+        #[test]
+        fn test_serde_json_values() {
+            use serde_json::Value;
+
+            unsafe {
+                let value = json!({
+                    "int": 1,
+                    "float": 1.5,
+                    "negative": -7,
+                    "big": u64::MAX,
+                    "bool": false,
+                    "null": null,
+                    "nested": [[], {}, "text", 0.5],
+                });
+                let object = value.to_cocoa();
+                assert_eq!(Value::from_cocoa(*object), Some(value.clone()));
+
+                // Converted directly, whole floats stay floats. JSON text would write 1.0 as 1.
+                let whole = Value::from_cocoa(*json!(1.0).to_cocoa()).unwrap();
+                assert!(whole.is_f64() && whole == json!(1.0));
+                assert!(Value::from_cocoa(*json!(1).to_cocoa()).unwrap().is_i64());
+
+                // Through JSON text and back, integers, floats and booleans keep their types.
+                let json = NSJSONSerialization::dataWithJSONObject_options_(nil, *object,
+                                                                            NSJSONWritingOptions::empty())
+                    .unwrap();
+                let parsed: Value = serde_json::from_slice(&json.to_vec()).unwrap();
+                assert_eq!(parsed, value);
+                let object = NSJSONSerialization::JSONObjectWithData_options_(nil, *json,
+                                                                              NSJSONReadingOptions::empty())
+                    .unwrap();
+                let back = Value::from_cocoa(*object).unwrap();
+                assert_eq!(back, value);
+                assert!(back["int"].is_i64() && back["float"].is_f64() && back["bool"].is_boolean());
+
+                assert_eq!(Value::from_cocoa(NSDate::date(nil)), None);
+                assert_eq!(Value::from_cocoa(NSNumber::numberWithDouble_(nil, f64::INFINITY)), None);
+            }
+        }
+    }
+
+    mod nsattributedstring {
+        use cocoa::appkit::{ForegroundColorAttribute, LinkAttribute, UnderlineAttribute};
+        use cocoa::base::{nil, Id};
+        use cocoa::foundation::{NSAttributedString, NSMutableAttributedString, FromCocoa, ToCocoa};
+        use cocoa::styled_text::{Attributes, Color, Font, ParagraphStyle, StyledText, TextAlignment, Underline};
+
+        // "é" is two bytes and "🎨" four, so byte and UTF-16 offsets differ.
+        const TEXT: &str = "héllo 🎨 world";
+
+        #[test]
+        fn test_typed_attributes() {
+            unsafe {
+                let string = NSMutableAttributedString::alloc(nil);
+                let string = Id::from_retained(NSAttributedString::initWithString_(string, *TEXT.to_cocoa()));
+                assert_eq!(string.text(), TEXT);
+
+                let red = Color::rgb(1.0, 0.0, 0.0);
+                string.set_attribute::<ForegroundColorAttribute>(&red, 0..6);
+                string.set_attribute::<UnderlineAttribute>(&Underline::Double, 7..11);
+                string.set_attribute::<LinkAttribute>(&"https://example.com/".to_owned(), 12..17);
+
+                assert_eq!(string.attribute::<ForegroundColorAttribute>(1), Some(red));
+                assert_eq!(string.attribute::<ForegroundColorAttribute>(7), None);
+                assert_eq!(string.attribute::<ForegroundColorAttribute>(TEXT.len()), None);
+                assert_eq!(string.attribute::<ForegroundColorAttribute>(TEXT.len() + 10), None);
+                // Byte 2 is inside "é".
+                assert_eq!(string.attribute::<ForegroundColorAttribute>(2), None);
+                assert_eq!(string.enumerate_attribute::<UnderlineAttribute>(), vec![(7..11, Underline::Double)]);
+                assert_eq!(string.enumerate_attribute::<LinkAttribute>(),
+                           vec![(12..17, "https://example.com/".to_owned())]);
+                let runs: Vec<_> = string.attributes_runs().into_iter().map(|(range, _)| range).collect();
+                assert_eq!(runs, vec![0..6, 6..7, 7..11, 11..12, 12..17]);
+
+                string.remove_attribute::<UnderlineAttribute>(0..TEXT.len());
+                assert!(string.enumerate_attribute::<UnderlineAttribute>().is_empty());
+                string.append_str("!", nil);
+                assert_eq!(string.text(), format!("{}!", TEXT));
+            }
+        }
+
+        #[test]
+        fn test_styled_text() {
+            let mut text = StyledText::new();
+            text.push("Title", Attributes {
+                font: Some(Font { name: "Helvetica-Bold".to_owned(), size: 18.0 }),
+                paragraph_style: Some(ParagraphStyle {
+                    alignment: TextAlignment::Center,
+                    paragraph_spacing: 6.0,
+                    ..ParagraphStyle::default()
+                }),
+                ..Attributes::default()
+            });
+            text.push(" and ", Attributes::default());
+            text.push("🎨 link", Attributes {
+                foreground_color: Some(Color { red: 0.25, green: 0.5, blue: 0.75, alpha: 0.5 }),
+                background_color: Some(Color::rgb(1.0, 1.0, 0.0)),
+                underline: Some(Underline::Single),
+                link: Some("https://example.com/paint".to_owned()),
+                ..Attributes::default()
+            });
+            unsafe {
+                let string = text.to_cocoa();
+                assert_eq!(string.text(), "Title and 🎨 link");
+                assert_eq!(StyledText::from_cocoa(*string), Some(text));
+                assert_eq!(StyledText::from_cocoa(*"plain".to_cocoa()), None);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    mod object_graph {
+        use std::time::{Duration, UNIX_EPOCH};
+        use cocoa::base::{id, nil, Id};
+        use cocoa::foundation::{from_object, to_object, NSArray, NSDate, NSDictionary, NSString, NSURL,
+                                FromCocoa, ToCocoa};
+        use cocoa::plist::serde::Date;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Layer {
+            Hidden,
+            Image { name: String, opacity: f32 },
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Canvas {
+            title: String,
+            size: (u32, u32),
+            big: u64,
+            created: Date,
+            thumbnail: Option<Vec<u8>>,
+            layers: Vec<Layer>,
+        }
+
+        fn canvas() -> Canvas {
+            Canvas {
+                title: "sketch".to_owned(),
+                size: (640, 480),
+                big: u64::MAX,
+                created: Date(UNIX_EPOCH + Duration::from_secs(978307200)),
+                thumbnail: None,
+                layers: vec![Layer::Hidden, Layer::Image { name: "sky".to_owned(), opacity: 0.5 }],
+            }
+        }
+
+        #[test]
+        fn test_round_trip() {
+            unsafe {
+                let object: Id = to_object(&canvas()).unwrap();
+                let title: id = NSDictionary::objectForKey_(*object, *"title".to_cocoa());
+                assert!(title.isEqualToString("sketch"));
+                let created: id = NSDictionary::objectForKey_(*object, *"created".to_cocoa());
+                assert_eq!(created.timeIntervalSinceReferenceDate(), 0.0);
+                assert!(NSDictionary::objectForKey_(*object, *"thumbnail".to_cocoa()).is_null());
+                let layers: id = NSDictionary::objectForKey_(*object, *"layers".to_cocoa());
+                assert_eq!(String::from_cocoa(NSArray::objectAtIndex_(layers, 0)), Some("Hidden".to_owned()));
+                assert_eq!(from_object::<Canvas>(*object).unwrap(), canvas());
+
+                let mut with_thumbnail = canvas();
+                with_thumbnail.thumbnail = Some(vec![1, 2, 3]);
+                let object: Id = to_object(&with_thumbnail).unwrap();
+                assert_eq!(from_object::<Canvas>(*object).unwrap(), with_thumbnail);
+            }
+        }
+
+        #[test]
+        fn test_errors() {
+            unsafe {
+                let object: Id = to_object(&canvas()).unwrap();
+                let layers: id = NSDictionary::objectForKey_(*object, *"layers".to_cocoa());
+                let url = NSURL::URLWithString_(nil, NSString::alloc(nil).init_str("https://example.com"));
+                let image: id = NSArray::objectAtIndex_(layers, 1);
+                let image: id = NSDictionary::objectForKey_(image, *"Image".to_cocoa());
+                let _: () = msg_send![image, setObject:url forKey:*"name".to_cocoa()];
+                let error = from_object::<Canvas>(*object).unwrap_err();
+                assert_eq!(error.key_path(), "layers[1].Image.name");
+                assert!(error.inner().to_string().contains("NSURL"), "{}", error);
+            }
+        }
+    }
+
     mod nsfastenumeration {
         use std::str;
         use std::slice;
-        use cocoa::foundation::{NSString, NSFastEnumeration};
+        use cocoa::foundation::{FromCocoa, MutatedDuringEnumeration, NSArray, NSString,
+                                NSFastEnumeration, NSMutableArray, ToCocoa};
         use cocoa::base::{id, nil};
 
         #[test]
@@ -100,16 +1648,59 @@ mod foundation {
                 let mut_components: id = msg_send![components, mutableCopy];
                 let mut iter = mut_components.iter();
                 iter.next();
-                msg_send![mut_components, removeObjectAtIndex:1];
+                mut_components.removeObjectAtIndex_(1);
                 iter.next();
             }
         }
+
+        #[test]
+        fn test_try_next() {
+            unsafe {
+                let array = vec!["a", "b", "c"].to_cocoa();
+                let mut_array: id = msg_send![*array, mutableCopy];
+                let mut iter = mut_array.iter();
+                assert!(iter.try_next().unwrap().unwrap().isEqualToString("a"));
+                mut_array.addObject_(*"d".to_cocoa());
+                assert_eq!(iter.try_next(), Err(MutatedDuringEnumeration));
+            }
+        }
+
+        #[test]
+        fn test_iter_as() {
+            unsafe {
+                let array = vec!["1".to_cocoa(), 2.to_cocoa(), "3".to_cocoa()].to_cocoa();
+                let strings = array.iter_as::<String>().collect::<Vec<_>>();
+                assert_eq!(strings, vec![Some("1".to_owned()), None, Some("3".to_owned())]);
+                let numbers = array.iter_as::<i32>().flatten().collect::<Vec<_>>();
+                assert_eq!(numbers, vec![2]);
+            }
+        }
+
+        #[test]
+        fn test_reverse() {
+            unsafe {
+                let array = (0..40).collect::<Vec<i64>>().to_cocoa();
+                let forward = array.iter_as::<i64>().map(Option::unwrap).collect::<Vec<_>>();
+                assert_eq!(forward, (0..40).collect::<Vec<_>>());
+                let reversed = array.iter_rev().map(|n| i64::from_cocoa(n).unwrap()).collect::<Vec<_>>();
+                assert_eq!(reversed, (0..40).rev().collect::<Vec<_>>());
+
+                let mut iter = array.array_iter();
+                assert_eq!(iter.len(), 40);
+                assert_eq!(i64::from_cocoa(iter.next_back().unwrap()), Some(39));
+                assert_eq!(i64::from_cocoa(iter.next().unwrap()), Some(0));
+                assert_eq!(iter.len(), 38);
+                assert_eq!(iter.rev().map(|n| i64::from_cocoa(n).unwrap()).collect::<Vec<_>>(),
+                           (1..39).rev().collect::<Vec<_>>());
+            }
+        }
     }
 
     mod nsdictionary {
         use block::ConcreteBlock;
-        use cocoa::foundation::{NSArray, NSComparisonResult, NSDictionary, NSFastEnumeration,
-                                NSString};
+        use std::collections::{BTreeMap, HashMap};
+        use cocoa::foundation::{FromCocoa, NSArray, NSComparisonResult, NSDictionary,
+                                NSFastEnumeration, NSMutableDictionary, NSString, ToCocoa};
         use cocoa::base::{id, nil};
 
         #[test]
@@ -185,5 +1776,56 @@ mod foundation {
                 }
             }
         }
+
+        #[test]
+        fn test_mutable() {
+            unsafe {
+                let dict = NSMutableDictionary::dictionaryWithCapacity_(nil, 2);
+                dict.setObject_forKey_(*"one".to_cocoa(), *"a".to_cocoa());
+                dict.setObject_forKey_(*"two".to_cocoa(), *"b".to_cocoa());
+                assert_eq!(NSDictionary::count(dict), 2);
+
+                let other = NSDictionary::dictionaryWithObject_forKey_(nil, *"three".to_cocoa(),
+                                                                       *"a".to_cocoa());
+                dict.addEntriesFromDictionary_(other);
+                assert!(dict.objectForKey_(*"a".to_cocoa()).isEqualToString("three"));
+
+                dict.removeObjectForKey_(*"b".to_cocoa());
+                assert_eq!(NSDictionary::count(dict), 1);
+                assert_eq!(dict.objectForKey_(*"b".to_cocoa()), nil);
+            }
+        }
+
+        #[test]
+        fn test_pairs() {
+            unsafe {
+                let mut map = HashMap::new();
+                map.insert("a".to_owned(), 1i64);
+                map.insert("b".to_owned(), 2);
+                let dict = map.to_cocoa();
+                let mut pairs = dict.pairs()
+                    .map(|(k, v)| (String::from_cocoa(k).unwrap(), i64::from_cocoa(v).unwrap()))
+                    .collect::<Vec<_>>();
+                pairs.sort();
+                assert_eq!(pairs, vec![("a".to_owned(), 1), ("b".to_owned(), 2)]);
+            }
+        }
+
+        #[test]
+        fn test_map_conversions() {
+            unsafe {
+                let mut map = BTreeMap::new();
+                map.insert("xs".to_owned(), vec![1.5f64, -2.0]);
+                map.insert("ys".to_owned(), vec![]);
+                let dict = map.to_cocoa();
+                assert_eq!(BTreeMap::<String, Vec<f64>>::from_cocoa(*dict), Some(map.clone()));
+                assert_eq!(HashMap::<String, Vec<f64>>::from_cocoa(*dict).unwrap().len(), 2);
+
+                // Values of the wrong type fail the whole conversion.
+                assert_eq!(BTreeMap::<String, Vec<String>>::from_cocoa(*dict), None);
+                assert_eq!(BTreeMap::<String, String>::from_cocoa(*"not a map".to_cocoa()), None);
+                assert_eq!(i32::from_cocoa(*(1u64 << 40).to_cocoa()), None);
+            }
+        }
     }
 }
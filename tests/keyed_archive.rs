@@ -0,0 +1,72 @@
+extern crate cocoa;
+
+mod keyed_archive {
+    use cocoa::keyed_archive::{self, Color, Error, Object};
+
+    const STRING: &[u8] = include_bytes!("fixtures/string.archive");
+    const ARRAY: &[u8] = include_bytes!("fixtures/array.archive");
+    const DICTIONARY: &[u8] = include_bytes!("fixtures/dictionary.archive");
+    const CYCLE: &[u8] = include_bytes!("fixtures/cycle.archive");
+
+    fn dictionary() -> Object {
+        let string = |s: &str| Object::String(s.to_owned());
+        Object::Dictionary(vec![
+            (string("created"), Object::Date(600000000.5)),
+            (string("tint"), Object::Color(Color::CalibratedRgb {
+                red: 1.0,
+                green: 0.5,
+                blue: 0.0,
+                alpha: 1.0,
+            })),
+            (string("background"), Object::Color(Color::DeviceWhite { white: 0.25, alpha: 0.5 })),
+            (string("tags"), Object::Array(vec![string("a"), string("b")])),
+        ])
+    }
+
+    #[test]
+    fn test_decode_string() {
+        assert_eq!(keyed_archive::from_slice(STRING),
+                   Ok(Object::String("Hello, world!".to_owned())));
+    }
+
+    #[test]
+    fn test_decode_mutable_subclasses() {
+        let expected = Object::Array(vec![
+            Object::String("Iñtërnâtiônàlizætiøn".to_owned()),
+            Object::Integer(-7),
+            Object::Real(2.5),
+            Object::Boolean(true),
+            Object::Data(vec![0, 1, 2, 255]),
+            Object::String("mutable".to_owned()),
+        ]);
+        assert_eq!(keyed_archive::from_slice(ARRAY), Ok(expected));
+    }
+
+    #[test]
+    fn test_decode_dictionary() {
+        assert_eq!(keyed_archive::from_slice(DICTIONARY), Ok(dictionary()));
+    }
+
+    #[test]
+    fn test_decode_cycle() {
+        match keyed_archive::from_slice(CYCLE) {
+            Err(Error::Invalid(_)) => {}
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let objects = vec![
+            Object::Null,
+            Object::String("Hello, world!".to_owned()),
+            dictionary(),
+            Object::Array(vec![Object::Null, Object::Integer(1 << 40), Object::Date(-1.0)]),
+            Object::Dictionary(vec![(Object::Integer(1), dictionary())]),
+        ];
+        for object in objects {
+            let archive = keyed_archive::to_vec(&object);
+            assert_eq!(keyed_archive::from_slice(&archive), Ok(object));
+        }
+    }
+}
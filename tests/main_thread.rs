@@ -0,0 +1,68 @@
+//! Tests that need the process's main thread, and so run without the test harness.
+
+#[cfg(target_os = "macos")]
+extern crate cocoa;
+
+#[cfg(target_os = "macos")]
+mod perform_on_main_thread {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use cocoa::base::{id, nil, NO, YES};
+    use cocoa::foundation::{NSDate, NSRunLoop, NSThread};
+
+    /// Runs the main run loop until `done` is set.
+    unsafe fn run_until(done: &AtomicBool) {
+        let run_loop: id = NSRunLoop::mainRunLoop();
+        while !done.load(Ordering::SeqCst) {
+            run_loop.runUntilDate_(NSDate::dateWithTimeIntervalSinceNow_(nil, 0.01));
+        }
+    }
+
+    pub fn test() {
+        unsafe {
+            assert_eq!(NSThread::isMainThread(nil), YES);
+
+            // On the main thread, waiting runs the closure straight away.
+            let (sender, receiver) = mpsc::channel();
+            NSThread::perform_on_main_thread(nil, move || sender.send(NSThread::isMainThread(nil)).unwrap(), true);
+            assert_eq!(receiver.try_recv(), Ok(YES));
+
+            // From another thread, the closure waits for the main run loop.
+            let done = Arc::new(AtomicBool::new(false));
+            let (sender, receiver) = mpsc::channel();
+            let worker = {
+                let done = done.clone();
+                thread::spawn(move || {
+                    assert_eq!(NSThread::isMainThread(nil), NO);
+                    NSThread::perform_on_main_thread(nil, move || {
+                        sender.send(NSThread::isMainThread(nil)).unwrap();
+                    }, true);
+                    let ran = receiver.try_recv();
+
+                    // A panic resumes in the waiting thread.
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        NSThread::perform_on_main_thread(nil, || panic!("on the main thread"), true)
+                    }));
+                    done.store(true, Ordering::SeqCst);
+                    (ran, result.unwrap_err().downcast_ref::<&str>().cloned())
+                })
+            };
+            run_until(&done);
+            assert_eq!(worker.join().unwrap(), (Ok(YES), Some("on the main thread")));
+
+            // Without a waiting caller, the panic is only reported and the run loop carries on.
+            NSThread::perform_on_main_thread(nil, || panic!("not waited for"), false);
+            let done = Arc::new(AtomicBool::new(false));
+            let finished = done.clone();
+            NSThread::perform_on_main_thread(nil, move || finished.store(true, Ordering::SeqCst), false);
+            run_until(&done);
+        }
+    }
+}
+
+fn main() {
+    #[cfg(target_os = "macos")]
+    perform_on_main_thread::test();
+}
@@ -0,0 +1,46 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers shared by the unit tests in `src/` and the integration tests.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Awaits `first` and then passes its output to `then`, standing in for an `async` block, which
+/// this crate's edition lacks.
+pub struct AndThen<A: Future, F, B> {
+    first: Option<Pin<Box<A>>>,
+    then: Option<F>,
+    second: Option<Pin<Box<B>>>,
+}
+
+pub fn and_then<A: Future, F: FnOnce(A::Output) -> B, B: Future>(first: A, then: F) -> AndThen<A, F, B> {
+    AndThen { first: Some(Box::pin(first)), then: Some(then), second: None }
+}
+
+impl<A: Future, F: FnOnce(A::Output) -> B + Unpin, B: Future> Future for AndThen<A, F, B> {
+    type Output = B::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<B::Output> {
+        if let Some(mut first) = self.first.take() {
+            match first.as_mut().poll(cx) {
+                Poll::Ready(output) => {
+                    let then = self.then.take().unwrap();
+                    self.second = Some(Box::pin(then(output)));
+                }
+                Poll::Pending => {
+                    self.first = Some(first);
+                    return Poll::Pending;
+                }
+            }
+        }
+        self.second.as_mut().unwrap().as_mut().poll(cx)
+    }
+}